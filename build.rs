@@ -8,6 +8,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "opentelemetry/proto/common/v1/common.proto",
             "opentelemetry/proto/resource/v1/resource.proto", 
             "opentelemetry/proto/trace/v1/trace.proto",
+            "opentelemetry/proto/logs/v1/logs.proto",
         ],
         &["."],
     )?;
@@ -0,0 +1,127 @@
+use crate::shared_data::retry_cas;
+use crate::span_buffer::SharedBufferStore;
+
+/// Shared-data key holding OTLP payloads waiting for the next batch flush
+pub const BATCHED_SPANS_KEY: &str = "sp.batched_spans";
+
+/// Serialized `TracesData` messages accumulated for a single `/v1/traces` POST
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Batch {
+    pub spans: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Batch {
+    /// A little-endian u32 span count followed by the concatenated payloads
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.payload.len());
+        bytes.extend_from_slice(&self.spans.to_le_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Decode bytes written by `encode`; anything shorter than the count is empty
+    fn decode(bytes: &[u8]) -> Self {
+        match bytes.get(..4).and_then(|count| <[u8; 4]>::try_from(count).ok()) {
+            Some(count) => Batch {
+                spans: u32::from_le_bytes(count),
+                payload: bytes[4..].to_vec(),
+            },
+            None => Batch::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans == 0
+    }
+}
+
+/// Add a serialized `TracesData` to the pending batch and return the batch as
+/// stored, or None if shared data could not be updated. Concatenated protobuf
+/// messages decode as one message with their repeated fields appended, so the
+/// batch payload is itself a valid `TracesData` holding every `ResourceSpans`.
+pub fn append<S: SharedBufferStore + ?Sized>(store: &S, payload: &[u8]) -> Option<Batch> {
+    let batch = retry_cas(|| {
+        let (bytes, cas) = store.load_buffer(BATCHED_SPANS_KEY);
+        let mut batch = Batch::decode(bytes.as_deref().unwrap_or_default());
+        batch.spans += 1;
+        batch.payload.extend_from_slice(payload);
+        store.store_buffer(BATCHED_SPANS_KEY, &batch.encode(), cas).then_some(batch)
+    });
+    if batch.is_none() {
+        crate::sp_warn!("Could not update span batch, dropping span");
+    }
+    batch
+}
+
+/// Take the whole pending batch, leaving it empty
+pub fn take<S: SharedBufferStore + ?Sized>(store: &S) -> Option<Batch> {
+    let batch = retry_cas(|| {
+        let (bytes, cas) = store.load_buffer(BATCHED_SPANS_KEY);
+        let batch = Batch::decode(bytes.as_deref().unwrap_or_default());
+        if batch.is_empty() {
+            return Some(None);
+        }
+        store.store_buffer(BATCHED_SPANS_KEY, &[], cas).then_some(Some(batch))
+    });
+    batch.unwrap_or_else(|| {
+        crate::sp_warn!("Could not take span batch, leaving it for the next flush");
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::otel::{serialize_traces_data, SpanBuilder, TracesData};
+    use crate::shared_data::testing::MockStore;
+    use prost::Message;
+    use std::collections::HashMap;
+
+    fn traces_payload(path: &str) -> Vec<u8> {
+        let traces_data = SpanBuilder::new().create_extract_span(
+            &HashMap::new(), &[], &HashMap::new(), &[], None, Some(path), None,
+        );
+        serialize_traces_data(&traces_data).unwrap()
+    }
+
+    #[test]
+    fn test_append_accumulates_spans_and_bytes() {
+        let store = MockStore::default();
+        let first = traces_payload("/a");
+        let second = traces_payload("/b");
+
+        assert_eq!(append(&store, &first).unwrap().spans, 1);
+        let batch = append(&store, &second).unwrap();
+        assert_eq!(batch.spans, 2);
+        assert_eq!(batch.payload.len(), first.len() + second.len());
+    }
+
+    #[test]
+    fn test_batch_payload_decodes_as_one_traces_data() {
+        let store = MockStore::default();
+        append(&store, &traces_payload("/a"));
+        append(&store, &traces_payload("/b"));
+
+        let batch = take(&store).unwrap();
+        let traces_data = TracesData::decode(batch.payload.as_slice()).unwrap();
+        let names: Vec<_> = traces_data
+            .resource_spans
+            .iter()
+            .flat_map(|rs| &rs.scope_spans)
+            .flat_map(|ss| &ss.spans)
+            .map(|span| span.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["/a", "/b"]);
+    }
+
+    #[test]
+    fn test_take_empties_the_batch() {
+        let store = MockStore::default();
+        assert_eq!(take(&store), None);
+
+        append(&store, &traces_payload("/a"));
+        assert_eq!(take(&store).unwrap().spans, 1);
+        assert_eq!(take(&store), None);
+    }
+}
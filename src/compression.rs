@@ -0,0 +1,136 @@
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::{ErrorKind, Read};
+
+/// Content codings whose bodies are decompressed before capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Parse a `content-encoding` value; None for identity, stacked or unsupported codings
+    pub fn from_header(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            "deflate" => Some(ContentEncoding::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// How a captured body was decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyDecoding {
+    pub encoding: ContentEncoding,
+    /// Output stopped at the size limit, or the compressed stream was cut short
+    pub truncated: bool,
+}
+
+/// Decompress a body, producing at most `limit` bytes (0 for no limit) so a small
+/// compressed payload cannot expand without bound. A body cut off mid-stream, as
+/// when its capture stopped at max_body_bytes, yields what it decoded to so far.
+/// None if the data is not valid.
+pub fn decompress(body: &[u8], encoding: ContentEncoding, limit: usize) -> Option<(Vec<u8>, BodyDecoding)> {
+    let (decoded, truncated) = match encoding {
+        ContentEncoding::Gzip => read_limited(GzDecoder::new(body), limit)?,
+        // "deflate" is zlib-wrapped per RFC 9110, but some servers send raw deflate
+        ContentEncoding::Deflate => read_limited(ZlibDecoder::new(body), limit)
+            .or_else(|| read_limited(DeflateDecoder::new(body), limit))?,
+    };
+    Some((decoded, BodyDecoding { encoding, truncated }))
+}
+
+fn read_limited<R: Read>(reader: R, limit: usize) -> Option<(Vec<u8>, bool)> {
+    let max_read = if limit == 0 { u64::MAX } else { limit as u64 + 1 };
+    let mut decoded = Vec::new();
+    let cut_short = match reader.take(max_read).read_to_end(&mut decoded) {
+        Ok(_) => false,
+        // read_to_end keeps the output decoded before the stream ran out
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof && !decoded.is_empty() => true,
+        Err(_) => return None,
+    };
+
+    let truncated = cut_short || (limit != 0 && decoded.len() > limit);
+    if limit != 0 && decoded.len() > limit {
+        decoded.truncate(limit);
+    }
+    Some((decoded, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(ContentEncoding::from_header("GZIP"), Some(ContentEncoding::Gzip));
+        assert_eq!(ContentEncoding::from_header(" deflate "), Some(ContentEncoding::Deflate));
+        assert_eq!(ContentEncoding::from_header("br"), None);
+        assert_eq!(ContentEncoding::from_header("gzip, br"), None);
+    }
+
+    #[test]
+    fn test_decompress_gzip() {
+        let (decoded, decoding) = decompress(&gzip(br#"{"id":1}"#), ContentEncoding::Gzip, 0).unwrap();
+
+        assert_eq!(decoded, br#"{"id":1}"#);
+        assert!(!decoding.truncated);
+    }
+
+    #[test]
+    fn test_decompress_zlib_and_raw_deflate() {
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(b"hello").unwrap();
+        let mut raw = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(b"hello").unwrap();
+
+        for body in [zlib.finish().unwrap(), raw.finish().unwrap()] {
+            let (decoded, _) = decompress(&body, ContentEncoding::Deflate, 0).unwrap();
+            assert_eq!(decoded, b"hello");
+        }
+    }
+
+    #[test]
+    fn test_decompress_stops_at_limit() {
+        let bomb = gzip(&vec![b'a'; 1024 * 1024]);
+
+        let (decoded, decoding) = decompress(&bomb, ContentEncoding::Gzip, 1024).unwrap();
+
+        assert_eq!(decoded.len(), 1024);
+        assert!(decoding.truncated);
+    }
+
+    #[test]
+    fn test_decompress_keeps_output_of_cut_off_stream() {
+        let data: Vec<u8> = (0..20_000u32).flat_map(|n| format!("{},", n.wrapping_mul(2_654_435_761)).into_bytes()).collect();
+        let compressed = gzip(&data);
+
+        let (decoded, decoding) = decompress(&compressed[..compressed.len() / 2], ContentEncoding::Gzip, 0).unwrap();
+
+        assert!(!decoded.is_empty());
+        assert!(data.starts_with(&decoded));
+        assert!(decoding.truncated);
+    }
+
+    #[test]
+    fn test_decompress_invalid_data() {
+        assert_eq!(decompress(b"not compressed", ContentEncoding::Gzip, 0), None);
+    }
+}
@@ -1,4 +1,7 @@
-use serde_json;
+use crate::traffic::CompiledPattern;
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone)]
 pub struct CollectionRule {
@@ -14,25 +17,89 @@ pub struct HttpCollectionRule {
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub path: String,
+    /// Request methods the rule applies to; empty matches any method
+    pub methods: Vec<String>,
+    /// (header name, value regex) pairs that must all match
+    pub header_patterns: Vec<(String, String)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
     pub host: String,
     pub paths: Vec<String>,
+    /// Request methods the rule applies to; empty matches any method
+    pub methods: Vec<String>,
+    /// (header name, value regex) pairs that must all match
+    pub header_patterns: Vec<(String, String)>,
+    pub compiled_host: CompiledPattern,
+    pub compiled_paths: Vec<CompiledPattern>,
+    pub compiled_header_patterns: Vec<(String, CompiledPattern)>,
+}
+
+impl ClientConfig {
+    pub fn new(host: String, paths: Vec<String>, methods: Vec<String>) -> Self {
+        Self {
+            compiled_host: CompiledPattern::new(&host),
+            compiled_paths: CompiledPattern::compile_all(&paths),
+            host,
+            paths,
+            methods,
+            header_patterns: vec![],
+            compiled_header_patterns: vec![],
+        }
+    }
+
+    pub fn with_header_patterns(mut self, header_patterns: Vec<(String, String)>) -> Self {
+        self.compiled_header_patterns = CompiledPattern::compile_headers(&header_patterns);
+        self.header_patterns = header_patterns;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ExemptionRule {
     pub host_patterns: Vec<String>,
     pub path_patterns: Vec<String>,
+    /// (header name, value regex) pairs that must all match
+    pub header_patterns: Vec<(String, String)>,
+    pub compiled_host_patterns: Vec<CompiledPattern>,
+    pub compiled_path_patterns: Vec<CompiledPattern>,
+    pub compiled_header_patterns: Vec<(String, CompiledPattern)>,
+}
+
+impl ExemptionRule {
+    pub fn new(host_patterns: Vec<String>, path_patterns: Vec<String>) -> Self {
+        Self {
+            compiled_host_patterns: CompiledPattern::compile_all(&host_patterns),
+            compiled_path_patterns: CompiledPattern::compile_all(&path_patterns),
+            host_patterns,
+            path_patterns,
+            header_patterns: vec![],
+            compiled_header_patterns: vec![],
+        }
+    }
+
+    pub fn with_header_patterns(mut self, header_patterns: Vec<(String, String)>) -> Self {
+        self.compiled_header_patterns = CompiledPattern::compile_headers(&header_patterns);
+        self.header_patterns = header_patterns;
+        self
+    }
+
+    /// Human-readable form used when reporting which rule matched
+    pub fn describe(&self) -> String {
+        let mut description = format!("hostPatterns={:?} pathPatterns={:?}", self.host_patterns, self.path_patterns);
+        if !self.header_patterns.is_empty() {
+            description.push_str(&format!(" headerPatterns={:?}", self.header_patterns));
+        }
+        description
+    }
 }
 
 impl Default for ExemptionRule {
     fn default() -> Self {
-        Self {
-            host_patterns: vec![],
-            path_patterns: vec![
+        Self::new(
+            vec![],
+            vec![
                 "/v1/traces".to_string(),
                 "/api/traces".to_string(),
                 "/v1/metrics".to_string(),
@@ -42,7 +109,271 @@ impl Default for ExemptionRule {
                 "/otlp/v1/traces".to_string(),
                 "/otlp/v1/metrics".to_string(),
                 "/otlp/v1/logs".to_string(),
+                "/api/v2/spans".to_string(),
             ],
+        )
+    }
+}
+
+/// Which sides of the exchange are captured into the span
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaptureSides {
+    #[default]
+    Both,
+    Request,
+    Response,
+}
+
+impl CaptureSides {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "both" => Some(CaptureSides::Both),
+            "request" => Some(CaptureSides::Request),
+            "response" => Some(CaptureSides::Response),
+            _ => None,
+        }
+    }
+
+    pub fn captures_request(&self) -> bool {
+        matches!(self, CaptureSides::Both | CaptureSides::Request)
+    }
+
+    pub fn captures_response(&self) -> bool {
+        matches!(self, CaptureSides::Both | CaptureSides::Response)
+    }
+}
+
+/// Value the sampling decision is keyed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplingKey {
+    #[default]
+    Trace,
+    Session,
+}
+
+impl SamplingKey {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "trace" => Some(SamplingKey::Trace),
+            "session" => Some(SamplingKey::Session),
+            _ => None,
+        }
+    }
+}
+
+/// What happens to a span when max_inflight_dispatches is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InflightOverflow {
+    #[default]
+    Drop,
+    /// Hold the payload in the shared span buffer (bounded by max_buffered_spans)
+    /// until a dispatch slot frees up
+    Queue,
+}
+
+impl InflightOverflow {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "drop" => Some(InflightOverflow::Drop),
+            "queue" => Some(InflightOverflow::Queue),
+            _ => None,
+        }
+    }
+}
+
+/// Wire format used to export captured spans
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Otlp,
+    Zipkin,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "otlp" => Some(ExportFormat::Otlp),
+            "zipkin" => Some(ExportFormat::Zipkin),
+            _ => None,
+        }
+    }
+}
+
+/// Trace context headers read from requests and injected into them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PropagationFormat {
+    /// W3C `traceparent`/`tracestate`
+    #[default]
+    W3c,
+    /// Single `b3` header
+    B3,
+    /// `x-b3-traceid`, `x-b3-spanid` and `x-b3-sampled`
+    B3Multi,
+}
+
+impl PropagationFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "w3c" => Some(PropagationFormat::W3c),
+            "b3" => Some(PropagationFormat::B3),
+            "b3multi" => Some(PropagationFormat::B3Multi),
+            _ => None,
+        }
+    }
+}
+
+/// How a sensitive value is redacted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaskingMode {
+    /// Replace the middle with `*`, keeping the configured prefix and suffix
+    #[default]
+    Asterisk,
+    /// Replace the value with `hash:` and a short stable hash, so equal values still correlate
+    Hash,
+}
+
+impl MaskingMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "asterisk" => Some(MaskingMode::Asterisk),
+            "hash" => Some(MaskingMode::Hash),
+            _ => None,
+        }
+    }
+}
+
+/// Unit of duration attributes, also used as the attribute key suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurationUnit {
+    #[default]
+    Milliseconds,
+    Nanoseconds,
+    Seconds,
+}
+
+impl DurationUnit {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "ms" => Some(DurationUnit::Milliseconds),
+            "ns" => Some(DurationUnit::Nanoseconds),
+            "s" => Some(DurationUnit::Seconds),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DurationUnit::Milliseconds => "ms",
+            DurationUnit::Nanoseconds => "ns",
+            DurationUnit::Seconds => "s",
+        }
+    }
+}
+
+/// Contradictory option combinations, each resolved in favour of one side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigConflict {
+    /// propagation_only exports nothing, so bodies_as_logs is turned off
+    PropagationOnlyWithBodiesAsLogs,
+    /// hash_bodies hashes the request body, which capture_sides=response never
+    /// buffers, so hashing is turned off
+    HashBodiesWithoutRequestCapture,
+    /// Zipkin payloads are JSON arrays that cannot be merged, so batching is turned off
+    BatchingWithZipkin,
+    /// Body log records are OTLP only, so with Zipkin export bodies stay on the spans
+    BodiesAsLogsWithZipkin,
+}
+
+impl ConfigConflict {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ConfigConflict::PropagationOnlyWithBodiesAsLogs => {
+                "propagation_only conflicts with bodies_as_logs; disabling bodies_as_logs"
+            }
+            ConfigConflict::HashBodiesWithoutRequestCapture => {
+                "hash_bodies requires the request side of capture_sides; disabling hash_bodies"
+            }
+            ConfigConflict::BatchingWithZipkin => {
+                "batching is only supported with export_format=otlp; disabling batching"
+            }
+            ConfigConflict::BodiesAsLogsWithZipkin => {
+                "bodies_as_logs is only supported with export_format=otlp; keeping bodies on the spans"
+            }
+        }
+    }
+}
+
+/// Controls masking of sensitive values in captured spans
+#[derive(Debug, Clone)]
+pub struct MaskingConfig {
+    pub enabled: bool,
+    pub mode: MaskingMode,
+    pub mask_request_body: bool,
+    pub mask_response_body: bool,
+    pub mask_request_headers: Vec<String>,
+    pub mask_response_headers: Vec<String>,
+    pub keep_prefix_length: usize,
+    pub keep_suffix_length: usize,
+    /// Field names masked in bodies in addition to the built-in list
+    pub custom_field_names: Vec<String>,
+    /// (pattern, replacement) pairs applied to whole bodies after field masking
+    pub custom_patterns: Vec<(Regex, String)>,
+    /// Parse JSON bodies and mask sensitive keys at any depth and of any value type
+    pub structured_json: bool,
+    /// Mask JSON string values and header values that look sensitive, whatever their name
+    pub auto_detect_values: bool,
+    /// Larger JSON bodies skip the full parse and use the regex masking; 0 disables the limit
+    pub deep_masking_max_bytes: usize,
+    /// Field regexes for the built-in and custom field names
+    pub field_patterns: crate::masking::FieldPatterns,
+}
+
+impl Default for MaskingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: MaskingMode::Asterisk,
+            mask_request_body: true,
+            mask_response_body: true,
+            mask_request_headers: vec![],
+            mask_response_headers: vec![],
+            keep_prefix_length: 3,
+            keep_suffix_length: 4,
+            custom_field_names: vec![],
+            custom_patterns: vec![],
+            structured_json: false,
+            auto_detect_values: false,
+            deep_masking_max_bytes: 0,
+            field_patterns: crate::masking::FieldPatterns::new(&[]),
+        }
+    }
+}
+
+impl MaskingConfig {
+    /// Set the custom field names and recompile the field patterns for them
+    pub fn with_custom_field_names(mut self, custom_field_names: Vec<String>) -> Self {
+        self.field_patterns = crate::masking::FieldPatterns::new(&custom_field_names);
+        self.custom_field_names = custom_field_names;
+        self
+    }
+}
+
+/// Accumulate spans in shared data and export them in one `/v1/traces` POST
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchingConfig {
+    pub enabled: bool,
+    pub flush_interval_ms: u64,  // Root context tick flushing whatever is pending
+    pub max_batch_spans: u32,  // Flush immediately once this many spans are pending
+    pub max_batch_bytes: usize,  // Flush immediately once the payload reaches this size
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            flush_interval_ms: 1000,
+            max_batch_spans: 50,
+            max_batch_bytes: 512 * 1024,
         }
     }
 }
@@ -51,21 +382,157 @@ impl Default for ExemptionRule {
 pub struct Config {
     pub sp_backend_url: String,
     pub service_name: String,
+    pub service_name_by_host: HashMap<String, String>,  // Lower-cased authority -> service name
     pub traffic_direction: Option<String>,
     pub collection_rules: Vec<CollectionRule>,
     pub exemption_rules: Vec<ExemptionRule>,
     pub public_key: String,
+    pub injection_path_denylist: Vec<CompiledPattern>,
+    pub capture_sides: CaptureSides,
+    pub annotate_ingress_traffic: bool,
+    pub max_url_length: Option<usize>,
+    pub masking: MaskingConfig,
+    pub batching: BatchingConfig,
+    pub scope_attributes: HashMap<String, String>,
+    pub strip_request_headers: Vec<String>,
+    pub sampling_rate: f64,
+    pub sampling_key: SamplingKey,
+    pub session_sampling_rate: Option<f64>,
+    pub bodies_as_logs: bool,
+    pub skip_response_headers: Vec<String>,
+    pub export_format: ExportFormat,
+    pub zipkin_path: String,
+    pub body_capture_methods: Vec<String>,
+    pub shadow_backend_url: Option<String>,
+    pub config_hash: String,
+    pub conflicts: Vec<ConfigConflict>,  // Resolved by the last parse, reported as the sp.config_conflicts gauge
+    pub server_rule_index: crate::traffic::ServerRuleIndex,
+    pub capture_cookies: bool,
+    pub emit_exemption_marker: bool,
+    pub max_tracestate_entries: usize,
+    pub no_body_paths: Vec<CompiledPattern>,
+    pub dual_emit: bool,
+    pub log_payload_size: bool,
+    pub traces_method: String,
+    pub force_capture_header: Option<String>,
+    pub outbound_ports: Vec<u16>,
+    pub node_metadata_attributes: Vec<String>,
+    pub propagate_hosts: Vec<String>,
+    pub min_body_capture_bytes: usize,
+    pub auto_generate_session_id: bool,
+    pub keep_authorization_scheme: bool,
+    pub max_inflight_dispatches: u32,
+    pub max_retries: u32,  // Re-sends of a save after a 429 or 5xx response
+    pub inflight_overflow: InflightOverflow,
+    pub collapse_numeric_segments: bool,
+    pub require_body_for_capture: bool,
+    pub expose_span_id_header: bool,
+    pub numeric_header_attributes: Vec<String>,
+    pub low_cardinality_headers: Vec<String>,
+    pub low_cardinality_buckets: u32,
+    pub grpc_capture_methods: Vec<String>,
+    pub grpc_message_spans: bool,
+    pub hash_bodies: bool,
+    pub max_body_bytes: usize,
+    pub text_content_types: Vec<String>,
+    pub propagation_only: bool,
+    pub custom_traceparent_header: Option<String>,
+    pub propagation_format: PropagationFormat,
+    pub custom_traceparent_authoritative: bool,
+    pub max_buffered_spans: u32,
+    pub drop_mismatched_bodies: bool,
+    pub fixed_parent_traceparent: Option<String>,
+    pub infra_ports: Vec<RangeInclusive<u16>>,
+    pub mask_path_secrets: bool,
+    pub duration_unit: DurationUnit,
 }
 
+/// Bytes of each body buffered before capture stops; 0 disables the limit
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Buckets the values of low_cardinality_headers are hashed into
+pub const DEFAULT_LOW_CARDINALITY_BUCKETS: u32 = 16;
+
+/// Re-sends of a failed /v1/traces save before it is dropped
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+
+/// Spans held in shared data while waiting for a dispatch slot
+pub const DEFAULT_MAX_BUFFERED_SPANS: u32 = 100;
+
+/// Istio/Envoy infrastructure ports (admin, health, xDS) that are never captured
+pub const DEFAULT_INFRA_PORTS: RangeInclusive<u16> = 15000..=15099;
+
+/// Methods an ingest endpoint can reasonably accept a payload with
+const TRACES_METHODS: &[&str] = &["POST", "PUT", "PATCH"];
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             sp_backend_url: "https://o.softprobe.ai".to_string(),
             traffic_direction: None,
             service_name: "default-service".to_string(),
+            service_name_by_host: HashMap::new(),
             collection_rules: vec![],
             exemption_rules: vec![],
             public_key: String::new(),
+            injection_path_denylist: vec![],
+            capture_sides: CaptureSides::Both,
+            annotate_ingress_traffic: false,
+            max_url_length: None,
+            masking: MaskingConfig::default(),
+            batching: BatchingConfig::default(),
+            scope_attributes: HashMap::new(),
+            strip_request_headers: vec![],
+            sampling_rate: 1.0,
+            sampling_key: SamplingKey::Trace,
+            session_sampling_rate: None,
+            bodies_as_logs: false,
+            skip_response_headers: vec![],
+            export_format: ExportFormat::Otlp,
+            zipkin_path: crate::zipkin::DEFAULT_ZIPKIN_PATH.to_string(),
+            body_capture_methods: ["POST", "PUT", "PATCH", "DELETE"].iter().map(|m| m.to_string()).collect(),
+            shadow_backend_url: None,
+            config_hash: String::new(),
+            conflicts: vec![],
+            server_rule_index: crate::traffic::ServerRuleIndex::default(),
+            capture_cookies: false,
+            emit_exemption_marker: false,
+            max_tracestate_entries: crate::headers::DEFAULT_MAX_TRACESTATE_ENTRIES,
+            no_body_paths: vec![],
+            dual_emit: false,
+            log_payload_size: false,
+            traces_method: "POST".to_string(),
+            force_capture_header: None,
+            outbound_ports: vec![],
+            node_metadata_attributes: vec![],
+            propagate_hosts: vec![],
+            min_body_capture_bytes: 0,
+            auto_generate_session_id: true,
+            keep_authorization_scheme: false,
+            max_inflight_dispatches: 0,
+            max_retries: DEFAULT_MAX_RETRIES,
+            inflight_overflow: InflightOverflow::Drop,
+            collapse_numeric_segments: false,
+            require_body_for_capture: false,
+            expose_span_id_header: false,
+            numeric_header_attributes: vec![],
+            low_cardinality_headers: vec![],
+            low_cardinality_buckets: DEFAULT_LOW_CARDINALITY_BUCKETS,
+            grpc_capture_methods: vec![],
+            grpc_message_spans: false,
+            hash_bodies: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            text_content_types: vec![],
+            propagation_only: false,
+            custom_traceparent_header: None,
+            propagation_format: PropagationFormat::W3c,
+            custom_traceparent_authoritative: false,
+            max_buffered_spans: DEFAULT_MAX_BUFFERED_SPANS,
+            drop_mismatched_bodies: false,
+            fixed_parent_traceparent: None,
+            infra_ports: vec![DEFAULT_INFRA_PORTS],
+            mask_path_secrets: false,
+            duration_unit: DurationUnit::default(),
         }
     }
 }
@@ -74,18 +541,98 @@ impl Config {
     pub fn parse_from_json(&mut self, config_bytes: &[u8]) -> bool {
         if let Ok(config_str) = std::str::from_utf8(config_bytes) {
             if let Ok(config_json) = serde_json::from_str::<serde_json::Value>(config_str) {
+                // Start from defaults so a repeated on_configure doesn't accumulate rules
+                *self = Config::default();
                 self.parse_backend_url(&config_json);
                 self.parse_traffic_direction(&config_json);
                 self.parse_service_name(&config_json);
+                self.parse_service_name_by_host(&config_json);
                 self.parse_public_key(&config_json);
                 self.parse_collection_rules(&config_json);
                 self.parse_exemption_rules(&config_json);
+                self.parse_masking_rules(&config_json);
+                self.parse_batching(&config_json);
+                self.parse_injection_path_denylist(&config_json);
+                self.parse_capture_sides(&config_json);
+                self.parse_annotate_ingress_traffic(&config_json);
+                self.parse_max_url_length(&config_json);
+                self.parse_scope_attributes(&config_json);
+                self.parse_strip_request_headers(&config_json);
+                self.parse_sampling(&config_json);
+                self.parse_bodies_as_logs(&config_json);
+                self.parse_skip_response_headers(&config_json);
+                self.parse_export_format(&config_json);
+                self.parse_body_capture_methods(&config_json);
+                self.parse_shadow_backend_url(&config_json);
+                self.parse_capture_cookies(&config_json);
+                self.parse_emit_exemption_marker(&config_json);
+                self.parse_max_tracestate_entries(&config_json);
+                self.parse_no_body_paths(&config_json);
+                self.parse_dual_emit(&config_json);
+                self.parse_log_payload_size(&config_json);
+                self.parse_traces_method(&config_json);
+                self.parse_force_capture_header(&config_json);
+                self.parse_outbound_ports(&config_json);
+                self.parse_infra_ports(&config_json);
+                self.parse_node_metadata_attributes(&config_json);
+                self.parse_propagate_hosts(&config_json);
+                self.parse_min_body_capture_bytes(&config_json);
+                self.parse_auto_generate_session_id(&config_json);
+                self.parse_keep_authorization_scheme(&config_json);
+                self.parse_inflight_limit(&config_json);
+                self.parse_max_retries(&config_json);
+                self.parse_collapse_numeric_segments(&config_json);
+                self.parse_require_body_for_capture(&config_json);
+                self.parse_expose_span_id_header(&config_json);
+                self.parse_numeric_header_attributes(&config_json);
+                self.parse_low_cardinality_headers(&config_json);
+                self.parse_grpc_capture_methods(&config_json);
+                self.parse_grpc_message_spans(&config_json);
+                self.parse_hash_bodies(&config_json);
+                self.parse_max_body_bytes(&config_json);
+                self.parse_text_content_types(&config_json);
+                self.parse_propagation_only(&config_json);
+                self.parse_custom_traceparent_header(&config_json);
+                self.parse_propagation_format(&config_json);
+                self.parse_drop_mismatched_bodies(&config_json);
+                self.parse_fixed_parent_traceparent(&config_json);
+                self.parse_mask_path_secrets(&config_json);
+                self.parse_duration_unit(&config_json);
+                self.resolve_conflicts();
+                self.config_hash = compute_config_hash(&config_json);
                 return true;
             }
         }
         false
     }
 
+    /// Detect contradictory options, log each one and apply its documented precedence
+    fn resolve_conflicts(&mut self) {
+        let mut conflicts = Vec::new();
+        if self.propagation_only && self.bodies_as_logs {
+            self.bodies_as_logs = false;
+            conflicts.push(ConfigConflict::PropagationOnlyWithBodiesAsLogs);
+        }
+        if self.hash_bodies && !self.capture_sides.captures_request() {
+            self.hash_bodies = false;
+            conflicts.push(ConfigConflict::HashBodiesWithoutRequestCapture);
+        }
+        if self.export_format == ExportFormat::Zipkin {
+            if self.batching.enabled {
+                self.batching.enabled = false;
+                conflicts.push(ConfigConflict::BatchingWithZipkin);
+            }
+            if self.bodies_as_logs {
+                self.bodies_as_logs = false;
+                conflicts.push(ConfigConflict::BodiesAsLogsWithZipkin);
+            }
+        }
+        for conflict in &conflicts {
+            crate::sp_error!("Conflicting configuration: {}", conflict.describe());
+        }
+        self.conflicts = conflicts;
+    }
+
     fn parse_backend_url(&mut self, config_json: &serde_json::Value) {
         if let Some(backend_url) = config_json.get("sp_backend_url").and_then(|v| v.as_str()) {
             self.sp_backend_url = backend_url.to_string();
@@ -98,8 +645,16 @@ impl Config {
             .get("traffic_direction")
             .and_then(|v| v.as_str())
         {
-            self.traffic_direction = Some(direction.to_string());
-            crate::sp_info!("Configured traffic direction: {:?}", self.traffic_direction);
+            match normalize_traffic_direction(direction) {
+                Some(normalized) => {
+                    self.traffic_direction = Some(normalized.to_string());
+                    crate::sp_info!("Configured traffic direction: {:?}", self.traffic_direction);
+                }
+                None => {
+                    self.traffic_direction = None;
+                    crate::sp_warn!("Invalid traffic_direction '{}', falling back to auto detection", direction);
+                }
+            }
         }
     }
 
@@ -118,286 +673,1760 @@ impl Config {
         }
     }
 
-    fn parse_collection_rules(&mut self, config_json: &serde_json::Value) {
-        if let Some(rules) = config_json.get("collectionRules") {
-            let (server_paths, client_configs) = self.extract_collection_data(rules);
-            self.create_collection_rules(server_paths, client_configs);
+    fn parse_injection_path_denylist(&mut self, config_json: &serde_json::Value) {
+        if let Some(paths) = config_json.get("injection_path_denylist") {
+            self.injection_path_denylist = CompiledPattern::compile_all(&string_array(paths));
+            crate::sp_info!("Configured injection path denylist: {:?}", self.injection_path_denylist);
         }
     }
 
-    fn extract_collection_data(&self, rules: &serde_json::Value) -> (Vec<String>, Vec<(String, Vec<String>)>) {
-        let mut server_paths = Vec::new();
-        let mut client_configs = Vec::new();
-
-        // Extract server paths
-        if let Some(server_obj) = rules.get("http").and_then(|v| v.get("server")) {
-            if let Some(server_array) = server_obj.as_array() {
-                for server_entry in server_array {
-                    if let Some(path) = server_entry.get("path").and_then(|v| v.as_str()) {
-                        server_paths.push(path.to_string());
-                    }
+    fn parse_capture_sides(&mut self, config_json: &serde_json::Value) {
+        if let Some(sides) = config_json.get("capture_sides").and_then(|v| v.as_str()) {
+            match CaptureSides::parse(sides) {
+                Some(capture_sides) => {
+                    self.capture_sides = capture_sides;
+                    crate::sp_info!("Configured capture sides: {:?}", self.capture_sides);
                 }
-            }
-        }
-
-        // Extract client configs
-        if let Some(client_obj) = rules.get("http").and_then(|v| v.get("client")) {
-            if let Some(client_array) = client_obj.as_array() {
-                for client_entry in client_array {
-                    if let Some(host) = client_entry.get("host").and_then(|v| v.as_str()) {
-                        let mut paths = Vec::new();
-                        if let Some(paths_obj) = client_entry.get("paths") {
-                            if let Some(paths_array) = paths_obj.as_array() {
-                                for path_entry in paths_array {
-                                    if let Some(path) = path_entry.as_str() {
-                                        paths.push(path.to_string());
-                                    }
-                                }
-                            }
-                        }
-                        client_configs.push((host.to_string(), paths));
-                    }
+                None => {
+                    crate::sp_warn!("Invalid capture_sides '{}', using {:?}", sides, self.capture_sides);
                 }
             }
         }
-
-        (server_paths, client_configs)
     }
 
-    fn create_collection_rules(&mut self, server_paths: Vec<String>, client_configs: Vec<(String, Vec<String>)>) {
-        // Create rules for each server path
-        for server_path in server_paths {
-            crate::sp_info!("Added server collection rule: {}", server_path);
-            self.collection_rules.push(CollectionRule {
-                http: HttpCollectionRule {
-                    server: ServerConfig {
-                        path: server_path,
-                    },
-                    client: vec![],
-                },
-            });
+    fn parse_annotate_ingress_traffic(&mut self, config_json: &serde_json::Value) {
+        if let Some(annotate) = config_json.get("annotate_ingress_traffic").and_then(|v| v.as_bool()) {
+            self.annotate_ingress_traffic = annotate;
+            crate::sp_info!("Configured annotate ingress traffic: {}", self.annotate_ingress_traffic);
         }
+    }
 
-        // Create rules for each client config
-        for (client_host, client_paths) in &client_configs {
-            crate::sp_info!("Added client collection rule: host={}, paths={:?}", client_host, client_paths);
-            self.collection_rules.push(CollectionRule {
-                http: HttpCollectionRule {
-                    server: ServerConfig {
-                        path: String::new(),
-                    },
-                    client: vec![ClientConfig {
-                        host: client_host.clone(),
-                        paths: client_paths.clone(),
-                    }],
-                },
-            });
+    fn parse_max_url_length(&mut self, config_json: &serde_json::Value) {
+        if let Some(max_len) = config_json.get("max_url_length").and_then(|v| v.as_u64()) {
+            self.max_url_length = if max_len > 0 { Some(max_len as usize) } else { None };
+            crate::sp_info!("Configured max URL length: {:?}", self.max_url_length);
         }
     }
 
-    fn parse_exemption_rules(&mut self, config_json: &serde_json::Value) {
-        if let Some(exemption_rules) = config_json.get("exemptionRules") {
-            if let Some(exemption_array) = exemption_rules.as_array() {
-                for exemption_entry in exemption_array {
-                    let (host_patterns, path_patterns) = self.extract_exemption_patterns(exemption_entry);
-                    
-                    if !path_patterns.is_empty() {
-                        crate::sp_info!("Added exemption rule: hostPatterns={:?}, pathPatterns={:?}", host_patterns, path_patterns);
-                        self.exemption_rules.push(ExemptionRule {
-                            host_patterns,
-                            path_patterns,
-                        });
+    fn parse_service_name_by_host(&mut self, config_json: &serde_json::Value) {
+        if let Some(hosts) = config_json.get("service_name_by_host").and_then(|v| v.as_object()) {
+            for (host, service_name) in hosts {
+                match service_name.as_str() {
+                    Some(service_name) => {
+                        self.service_name_by_host.insert(host.to_ascii_lowercase(), service_name.to_string());
+                    }
+                    None => {
+                        crate::sp_warn!("Ignoring non-string service name for host '{}'", host);
                     }
                 }
             }
-        } else {
-            // Add default exemption rule if none configured
-            let default_rule = ExemptionRule::default();
-            crate::sp_info!("Added default exemption rule: pathPatterns={:?}", default_rule.path_patterns);
-            self.exemption_rules.push(default_rule);
+            crate::sp_info!("Configured service names by host: {:?}", self.service_name_by_host);
         }
     }
 
-    fn extract_exemption_patterns(&self, exemption_entry: &serde_json::Value) -> (Vec<String>, Vec<String>) {
-        let mut host_patterns = Vec::new();
-        let mut path_patterns = Vec::new();
-
-        if let Some(hosts) = exemption_entry.get("hostPatterns") {
-            if let Some(hosts_array) = hosts.as_array() {
-                for host_entry in hosts_array {
-                    if let Some(host) = host_entry.as_str() {
-                        host_patterns.push(host.to_string());
+    fn parse_scope_attributes(&mut self, config_json: &serde_json::Value) {
+        if let Some(attrs) = config_json.get("scope_attributes").and_then(|v| v.as_object()) {
+            for (key, value) in attrs {
+                match value.as_str() {
+                    Some(value) => {
+                        self.scope_attributes.insert(key.clone(), value.to_string());
+                    }
+                    None => {
+                        crate::sp_warn!("Ignoring non-string scope attribute '{}'", key);
                     }
                 }
             }
+            crate::sp_info!("Configured {} scope attributes", self.scope_attributes.len());
         }
+    }
 
-        if let Some(paths) = exemption_entry.get("pathPatterns") {
-            if let Some(paths_array) = paths.as_array() {
-                for path_entry in paths_array {
-                    if let Some(path) = path_entry.as_str() {
-                        path_patterns.push(path.to_string());
+    fn parse_strip_request_headers(&mut self, config_json: &serde_json::Value) {
+        if let Some(headers) = config_json.get("strip_request_headers") {
+            self.strip_request_headers = string_array(headers)
+                .into_iter()
+                .map(|name| name.to_ascii_lowercase())
+                .filter(|name| {
+                    // Pseudo-headers are required by the proxy and never stripped
+                    let is_pseudo = name.starts_with(':');
+                    if is_pseudo {
+                        crate::sp_warn!("Ignoring pseudo-header '{}' in strip_request_headers", name);
                     }
+                    !is_pseudo
+                })
+                .collect();
+            crate::sp_info!("Configured request headers to strip: {:?}", self.strip_request_headers);
+        }
+    }
+
+    fn parse_sampling(&mut self, config_json: &serde_json::Value) {
+        if let Some(rate) = config_json.get("sampling_rate").and_then(|v| v.as_f64()) {
+            self.sampling_rate = rate.clamp(0.0, 1.0);
+            if self.sampling_rate != rate {
+                crate::sp_warn!("sampling_rate {} is outside 0.0-1.0, using {}", rate, self.sampling_rate);
+            }
+            crate::sp_info!("Configured sampling rate: {}", self.sampling_rate);
+        }
+        if let Some(rate) = config_json.get("session_sampling_rate").and_then(|v| v.as_f64()) {
+            let clamped = rate.clamp(0.0, 1.0);
+            if clamped != rate {
+                crate::sp_warn!("session_sampling_rate {} is outside 0.0-1.0, using {}", rate, clamped);
+            }
+            self.session_sampling_rate = Some(clamped);
+            crate::sp_info!("Configured session sampling rate: {}", clamped);
+        }
+        if let Some(key) = config_json.get("sampling_key").and_then(|v| v.as_str()) {
+            match SamplingKey::parse(key) {
+                Some(sampling_key) => {
+                    self.sampling_key = sampling_key;
+                    crate::sp_info!("Configured sampling key: {:?}", self.sampling_key);
+                }
+                None => {
+                    crate::sp_warn!("Invalid sampling_key '{}', using {:?}", key, self.sampling_key);
                 }
             }
-        } else {
-            // Use default path patterns if none specified
-            path_patterns = ExemptionRule::default().path_patterns;
         }
+    }
 
-        (host_patterns, path_patterns)
+    fn parse_bodies_as_logs(&mut self, config_json: &serde_json::Value) {
+        if let Some(as_logs) = config_json.get("bodies_as_logs").and_then(|v| v.as_bool()) {
+            self.bodies_as_logs = as_logs;
+            crate::sp_info!("Configured bodies as log records: {}", self.bodies_as_logs);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+    fn parse_skip_response_headers(&mut self, config_json: &serde_json::Value) {
+        if let Some(headers) = config_json.get("skip_response_headers") {
+            self.skip_response_headers = string_array(headers)
+                .into_iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect();
+            crate::sp_info!("Configured response headers to skip: {:?}", self.skip_response_headers);
+        }
+    }
 
-    #[test]
-    fn test_config_default() {
-        let config = Config::default();
-        assert_eq!(config.sp_backend_url, "https://o.softprobe.ai");
-        assert_eq!(config.service_name, "default-service");
-        assert!(config.traffic_direction.is_none());
-        assert!(config.collection_rules.is_empty());
-        assert!(config.public_key.is_empty());
+    fn parse_export_format(&mut self, config_json: &serde_json::Value) {
+        if let Some(format) = config_json.get("export_format").and_then(|v| v.as_str()) {
+            match ExportFormat::parse(format) {
+                Some(export_format) => {
+                    self.export_format = export_format;
+                    crate::sp_info!("Configured export format: {:?}", self.export_format);
+                }
+                None => {
+                    crate::sp_warn!("Invalid export_format '{}', using {:?}", format, self.export_format);
+                }
+            }
+        }
+        if let Some(path) = config_json.get("zipkin_path").and_then(|v| v.as_str()) {
+            self.zipkin_path = path.to_string();
+            crate::sp_info!("Configured Zipkin path: {}", self.zipkin_path);
+        }
     }
 
-    #[test]
-    fn test_exemption_rule_default() {
-        let rule = ExemptionRule::default();
-        assert!(rule.host_patterns.is_empty());
-        assert!(rule.path_patterns.contains(&"/v1/traces".to_string()));
-        assert!(rule.path_patterns.contains(&"/api/traces".to_string()));
+    fn parse_body_capture_methods(&mut self, config_json: &serde_json::Value) {
+        if let Some(methods) = config_json.get("body_capture_methods") {
+            self.body_capture_methods = string_array(methods)
+                .into_iter()
+                .map(|method| method.to_ascii_uppercase())
+                .collect();
+            crate::sp_info!("Configured body capture methods: {:?}", self.body_capture_methods);
+        }
     }
 
-    #[test]
-    fn test_config_parse_backend_url() {
-        let mut config = Config::default();
-        let json_config = json!({
-            "sp_backend_url": "https://custom.backend.com"
-        });
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
-        assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.sp_backend_url, "https://custom.backend.com");
+    fn parse_shadow_backend_url(&mut self, config_json: &serde_json::Value) {
+        if let Some(url) = config_json.get("shadow_backend_url").and_then(|v| v.as_str()) {
+            self.shadow_backend_url = if url.is_empty() { None } else { Some(url.to_string()) };
+            crate::sp_info!("Configured shadow backend URL: {:?}", self.shadow_backend_url);
+        }
     }
 
-    #[test]
-    fn test_config_parse_service_name() {
-        let mut config = Config::default();
-        let json_config = json!({
-            "service_name": "test-service"
-        });
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
-        assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.service_name, "test-service");
+    fn parse_capture_cookies(&mut self, config_json: &serde_json::Value) {
+        if let Some(capture) = config_json.get("capture_cookies").and_then(|v| v.as_bool()) {
+            self.capture_cookies = capture;
+            crate::sp_info!("Configured cookie capture: {}", self.capture_cookies);
+        }
     }
 
-    #[test]
-    fn test_config_parse_traffic_direction() {
-        let mut config = Config::default();
-        let json_config = json!({
-            "traffic_direction": "outbound"
+    fn parse_emit_exemption_marker(&mut self, config_json: &serde_json::Value) {
+        if let Some(emit) = config_json.get("emit_exemption_marker").and_then(|v| v.as_bool()) {
+            self.emit_exemption_marker = emit;
+            crate::sp_info!("Configured exemption marker spans: {}", self.emit_exemption_marker);
+        }
+    }
+
+    fn parse_max_tracestate_entries(&mut self, config_json: &serde_json::Value) {
+        if let Some(max_entries) = config_json.get("max_tracestate_entries").and_then(|v| v.as_u64()) {
+            if max_entries > 0 {
+                self.max_tracestate_entries = max_entries as usize;
+                crate::sp_info!("Configured max tracestate entries: {}", self.max_tracestate_entries);
+            } else {
+                crate::sp_warn!("Invalid max_tracestate_entries 0, using {}", self.max_tracestate_entries);
+            }
+        }
+    }
+
+    fn parse_no_body_paths(&mut self, config_json: &serde_json::Value) {
+        if let Some(paths) = config_json.get("no_body_paths") {
+            self.no_body_paths = CompiledPattern::compile_all(&string_array(paths));
+            crate::sp_info!("Configured no-body paths: {:?}", self.no_body_paths);
+        }
+    }
+
+    fn parse_dual_emit(&mut self, config_json: &serde_json::Value) {
+        if let Some(dual_emit) = config_json.get("dual_emit").and_then(|v| v.as_bool()) {
+            self.dual_emit = dual_emit;
+            crate::sp_info!("Configured dual attribute emission: {}", self.dual_emit);
+        }
+    }
+
+    fn parse_log_payload_size(&mut self, config_json: &serde_json::Value) {
+        if let Some(log_payload_size) = config_json.get("log_payload_size").and_then(|v| v.as_bool()) {
+            self.log_payload_size = log_payload_size;
+            crate::sp_info!("Configured payload size logging: {}", self.log_payload_size);
+        }
+    }
+
+    fn parse_traces_method(&mut self, config_json: &serde_json::Value) {
+        if let Some(method) = config_json.get("traces_method").and_then(|v| v.as_str()) {
+            let method = method.to_ascii_uppercase();
+            if TRACES_METHODS.contains(&method.as_str()) {
+                self.traces_method = method;
+                crate::sp_info!("Configured traces method: {}", self.traces_method);
+            } else {
+                crate::sp_warn!("Invalid traces_method '{}', using {}", method, self.traces_method);
+            }
+        }
+    }
+
+    fn parse_force_capture_header(&mut self, config_json: &serde_json::Value) {
+        if let Some(header) = config_json.get("force_capture_header").and_then(|v| v.as_str()) {
+            self.force_capture_header = if header.is_empty() { None } else { Some(header.to_ascii_lowercase()) };
+            crate::sp_info!("Configured force capture header: {:?}", self.force_capture_header);
+        }
+    }
+
+    fn parse_outbound_ports(&mut self, config_json: &serde_json::Value) {
+        if let Some(ports) = config_json.get("outbound_ports").and_then(|v| v.as_array()) {
+            self.outbound_ports = ports
+                .iter()
+                .filter_map(|port| {
+                    let parsed = port.as_u64().and_then(|p| u16::try_from(p).ok()).filter(|p| *p > 0);
+                    if parsed.is_none() {
+                        crate::sp_warn!("Ignoring invalid outbound port {}", port);
+                    }
+                    parsed
+                })
+                .collect();
+            crate::sp_info!("Configured outbound ports: {:?}", self.outbound_ports);
+        }
+    }
+
+    fn parse_infra_ports(&mut self, config_json: &serde_json::Value) {
+        if let Some(ports) = config_json.get("infra_ports").and_then(|v| v.as_array()) {
+            self.infra_ports = ports
+                .iter()
+                .filter_map(|entry| {
+                    let parsed = port_range(entry);
+                    if parsed.is_none() {
+                        crate::sp_warn!("Ignoring invalid infra port {}", entry);
+                    }
+                    parsed
+                })
+                .collect();
+            crate::sp_info!("Configured infra ports: {:?}", self.infra_ports);
+        }
+    }
+
+    fn parse_node_metadata_attributes(&mut self, config_json: &serde_json::Value) {
+        if let Some(keys) = config_json.get("node_metadata_attributes") {
+            self.node_metadata_attributes = string_array(keys);
+            crate::sp_info!("Configured node metadata attributes: {:?}", self.node_metadata_attributes);
+        }
+    }
+
+    fn parse_propagate_hosts(&mut self, config_json: &serde_json::Value) {
+        if let Some(hosts) = config_json.get("propagate_hosts") {
+            self.propagate_hosts = string_array(hosts);
+            crate::sp_info!("Configured trace propagation hosts: {:?}", self.propagate_hosts);
+        }
+    }
+
+    fn parse_min_body_capture_bytes(&mut self, config_json: &serde_json::Value) {
+        if let Some(min_bytes) = config_json.get("min_body_capture_bytes").and_then(|v| v.as_u64()) {
+            self.min_body_capture_bytes = min_bytes as usize;
+            crate::sp_info!("Configured min body capture bytes: {}", self.min_body_capture_bytes);
+        }
+    }
+
+    fn parse_auto_generate_session_id(&mut self, config_json: &serde_json::Value) {
+        if let Some(auto_generate) = config_json.get("auto_generate_session_id").and_then(|v| v.as_bool()) {
+            self.auto_generate_session_id = auto_generate;
+            crate::sp_info!("Configured session ID auto-generation: {}", self.auto_generate_session_id);
+        }
+    }
+
+    fn parse_keep_authorization_scheme(&mut self, config_json: &serde_json::Value) {
+        if let Some(keep_scheme) = config_json.get("keep_authorization_scheme").and_then(|v| v.as_bool()) {
+            self.keep_authorization_scheme = keep_scheme;
+            crate::sp_info!("Configured Authorization scheme capture: {}", self.keep_authorization_scheme);
+        }
+    }
+
+    fn parse_max_retries(&mut self, config_json: &serde_json::Value) {
+        if let Some(max_retries) = config_json.get("max_retries").and_then(|v| v.as_u64()) {
+            self.max_retries = u32::try_from(max_retries).unwrap_or(u32::MAX);
+            crate::sp_info!("Configured max export retries: {}", self.max_retries);
+        }
+    }
+
+    fn parse_inflight_limit(&mut self, config_json: &serde_json::Value) {
+        if let Some(limit) = config_json.get("max_inflight_dispatches").and_then(|v| v.as_u64()) {
+            self.max_inflight_dispatches = u32::try_from(limit).unwrap_or(u32::MAX);
+            crate::sp_info!("Configured max in-flight dispatches: {}", self.max_inflight_dispatches);
+        }
+        if let Some(max_spans) = config_json.get("max_buffered_spans").and_then(|v| v.as_u64()) {
+            self.max_buffered_spans = u32::try_from(max_spans).unwrap_or(u32::MAX);
+            crate::sp_info!("Configured max buffered spans: {}", self.max_buffered_spans);
+        }
+        if let Some(overflow) = config_json.get("inflight_overflow").and_then(|v| v.as_str()) {
+            match InflightOverflow::parse(overflow) {
+                Some(inflight_overflow) => {
+                    self.inflight_overflow = inflight_overflow;
+                    crate::sp_info!("Configured in-flight overflow: {:?}", self.inflight_overflow);
+                }
+                None => {
+                    crate::sp_warn!("Invalid inflight_overflow '{}', using {:?}", overflow, self.inflight_overflow);
+                }
+            }
+        }
+    }
+
+    fn parse_collapse_numeric_segments(&mut self, config_json: &serde_json::Value) {
+        if let Some(collapse) = config_json.get("collapse_numeric_segments").and_then(|v| v.as_bool()) {
+            self.collapse_numeric_segments = collapse;
+            crate::sp_info!("Configured numeric route segment collapsing: {}", self.collapse_numeric_segments);
+        }
+    }
+
+    fn parse_require_body_for_capture(&mut self, config_json: &serde_json::Value) {
+        if let Some(require_body) = config_json.get("require_body_for_capture").and_then(|v| v.as_bool()) {
+            self.require_body_for_capture = require_body;
+            crate::sp_info!("Configured require body for capture: {}", self.require_body_for_capture);
+        }
+    }
+
+    fn parse_expose_span_id_header(&mut self, config_json: &serde_json::Value) {
+        if let Some(expose) = config_json.get("expose_span_id_header").and_then(|v| v.as_bool()) {
+            self.expose_span_id_header = expose;
+            crate::sp_info!("Configured span ID response header: {}", self.expose_span_id_header);
+        }
+    }
+
+    fn parse_numeric_header_attributes(&mut self, config_json: &serde_json::Value) {
+        if let Some(headers) = config_json.get("numeric_header_attributes") {
+            self.numeric_header_attributes = string_array(headers)
+                .into_iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect();
+            crate::sp_info!("Configured numeric header attributes: {:?}", self.numeric_header_attributes);
+        }
+    }
+
+    fn parse_low_cardinality_headers(&mut self, config_json: &serde_json::Value) {
+        if let Some(headers) = config_json.get("low_cardinality_headers") {
+            self.low_cardinality_headers = string_array(headers)
+                .into_iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect();
+            crate::sp_info!("Configured low-cardinality headers: {:?}", self.low_cardinality_headers);
+        }
+        if let Some(buckets) = config_json.get("low_cardinality_buckets").and_then(|v| v.as_u64()) {
+            self.low_cardinality_buckets = u32::try_from(buckets).unwrap_or(u32::MAX);
+            crate::sp_info!("Configured low-cardinality buckets: {}", self.low_cardinality_buckets);
+        }
+    }
+
+    fn parse_grpc_capture_methods(&mut self, config_json: &serde_json::Value) {
+        if let Some(methods) = config_json.get("grpc_capture_methods") {
+            self.grpc_capture_methods = string_array(methods)
+                .into_iter()
+                .map(|method| method.trim_start_matches('/').to_string())
+                .collect();
+            crate::sp_info!("Configured gRPC body capture methods: {:?}", self.grpc_capture_methods);
+        }
+    }
+
+    fn parse_hash_bodies(&mut self, config_json: &serde_json::Value) {
+        if let Some(hash_bodies) = config_json.get("hash_bodies").and_then(|v| v.as_bool()) {
+            self.hash_bodies = hash_bodies;
+            crate::sp_info!("Configured request body hashing: {}", self.hash_bodies);
+        }
+    }
+
+    fn parse_max_body_bytes(&mut self, config_json: &serde_json::Value) {
+        if let Some(max_body_bytes) = config_json.get("max_body_bytes").and_then(|v| v.as_u64()) {
+            self.max_body_bytes = usize::try_from(max_body_bytes).unwrap_or(usize::MAX);
+            crate::sp_info!("Configured max body bytes: {}", self.max_body_bytes);
+        }
+    }
+
+    fn parse_text_content_types(&mut self, config_json: &serde_json::Value) {
+        if let Some(types) = config_json.get("text_content_types") {
+            self.text_content_types = string_array(types)
+                .into_iter()
+                .map(|media_type| media_type.trim().to_ascii_lowercase())
+                .collect();
+            crate::sp_info!("Configured text content types: {:?}", self.text_content_types);
+        }
+    }
+
+    fn parse_propagation_only(&mut self, config_json: &serde_json::Value) {
+        if let Some(propagation_only) = config_json.get("propagation_only").and_then(|v| v.as_bool()) {
+            self.propagation_only = propagation_only;
+            crate::sp_info!("Configured propagation-only mode: {}", self.propagation_only);
+        }
+    }
+
+    fn parse_custom_traceparent_header(&mut self, config_json: &serde_json::Value) {
+        if let Some(header) = config_json.get("custom_traceparent_header").and_then(|v| v.as_str()) {
+            self.custom_traceparent_header = if header.is_empty() { None } else { Some(header.to_ascii_lowercase()) };
+            crate::sp_info!("Configured custom traceparent header: {:?}", self.custom_traceparent_header);
+        }
+        if let Some(authoritative) = config_json.get("custom_traceparent_authoritative").and_then(|v| v.as_bool()) {
+            self.custom_traceparent_authoritative = authoritative;
+            crate::sp_info!("Configured custom traceparent authoritative: {}", self.custom_traceparent_authoritative);
+        }
+    }
+
+    fn parse_propagation_format(&mut self, config_json: &serde_json::Value) {
+        if let Some(format) = config_json.get("propagation_format").and_then(|v| v.as_str()) {
+            match PropagationFormat::parse(format) {
+                Some(propagation_format) => {
+                    self.propagation_format = propagation_format;
+                    crate::sp_info!("Configured propagation format: {:?}", self.propagation_format);
+                }
+                None => {
+                    crate::sp_warn!("Invalid propagation_format '{}', using {:?}", format, self.propagation_format);
+                }
+            }
+        }
+    }
+
+    fn parse_drop_mismatched_bodies(&mut self, config_json: &serde_json::Value) {
+        if let Some(drop) = config_json.get("drop_mismatched_bodies").and_then(|v| v.as_bool()) {
+            self.drop_mismatched_bodies = drop;
+            crate::sp_info!("Configured dropping of content-length mismatched bodies: {}", self.drop_mismatched_bodies);
+        }
+    }
+
+    fn parse_fixed_parent_traceparent(&mut self, config_json: &serde_json::Value) {
+        if let Some(traceparent) = config_json.get("fixed_parent_traceparent").and_then(|v| v.as_str()) {
+            match crate::otel::parse_traceparent(traceparent) {
+                Ok(_) => {
+                    self.fixed_parent_traceparent = Some(traceparent.trim().to_string());
+                    crate::sp_info!("Configured fixed parent traceparent: {}", traceparent);
+                }
+                Err(err) => {
+                    crate::sp_warn!("Ignoring invalid fixed_parent_traceparent '{}': {:?}", traceparent, err);
+                }
+            }
+        }
+    }
+
+    fn parse_mask_path_secrets(&mut self, config_json: &serde_json::Value) {
+        if let Some(mask) = config_json.get("mask_path_secrets").and_then(|v| v.as_bool()) {
+            self.mask_path_secrets = mask;
+            crate::sp_info!("Configured path secret masking: {}", self.mask_path_secrets);
+        }
+    }
+
+    fn parse_duration_unit(&mut self, config_json: &serde_json::Value) {
+        if let Some(unit) = config_json.get("duration_unit").and_then(|v| v.as_str()) {
+            match DurationUnit::parse(unit) {
+                Some(unit) => {
+                    self.duration_unit = unit;
+                    crate::sp_info!("Configured duration unit: {}", unit.as_str());
+                }
+                None => {
+                    crate::sp_warn!("Ignoring unknown duration_unit '{}', expected ms, ns or s", unit);
+                }
+            }
+        }
+    }
+
+    fn parse_grpc_message_spans(&mut self, config_json: &serde_json::Value) {
+        if let Some(enabled) = config_json.get("grpc_message_spans").and_then(|v| v.as_bool()) {
+            self.grpc_message_spans = enabled;
+            crate::sp_info!("Configured per-message gRPC spans: {}", self.grpc_message_spans);
+        }
+    }
+
+    /// Whether request bodies are buffered for the given method
+    pub fn captures_body_for_method(&self, method: &str) -> bool {
+        self.body_capture_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    fn parse_collection_rules(&mut self, config_json: &serde_json::Value) {
+        if let Some(rules) = config_json.get("collectionRules") {
+            let (server_configs, client_configs) = self.extract_collection_data(rules);
+            self.create_collection_rules(server_configs, client_configs);
+        }
+    }
+
+    fn extract_collection_data(&self, rules: &serde_json::Value) -> (Vec<ServerConfig>, Vec<ClientConfig>) {
+        let mut server_configs = Vec::new();
+        let mut client_configs = Vec::new();
+
+        // Extract server paths
+        if let Some(server_obj) = rules.get("http").and_then(|v| v.get("server")) {
+            if let Some(server_array) = server_obj.as_array() {
+                for server_entry in server_array {
+                    if let Some(path) = server_entry.get("path").and_then(|v| v.as_str()) {
+                        server_configs.push(ServerConfig {
+                            path: path.to_string(),
+                            methods: rule_methods(server_entry),
+                            header_patterns: rule_header_patterns(server_entry),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Extract client configs
+        if let Some(client_obj) = rules.get("http").and_then(|v| v.get("client")) {
+            if let Some(client_array) = client_obj.as_array() {
+                for client_entry in client_array {
+                    if let Some(host) = client_entry.get("host").and_then(|v| v.as_str()) {
+                        let mut paths = Vec::new();
+                        if let Some(paths_obj) = client_entry.get("paths") {
+                            if let Some(paths_array) = paths_obj.as_array() {
+                                for path_entry in paths_array {
+                                    if let Some(path) = path_entry.as_str() {
+                                        paths.push(path.to_string());
+                                    }
+                                }
+                            }
+                        }
+                        client_configs.push(
+                            ClientConfig::new(host.to_string(), paths, rule_methods(client_entry))
+                                .with_header_patterns(rule_header_patterns(client_entry)),
+                        );
+                    }
+                }
+            }
+        }
+
+        (server_configs, client_configs)
+    }
+
+    fn create_collection_rules(&mut self, server_configs: Vec<ServerConfig>, client_configs: Vec<ClientConfig>) {
+        // Create rules for each server path
+        for server_config in server_configs {
+            crate::sp_info!(
+                "Added server collection rule: {}, methods={:?}, headerPatterns={:?}",
+                server_config.path, server_config.methods, server_config.header_patterns
+            );
+            self.collection_rules.push(CollectionRule {
+                http: HttpCollectionRule {
+                    server: server_config,
+                    client: vec![],
+                },
+            });
+        }
+
+        // Create rules for each client config
+        for client_config in client_configs {
+            crate::sp_info!(
+                "Added client collection rule: host={}, paths={:?}, methods={:?}, headerPatterns={:?}",
+                client_config.host, client_config.paths, client_config.methods, client_config.header_patterns
+            );
+            self.collection_rules.push(CollectionRule {
+                http: HttpCollectionRule {
+                    server: ServerConfig {
+                        path: String::new(),
+                        methods: vec![],
+                        header_patterns: vec![],
+                    },
+                    client: vec![client_config],
+                },
+            });
+        }
+
+        self.server_rule_index = crate::traffic::ServerRuleIndex::build(&self.collection_rules);
+    }
+
+    fn parse_masking_rules(&mut self, config_json: &serde_json::Value) {
+        let Some(masking) = config_json.get("masking").filter(|v| v.is_object()) else {
+            return;
+        };
+
+        let flag = |key: &str| masking.get(key).and_then(|v| v.as_bool());
+        let length = |key: &str| masking.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+
+        if let Some(enabled) = flag("enabled") {
+            self.masking.enabled = enabled;
+        }
+        if let Some(mode) = masking.get("mode").and_then(|v| v.as_str()) {
+            match MaskingMode::parse(mode) {
+                Some(mode) => self.masking.mode = mode,
+                None => {
+                    crate::sp_warn!("Unknown masking mode '{}', keeping {:?}", mode, self.masking.mode);
+                }
+            }
+        }
+        if let Some(mask_request_body) = flag("maskRequestBody") {
+            self.masking.mask_request_body = mask_request_body;
+        }
+        if let Some(mask_response_body) = flag("maskResponseBody") {
+            self.masking.mask_response_body = mask_response_body;
+        }
+        if let Some(structured_json) = flag("structuredJson") {
+            self.masking.structured_json = structured_json;
+        }
+        if let Some(auto_detect_values) = flag("autoDetectValues") {
+            self.masking.auto_detect_values = auto_detect_values;
+        }
+        if let Some(deep_masking_max_bytes) = length("deepMaskingMaxBytes") {
+            self.masking.deep_masking_max_bytes = deep_masking_max_bytes;
+        }
+        if let Some(headers) = masking.get("maskRequestHeaders") {
+            self.masking.mask_request_headers = string_array(headers);
+        }
+        if let Some(headers) = masking.get("maskResponseHeaders") {
+            self.masking.mask_response_headers = string_array(headers);
+        }
+        if let Some(keep_prefix_length) = length("keepPrefixLength") {
+            self.masking.keep_prefix_length = keep_prefix_length;
+        }
+        if let Some(keep_suffix_length) = length("keepSuffixLength") {
+            self.masking.keep_suffix_length = keep_suffix_length;
+        }
+        if let Some(fields) = masking.get("customFieldNames") {
+            self.masking = std::mem::take(&mut self.masking).with_custom_field_names(string_array(fields));
+        }
+        if let Some(patterns) = masking.get("customPatterns").and_then(|v| v.as_array()) {
+            self.masking.custom_patterns = patterns.iter().filter_map(custom_masking_pattern).collect();
+        }
+        crate::sp_info!("Configured masking: {:?}", self.masking);
+    }
+
+    fn parse_batching(&mut self, config_json: &serde_json::Value) {
+        let Some(batching) = config_json.get("batching").filter(|v| v.is_object()) else {
+            return;
+        };
+        let number = |key: &str| batching.get(key).and_then(|v| v.as_u64());
+
+        if let Some(enabled) = batching.get("enabled").and_then(|v| v.as_bool()) {
+            self.batching.enabled = enabled;
+        }
+        if let Some(flush_interval_ms) = number("flushIntervalMs").filter(|ms| *ms > 0) {
+            self.batching.flush_interval_ms = flush_interval_ms;
+        }
+        if let Some(max_batch_spans) = number("maxBatchSpans").filter(|spans| *spans > 0) {
+            self.batching.max_batch_spans = max_batch_spans.min(u32::MAX as u64) as u32;
+        }
+        if let Some(max_batch_bytes) = number("maxBatchBytes").filter(|bytes| *bytes > 0) {
+            self.batching.max_batch_bytes = max_batch_bytes as usize;
+        }
+        crate::sp_info!("Configured batching: {:?}", self.batching);
+    }
+
+    fn parse_exemption_rules(&mut self, config_json: &serde_json::Value) {
+        if let Some(exemption_rules) = config_json.get("exemptionRules") {
+            if let Some(exemption_array) = exemption_rules.as_array() {
+                for exemption_entry in exemption_array {
+                    let (host_patterns, path_patterns) = self.extract_exemption_patterns(exemption_entry);
+                    let header_patterns = rule_header_patterns(exemption_entry);
+                    
+                    if !path_patterns.is_empty() || !header_patterns.is_empty() {
+                        crate::sp_info!(
+                            "Added exemption rule: hostPatterns={:?}, pathPatterns={:?}, headerPatterns={:?}",
+                            host_patterns, path_patterns, header_patterns
+                        );
+                        self.exemption_rules.push(
+                            ExemptionRule::new(host_patterns, path_patterns).with_header_patterns(header_patterns),
+                        );
+                    }
+                }
+            }
+        } else {
+            // Add default exemption rule if none configured
+            let default_rule = ExemptionRule::default();
+            crate::sp_info!("Added default exemption rule: pathPatterns={:?}", default_rule.path_patterns);
+            self.exemption_rules.push(default_rule);
+        }
+    }
+
+    fn extract_exemption_patterns(&self, exemption_entry: &serde_json::Value) -> (Vec<String>, Vec<String>) {
+        let mut host_patterns = Vec::new();
+        let mut path_patterns = Vec::new();
+
+        if let Some(hosts) = exemption_entry.get("hostPatterns") {
+            if let Some(hosts_array) = hosts.as_array() {
+                for host_entry in hosts_array {
+                    if let Some(host) = host_entry.as_str() {
+                        host_patterns.push(host.to_string());
+                    }
+                }
+            }
+        }
+
+        if let Some(paths) = exemption_entry.get("pathPatterns") {
+            if let Some(paths_array) = paths.as_array() {
+                for path_entry in paths_array {
+                    if let Some(path) = path_entry.as_str() {
+                        path_patterns.push(path.to_string());
+                    }
+                }
+            }
+        } else if exemption_entry.get("headerPatterns").is_none() {
+            // Use default path patterns if none specified; header-only rules match any path
+            path_patterns = ExemptionRule::default().path_patterns;
+        }
+
+        (host_patterns, path_patterns)
+    }
+}
+
+/// Map a configured traffic direction to "inbound"/"outbound".
+/// Accepts server/inbound and client/outbound; returns None for anything else.
+pub fn normalize_traffic_direction(direction: &str) -> Option<&'static str> {
+    match direction.trim().to_ascii_lowercase().as_str() {
+        "server" | "inbound" => Some("inbound"),
+        "client" | "outbound" => Some("outbound"),
+        _ => None,
+    }
+}
+
+/// Short SHA-256 of the parsed config. serde_json keeps object keys sorted, so
+/// formatting and key order in the plugin configuration don't change the hash.
+fn compute_config_hash(config_json: &serde_json::Value) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(config_json.to_string().as_bytes());
+    digest[..6].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Collect the string entries of a JSON array, ignoring non-string values
+fn string_array(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| entry.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A port number or a `"start-end"` range string
+fn port_range(entry: &serde_json::Value) -> Option<RangeInclusive<u16>> {
+    let parse_port = |port: &str| port.trim().parse::<u16>().ok().filter(|p| *p > 0);
+    let (start, end) = match entry.as_u64() {
+        Some(port) => {
+            let port = u16::try_from(port).ok().filter(|p| *p > 0)?;
+            (port, port)
+        }
+        None => {
+            let (start, end) = entry.as_str()?.split_once('-')?;
+            (parse_port(start)?, parse_port(end)?)
+        }
+    };
+    (start <= end).then_some(start..=end)
+}
+
+/// Default replacement for custom masking patterns without one
+pub const DEFAULT_MASKING_REPLACEMENT: &str = "***";
+
+/// A `{ "pattern": ..., "replacement": ... }` entry of `masking.customPatterns`.
+/// Invalid regexes are logged and skipped.
+fn custom_masking_pattern(entry: &serde_json::Value) -> Option<(Regex, String)> {
+    let Some(pattern) = entry.get("pattern").and_then(|v| v.as_str()) else {
+        crate::sp_warn!("Ignoring custom masking pattern without a pattern: {}", entry);
+        return None;
+    };
+    let replacement = entry
+        .get("replacement")
+        .and_then(|v| v.as_str())
+        .unwrap_or(DEFAULT_MASKING_REPLACEMENT);
+    match Regex::new(pattern) {
+        Ok(regex) => Some((regex, replacement.to_string())),
+        Err(e) => {
+            crate::sp_warn!("Ignoring invalid custom masking pattern '{}': {}", pattern, e);
+            None
+        }
+    }
+}
+
+/// Upper-cased `methods` of a collection rule entry
+fn rule_methods(entry: &serde_json::Value) -> Vec<String> {
+    entry
+        .get("methods")
+        .map(string_array)
+        .unwrap_or_default()
+        .iter()
+        .map(|method| method.to_ascii_uppercase())
+        .collect()
+}
+
+/// `headerPatterns` object of a rule entry as (lower-cased header name, value regex) pairs
+fn rule_header_patterns(entry: &serde_json::Value) -> Vec<(String, String)> {
+    entry
+        .get("headerPatterns")
+        .and_then(|patterns| patterns.as_object())
+        .map(|patterns| {
+            patterns
+                .iter()
+                .filter_map(|(name, pattern)| Some((name.to_ascii_lowercase(), pattern.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_default() {
+        let config = Config::default();
+        assert_eq!(config.sp_backend_url, "https://o.softprobe.ai");
+        assert_eq!(config.service_name, "default-service");
+        assert!(config.traffic_direction.is_none());
+        assert!(config.collection_rules.is_empty());
+        assert!(config.public_key.is_empty());
+        assert!(config.injection_path_denylist.is_empty());
+    }
+
+    #[test]
+    fn test_exemption_rule_default() {
+        let rule = ExemptionRule::default();
+        assert!(rule.host_patterns.is_empty());
+        assert!(rule.path_patterns.contains(&"/v1/traces".to_string()));
+        assert!(rule.path_patterns.contains(&"/api/traces".to_string()));
+        assert!(rule.path_patterns.contains(&"/api/v2/spans".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_backend_url() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "sp_backend_url": "https://custom.backend.com"
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.sp_backend_url, "https://custom.backend.com");
+    }
+
+    #[test]
+    fn test_config_parse_service_name() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "service_name": "test-service"
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.service_name, "test-service");
+    }
+
+    #[test]
+    fn test_config_parse_traffic_direction() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "traffic_direction": "outbound"
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.traffic_direction, Some("outbound".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_traffic_direction_aliases() {
+        for (value, expected) in [("inbound", "inbound"), ("server", "inbound"), ("client", "outbound")] {
+            let mut config = Config::default();
+            let config_str = serde_json::to_string(&json!({ "traffic_direction": value })).unwrap();
+            assert!(config.parse_from_json(config_str.as_bytes()));
+            assert_eq!(config.traffic_direction, Some(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_config_parse_invalid_traffic_direction() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({ "traffic_direction": "sideways" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.traffic_direction.is_none());
+    }
+
+    #[test]
+    fn test_config_parse_public_key() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "public_key": "test-api-key-123"
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.public_key, "test-api-key-123");
+    }
+
+    #[test]
+    fn test_config_parse_collection_rules() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "collectionRules": {
+                "http": {
+                    "server": [
+                        {"path": "/api/test"}
+                    ],
+                    "client": [
+                        {
+                            "host": "example.com",
+                            "paths": ["/api/endpoint1", "/api/endpoint2"]
+                        }
+                    ]
+                }
+            }
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.collection_rules.len(), 2);
+        
+        // Check server rule
+        assert_eq!(config.collection_rules[0].http.server.path, "/api/test");
+        assert!(config.collection_rules[0].http.client.is_empty());
+        
+        // Check client rule
+        assert!(config.collection_rules[1].http.server.path.is_empty());
+        assert_eq!(config.collection_rules[1].http.client.len(), 1);
+        assert_eq!(config.collection_rules[1].http.client[0].host, "example.com");
+        assert_eq!(config.collection_rules[1].http.client[0].paths.len(), 2);
+    }
+
+    #[test]
+    fn test_config_parse_exemption_rules() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "exemptionRules": [
+                {
+                    "hostPatterns": ["internal.com", "localhost"],
+                    "pathPatterns": ["/health", "/metrics"]
+                }
+            ]
         });
         let config_str = serde_json::to_string(&json_config).unwrap();
         
         assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.traffic_direction, Some("outbound".to_string()));
+        assert_eq!(config.exemption_rules.len(), 1);
+        assert_eq!(config.exemption_rules[0].host_patterns.len(), 2);
+        assert_eq!(config.exemption_rules[0].path_patterns.len(), 2);
+        assert!(config.exemption_rules[0].host_patterns.contains(&"internal.com".to_string()));
+        assert!(config.exemption_rules[0].path_patterns.contains(&"/health".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_invalid_json() {
+        let mut config = Config::default();
+        let original_backend = config.sp_backend_url.clone();
+        
+        // Test with invalid JSON
+        assert!(!config.parse_from_json(b"invalid json"));
+        assert_eq!(config.sp_backend_url, original_backend);
+        
+        // Test with invalid UTF-8
+        assert!(!config.parse_from_json(&[0xFF, 0xFE]));
+        assert_eq!(config.sp_backend_url, original_backend);
+    }
+
+    #[test]
+    fn test_config_parse_empty_exemption_rules() {
+        let mut config = Config::default();
+        let json_config = json!({});
+        let config_str = serde_json::to_string(&json_config).unwrap();
+        
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        // Should add default exemption rule
+        assert_eq!(config.exemption_rules.len(), 1);
+        assert!(config.exemption_rules[0].path_patterns.contains(&"/v1/traces".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_masking_rules() {
+        let mut config = Config::default();
+        assert!(!config.masking.enabled);
+
+        let config_str = serde_json::to_string(&json!({
+            "masking": {
+                "enabled": true,
+                "mode": "Hash",
+                "maskResponseBody": false,
+                "structuredJson": true,
+                "autoDetectValues": true,
+                "deepMaskingMaxBytes": 4096,
+                "maskRequestHeaders": ["authorization"],
+                "keepPrefixLength": 2,
+                "keepSuffixLength": 0
+            }
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        assert!(config.masking.enabled);
+        assert_eq!(config.masking.mode, MaskingMode::Hash);
+        assert!(config.masking.mask_request_body);
+        assert!(!config.masking.mask_response_body);
+        assert!(config.masking.structured_json);
+        assert!(config.masking.auto_detect_values);
+        assert_eq!(config.masking.deep_masking_max_bytes, 4096);
+        assert_eq!(config.masking.mask_request_headers, vec!["authorization".to_string()]);
+        assert!(config.masking.mask_response_headers.is_empty());
+        assert_eq!(config.masking.keep_prefix_length, 2);
+        assert_eq!(config.masking.keep_suffix_length, 0);
+    }
+
+    #[test]
+    fn test_config_parse_custom_masking_rules_skips_invalid_patterns() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({
+            "masking": {
+                "enabled": true,
+                "customFieldNames": ["creditCard", "memberNo"],
+                "customPatterns": [
+                    { "pattern": "\\d{3}-\\d{2}-\\d{4}", "replacement": "<ssn>" },
+                    { "pattern": "acct-[0-9]+" },
+                    { "pattern": "(unclosed", "replacement": "x" },
+                    { "replacement": "missing pattern" }
+                ]
+            }
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        assert_eq!(config.masking.custom_field_names, vec!["creditCard".to_string(), "memberNo".to_string()]);
+        let patterns: Vec<(&str, &str)> = config
+            .masking
+            .custom_patterns
+            .iter()
+            .map(|(regex, replacement)| (regex.as_str(), replacement.as_str()))
+            .collect();
+        assert_eq!(patterns, vec![("\\d{3}-\\d{2}-\\d{4}", "<ssn>"), ("acct-[0-9]+", DEFAULT_MASKING_REPLACEMENT)]);
+    }
+
+    #[test]
+    fn test_config_parse_injection_path_denylist() {
+        let mut config = Config::default();
+        let json_config = json!({
+            "injection_path_denylist": ["^/signed/.*", "/s3/upload", 42]
+        });
+        let config_str = serde_json::to_string(&json_config).unwrap();
+
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        let patterns: Vec<&str> = config.injection_path_denylist.iter().map(|p| p.as_str()).collect();
+        assert_eq!(patterns, vec!["^/signed/.*", "/s3/upload"]);
+    }
+
+    #[test]
+    fn test_config_parse_capture_sides() {
+        for (value, expected) in [
+            ("both", CaptureSides::Both),
+            ("request", CaptureSides::Request),
+            ("RESPONSE", CaptureSides::Response),
+        ] {
+            let mut config = Config::default();
+            let config_str = serde_json::to_string(&json!({ "capture_sides": value })).unwrap();
+            assert!(config.parse_from_json(config_str.as_bytes()));
+            assert_eq!(config.capture_sides, expected);
+        }
+    }
+
+    #[test]
+    fn test_config_parse_invalid_capture_sides_keeps_default() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({ "capture_sides": "headers" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.capture_sides, CaptureSides::Both);
+    }
+
+    #[test]
+    fn test_capture_sides_modes() {
+        assert!(CaptureSides::Both.captures_request());
+        assert!(CaptureSides::Both.captures_response());
+        assert!(CaptureSides::Request.captures_request());
+        assert!(!CaptureSides::Request.captures_response());
+        assert!(!CaptureSides::Response.captures_request());
+        assert!(CaptureSides::Response.captures_response());
+    }
+
+    #[test]
+    fn test_config_parse_annotate_ingress_traffic() {
+        let mut config = Config::default();
+        assert!(!config.annotate_ingress_traffic);
+
+        let config_str = serde_json::to_string(&json!({ "annotate_ingress_traffic": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.annotate_ingress_traffic);
+    }
+
+    #[test]
+    fn test_config_parse_max_url_length() {
+        let mut config = Config::default();
+        assert!(config.max_url_length.is_none());
+
+        let config_str = serde_json::to_string(&json!({ "max_url_length": 2048 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_url_length, Some(2048));
+
+        let config_str = serde_json::to_string(&json!({ "max_url_length": 0 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.max_url_length.is_none());
+    }
+
+    #[test]
+    fn test_config_parse_service_name_by_host() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({
+            "service_name_by_host": { "Orders.Shop.Local": "orders", "payments.shop.local": "payments", "bad": 1 }
+        }))
+        .unwrap();
+
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.service_name_by_host.len(), 2);
+        assert_eq!(config.service_name_by_host.get("orders.shop.local"), Some(&"orders".to_string()));
+        assert_eq!(config.service_name_by_host.get("payments.shop.local"), Some(&"payments".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_scope_attributes() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({
+            "scope_attributes": { "agent.instance": "edge-1", "agent.zone": "us-east", "ignored": 3 }
+        }))
+        .unwrap();
+
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.scope_attributes.len(), 2);
+        assert_eq!(config.scope_attributes.get("agent.instance"), Some(&"edge-1".to_string()));
+        assert_eq!(config.scope_attributes.get("agent.zone"), Some(&"us-east".to_string()));
+    }
+
+    #[test]
+    fn test_config_parse_strip_request_headers() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({
+            "strip_request_headers": ["X-Internal-Token", ":authority", "x-debug"]
+        }))
+        .unwrap();
+
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.strip_request_headers, vec!["x-internal-token", "x-debug"]);
+    }
+
+    #[test]
+    fn test_config_parse_sampling() {
+        let mut config = Config::default();
+        assert_eq!(config.sampling_rate, 1.0);
+        assert_eq!(config.sampling_key, SamplingKey::Trace);
+
+        let config_str = serde_json::to_string(&json!({ "sampling_rate": 0.25, "sampling_key": "Session" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.sampling_rate, 0.25);
+        assert_eq!(config.sampling_key, SamplingKey::Session);
+
+        let config_str = serde_json::to_string(&json!({ "sampling_key": "user" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.sampling_key, SamplingKey::Trace);
+
+        let config_str = serde_json::to_string(&json!({ "sampling_rate": 5 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.sampling_rate, 1.0);
+
+        let config_str = serde_json::to_string(&json!({ "sampling_rate": -0.5 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.sampling_rate, 0.0);
+
+        assert_eq!(config.session_sampling_rate, None);
+        let config_str = serde_json::to_string(&json!({ "session_sampling_rate": 0.1 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.session_sampling_rate, Some(0.1));
+
+        let config_str = serde_json::to_string(&json!({ "session_sampling_rate": 2 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.session_sampling_rate, Some(1.0));
+    }
+
+    #[test]
+    fn test_config_parse_bodies_as_logs() {
+        let mut config = Config::default();
+        assert!(!config.bodies_as_logs);
+
+        let config_str = serde_json::to_string(&json!({ "bodies_as_logs": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.bodies_as_logs);
+    }
+
+    #[test]
+    fn test_config_parse_skip_response_headers() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({ "skip_response_headers": ["X-Auth-Echo", "set-cookie"] })).unwrap();
+
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.skip_response_headers, vec!["x-auth-echo", "set-cookie"]);
+    }
+
+    #[test]
+    fn test_config_parse_export_format() {
+        let mut config = Config::default();
+        assert_eq!(config.export_format, ExportFormat::Otlp);
+        assert_eq!(config.zipkin_path, "/api/v2/spans");
+
+        let config_str = serde_json::to_string(&json!({
+            "export_format": "zipkin",
+            "zipkin_path": "/zipkin/api/v2/spans"
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.export_format, ExportFormat::Zipkin);
+        assert_eq!(config.zipkin_path, "/zipkin/api/v2/spans");
+    }
+
+    #[test]
+    fn test_config_parse_body_capture_methods() {
+        let mut config = Config::default();
+        assert!(config.captures_body_for_method("POST"));
+        assert!(!config.captures_body_for_method("GET"));
+
+        let config_str = serde_json::to_string(&json!({ "body_capture_methods": ["get", "post"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.body_capture_methods, vec!["GET", "POST"]);
+        assert!(config.captures_body_for_method("get"));
+        assert!(!config.captures_body_for_method("PUT"));
+    }
+
+    #[test]
+    fn test_config_parse_shadow_backend_url() {
+        let mut config = Config::default();
+        assert!(config.shadow_backend_url.is_none());
+
+        let config_str = serde_json::to_string(&json!({ "shadow_backend_url": "https://shadow.example.com" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.shadow_backend_url.as_deref(), Some("https://shadow.example.com"));
+    }
+
+    #[test]
+    fn test_config_hash() {
+        let parse = |config_json: serde_json::Value| {
+            let mut config = Config::default();
+            assert!(config.parse_from_json(config_json.to_string().as_bytes()));
+            config.config_hash
+        };
+
+        let hash = parse(json!({ "service_name": "orders", "sampling_rate": 0.5 }));
+        assert_eq!(hash.len(), 12);
+        assert_eq!(hash, parse(json!({ "sampling_rate": 0.5, "service_name": "orders" })));
+        assert_ne!(hash, parse(json!({ "service_name": "payments", "sampling_rate": 0.5 })));
+        assert!(Config::default().config_hash.is_empty());
+    }
+
+    #[test]
+    fn test_config_parse_capture_cookies() {
+        let mut config = Config::default();
+        assert!(!config.capture_cookies);
+
+        let config_str = serde_json::to_string(&json!({ "capture_cookies": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.capture_cookies);
+    }
+
+    #[test]
+    fn test_config_parse_emit_exemption_marker() {
+        let mut config = Config::default();
+        assert!(!config.emit_exemption_marker);
+
+        let config_str = serde_json::to_string(&json!({ "emit_exemption_marker": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.emit_exemption_marker);
+    }
+
+    #[test]
+    fn test_config_reparse_is_idempotent() {
+        let config_str = serde_json::to_string(&json!({
+            "collectionRules": {
+                "http": {
+                    "server": [{"path": "/api/orders"}],
+                    "client": [{"host": "example.com", "paths": ["/v1"]}]
+                }
+            },
+            "exemptionRules": [{"pathPatterns": ["/healthz"]}]
+        }))
+        .unwrap();
+
+        let mut once = Config::default();
+        assert!(once.parse_from_json(config_str.as_bytes()));
+
+        let mut twice = Config::default();
+        assert!(twice.parse_from_json(config_str.as_bytes()));
+        assert!(twice.parse_from_json(config_str.as_bytes()));
+
+        assert_eq!(twice.collection_rules.len(), once.collection_rules.len());
+        assert_eq!(twice.exemption_rules.len(), once.exemption_rules.len());
+        assert_eq!(twice.config_hash, once.config_hash);
+    }
+
+    #[test]
+    fn test_config_reparse_resets_removed_fields() {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&json!({ "service_name": "orders", "capture_cookies": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        assert!(config.parse_from_json(b"{}"));
+        assert_eq!(config.service_name, "default-service");
+        assert!(!config.capture_cookies);
+    }
+
+    #[test]
+    fn test_config_parse_max_tracestate_entries() {
+        let mut config = Config::default();
+        assert_eq!(config.max_tracestate_entries, 32);
+
+        let config_str = serde_json::to_string(&json!({ "max_tracestate_entries": 8 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_tracestate_entries, 8);
+
+        let config_str = serde_json::to_string(&json!({ "max_tracestate_entries": 0 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_tracestate_entries, 32);
+    }
+
+    #[test]
+    fn test_config_parse_no_body_paths() {
+        let mut config = Config::default();
+        assert!(config.no_body_paths.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "no_body_paths": ["^/upload/", "/stream"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        let patterns: Vec<&str> = config.no_body_paths.iter().map(|p| p.as_str()).collect();
+        assert_eq!(patterns, vec!["^/upload/", "/stream"]);
+    }
+
+    #[test]
+    fn test_config_parse_dual_emit() {
+        let mut config = Config::default();
+        assert!(!config.dual_emit);
+
+        let config_str = serde_json::to_string(&json!({ "dual_emit": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.dual_emit);
+    }
+
+    #[test]
+    fn test_config_parse_log_payload_size() {
+        let mut config = Config::default();
+        assert!(!config.log_payload_size);
+
+        let config_str = serde_json::to_string(&json!({ "log_payload_size": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.log_payload_size);
     }
 
     #[test]
-    fn test_config_parse_api_key() {
+    fn test_config_parse_traces_method() {
         let mut config = Config::default();
-        let json_config = json!({
-            "api_key": "test-api-key-123"
-        });
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
+        assert_eq!(config.traces_method, "POST");
+
+        let config_str = serde_json::to_string(&json!({ "traces_method": "put" })).unwrap();
         assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.public_key, "test-api-key-123");
+        assert_eq!(config.traces_method, "PUT");
+
+        let config_str = serde_json::to_string(&json!({ "traces_method": "GET" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.traces_method, "POST");
     }
 
     #[test]
-    fn test_config_parse_collection_rules() {
+    fn test_config_parse_force_capture_header() {
         let mut config = Config::default();
-        let json_config = json!({
-            "collectionRules": {
-                "http": {
-                    "server": [
-                        {"path": "/api/test"}
-                    ],
-                    "client": [
-                        {
-                            "host": "example.com",
-                            "paths": ["/api/endpoint1", "/api/endpoint2"]
-                        }
-                    ]
-                }
-            }
-        });
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
+        assert!(config.force_capture_header.is_none());
+
+        let config_str = serde_json::to_string(&json!({ "force_capture_header": "X-SP-Force" })).unwrap();
         assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.collection_rules.len(), 2);
-        
-        // Check server rule
-        assert_eq!(config.collection_rules[0].http.server.path, "/api/test");
-        assert!(config.collection_rules[0].http.client.is_empty());
-        
-        // Check client rule
-        assert!(config.collection_rules[1].http.server.path.is_empty());
-        assert_eq!(config.collection_rules[1].http.client.len(), 1);
-        assert_eq!(config.collection_rules[1].http.client[0].host, "example.com");
-        assert_eq!(config.collection_rules[1].http.client[0].paths.len(), 2);
+        assert_eq!(config.force_capture_header.as_deref(), Some("x-sp-force"));
     }
 
     #[test]
-    fn test_config_parse_exemption_rules() {
+    fn test_config_parse_outbound_ports() {
         let mut config = Config::default();
-        let json_config = json!({
-            "exemptionRules": [
-                {
-                    "hostPatterns": ["internal.com", "localhost"],
-                    "pathPatterns": ["/health", "/metrics"]
-                }
-            ]
-        });
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
+        assert!(config.outbound_ports.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "outbound_ports": [80, 8080, 70000, "443"] })).unwrap();
         assert!(config.parse_from_json(config_str.as_bytes()));
-        assert_eq!(config.exemption_rules.len(), 1);
-        assert_eq!(config.exemption_rules[0].host_patterns.len(), 2);
-        assert_eq!(config.exemption_rules[0].path_patterns.len(), 2);
-        assert!(config.exemption_rules[0].host_patterns.contains(&"internal.com".to_string()));
-        assert!(config.exemption_rules[0].path_patterns.contains(&"/health".to_string()));
+        assert_eq!(config.outbound_ports, vec![80, 8080]);
     }
 
     #[test]
-    fn test_config_parse_invalid_json() {
+    fn test_config_parse_infra_ports() {
         let mut config = Config::default();
-        let original_backend = config.sp_backend_url.clone();
-        
-        // Test with invalid JSON
-        assert!(!config.parse_from_json(b"invalid json"));
-        assert_eq!(config.sp_backend_url, original_backend);
-        
-        // Test with invalid UTF-8
-        assert!(!config.parse_from_json(&[0xFF, 0xFE]));
-        assert_eq!(config.sp_backend_url, original_backend);
+        assert_eq!(config.infra_ports, vec![DEFAULT_INFRA_PORTS]);
+
+        let config_str =
+            serde_json::to_string(&json!({ "infra_ports": [15021, "15000-15010", "9000-8000", 70000, "x"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.infra_ports, vec![15021..=15021, 15000..=15010]);
+
+        let config_str = serde_json::to_string(&json!({ "infra_ports": [] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.infra_ports.is_empty());
     }
 
     #[test]
-    fn test_config_parse_empty_exemption_rules() {
+    fn test_config_parse_node_metadata_attributes() {
         let mut config = Config::default();
-        let json_config = json!({});
-        let config_str = serde_json::to_string(&json_config).unwrap();
-        
+        assert!(config.node_metadata_attributes.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "node_metadata_attributes": ["ISTIO_VERSION", "MESH_ID"] })).unwrap();
         assert!(config.parse_from_json(config_str.as_bytes()));
-        // Should add default exemption rule
-        assert_eq!(config.exemption_rules.len(), 1);
-        assert!(config.exemption_rules[0].path_patterns.contains(&"/v1/traces".to_string()));
+        assert_eq!(config.node_metadata_attributes, vec!["ISTIO_VERSION", "MESH_ID"]);
+    }
+
+    #[test]
+    fn test_config_parse_propagate_hosts() {
+        let mut config = Config::default();
+        assert!(config.propagate_hosts.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "propagate_hosts": [".svc.cluster.local", "api.partner.com"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.propagate_hosts, vec![".svc.cluster.local", "api.partner.com"]);
+    }
+
+    #[test]
+    fn test_config_parse_min_body_capture_bytes() {
+        let mut config = Config::default();
+        assert_eq!(config.min_body_capture_bytes, 0);
+
+        let config_str = serde_json::to_string(&json!({ "min_body_capture_bytes": 16 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.min_body_capture_bytes, 16);
+    }
+
+    #[test]
+    fn test_config_parse_auto_generate_session_id() {
+        let mut config = Config::default();
+        assert!(config.auto_generate_session_id);
+
+        let config_str = serde_json::to_string(&json!({ "auto_generate_session_id": false })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(!config.auto_generate_session_id);
+    }
+
+    #[test]
+    fn test_config_parse_keep_authorization_scheme() {
+        let mut config = Config::default();
+        assert!(!config.keep_authorization_scheme);
+
+        let config_str = serde_json::to_string(&json!({ "keep_authorization_scheme": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.keep_authorization_scheme);
+    }
+
+    #[test]
+    fn test_config_parse_inflight_limit() {
+        let mut config = Config::default();
+        assert_eq!(config.max_inflight_dispatches, 0);
+        assert_eq!(config.inflight_overflow, InflightOverflow::Drop);
+
+        let config_str = serde_json::to_string(&json!({
+            "max_inflight_dispatches": 64,
+            "inflight_overflow": "Queue"
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_inflight_dispatches, 64);
+        assert_eq!(config.inflight_overflow, InflightOverflow::Queue);
+    }
+
+    #[test]
+    fn test_config_parse_collapse_numeric_segments() {
+        let mut config = Config::default();
+        assert!(!config.collapse_numeric_segments);
+
+        let config_str = serde_json::to_string(&json!({ "collapse_numeric_segments": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.collapse_numeric_segments);
+    }
+
+    #[test]
+    fn test_config_parse_require_body_for_capture() {
+        let mut config = Config::default();
+        assert!(!config.require_body_for_capture);
+
+        let config_str = serde_json::to_string(&json!({ "require_body_for_capture": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.require_body_for_capture);
+    }
+
+    #[test]
+    fn test_config_parse_expose_span_id_header() {
+        let mut config = Config::default();
+        assert!(!config.expose_span_id_header);
+
+        let config_str = serde_json::to_string(&json!({ "expose_span_id_header": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.expose_span_id_header);
+    }
+
+    #[test]
+    fn test_config_parse_numeric_header_attributes() {
+        let mut config = Config::default();
+        assert!(config.numeric_header_attributes.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "numeric_header_attributes": ["Content-Length", "retry-after"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.numeric_header_attributes, vec!["content-length", "retry-after"]);
+    }
+
+    #[test]
+    fn test_config_parse_grpc_capture_methods() {
+        let mut config = Config::default();
+        assert!(config.grpc_capture_methods.is_empty());
+
+        let config_str = serde_json::to_string(&json!({
+            "grpc_capture_methods": ["orders.v1.OrderService/CreateOrder", "/billing.Billing/Charge"]
+        })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(
+            config.grpc_capture_methods,
+            vec!["orders.v1.OrderService/CreateOrder", "billing.Billing/Charge"]
+        );
+    }
+
+    #[test]
+    fn test_config_parse_hash_bodies() {
+        let mut config = Config::default();
+        assert!(!config.hash_bodies);
+
+        let config_str = serde_json::to_string(&json!({ "hash_bodies": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.hash_bodies);
+    }
+
+    #[test]
+    fn test_config_parse_max_body_bytes() {
+        let mut config = Config::default();
+        assert_eq!(config.max_body_bytes, DEFAULT_MAX_BODY_BYTES);
+
+        let config_str = serde_json::to_string(&json!({ "max_body_bytes": 1024 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_body_bytes, 1024);
+    }
+
+    #[test]
+    fn test_config_parse_text_content_types() {
+        let mut config = Config::default();
+        assert!(config.text_content_types.is_empty());
+
+        let config_str = serde_json::to_string(&json!({ "text_content_types": ["Application/GraphQL", "message/*"] })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.text_content_types, vec!["application/graphql", "message/*"]);
+    }
+
+    #[test]
+    fn test_config_parse_propagation_only() {
+        let mut config = Config::default();
+        assert!(!config.propagation_only);
+
+        let config_str = serde_json::to_string(&json!({ "propagation_only": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.propagation_only);
+    }
+
+    #[test]
+    fn test_config_parse_custom_traceparent_header() {
+        let mut config = Config::default();
+        assert_eq!(config.custom_traceparent_header, None);
+        assert!(!config.custom_traceparent_authoritative);
+
+        let config_str = serde_json::to_string(&json!({
+            "custom_traceparent_header": "X-Acme-Trace",
+            "custom_traceparent_authoritative": true
+        })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.custom_traceparent_header.as_deref(), Some("x-acme-trace"));
+        assert!(config.custom_traceparent_authoritative);
+    }
+
+    #[test]
+    fn test_config_parse_max_buffered_spans() {
+        let mut config = Config::default();
+        assert_eq!(config.max_buffered_spans, DEFAULT_MAX_BUFFERED_SPANS);
+
+        let config_str = serde_json::to_string(&json!({ "max_buffered_spans": 10 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_buffered_spans, 10);
+    }
+
+    #[test]
+    fn test_config_parse_fixed_parent_traceparent() {
+        let mut config = Config::default();
+        assert_eq!(config.fixed_parent_traceparent, None);
+
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let config_str = serde_json::to_string(&json!({ "fixed_parent_traceparent": traceparent })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.fixed_parent_traceparent.as_deref(), Some(traceparent));
+
+        let config_str = serde_json::to_string(&json!({ "fixed_parent_traceparent": "00-not-a-traceparent" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.fixed_parent_traceparent, None);
+    }
+
+    #[test]
+    fn test_config_parse_mask_path_secrets() {
+        let mut config = Config::default();
+        assert!(!config.mask_path_secrets);
+
+        let config_str = serde_json::to_string(&json!({ "mask_path_secrets": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.mask_path_secrets);
+    }
+
+    fn parsed_config(config_json: serde_json::Value) -> Config {
+        let mut config = Config::default();
+        assert!(config.parse_from_json(serde_json::to_string(&config_json).unwrap().as_bytes()));
+        config
+    }
+
+    #[test]
+    fn test_propagation_only_disables_bodies_as_logs() {
+        let config = parsed_config(json!({ "propagation_only": true, "bodies_as_logs": true }));
+        assert!(config.propagation_only);
+        assert!(!config.bodies_as_logs);
+        assert_eq!(config.conflicts, vec![ConfigConflict::PropagationOnlyWithBodiesAsLogs]);
+    }
+
+    #[test]
+    fn test_response_only_capture_disables_body_hashing() {
+        let config = parsed_config(json!({ "capture_sides": "response", "hash_bodies": true }));
+        assert!(!config.hash_bodies);
+        assert_eq!(config.conflicts, vec![ConfigConflict::HashBodiesWithoutRequestCapture]);
+
+        let config = parsed_config(json!({ "capture_sides": "request", "hash_bodies": true }));
+        assert!(config.hash_bodies);
+        assert!(config.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_zipkin_export_disables_batching_and_body_logs() {
+        let config = parsed_config(json!({
+            "export_format": "zipkin",
+            "batching": { "enabled": true },
+            "bodies_as_logs": true
+        }));
+        assert!(!config.batching.enabled);
+        assert!(!config.bodies_as_logs);
+        assert_eq!(
+            config.conflicts,
+            vec![ConfigConflict::BatchingWithZipkin, ConfigConflict::BodiesAsLogsWithZipkin]
+        );
+    }
+
+    #[test]
+    fn test_conflicts_reset_on_reparse() {
+        let mut config = parsed_config(json!({ "propagation_only": true, "bodies_as_logs": true }));
+        assert!(config.parse_from_json(b"{}"));
+        assert!(config.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_config_parse_propagation_format() {
+        let mut config = Config::default();
+        assert_eq!(config.propagation_format, PropagationFormat::W3c);
+
+        let config_str = serde_json::to_string(&json!({ "propagation_format": "B3Multi" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.propagation_format, PropagationFormat::B3Multi);
+
+        let config_str = serde_json::to_string(&json!({ "propagation_format": "jaeger" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.propagation_format, PropagationFormat::W3c);
+    }
+
+    #[test]
+    fn test_config_parse_low_cardinality_headers() {
+        let mut config = Config::default();
+        assert!(config.low_cardinality_headers.is_empty());
+        assert_eq!(config.low_cardinality_buckets, DEFAULT_LOW_CARDINALITY_BUCKETS);
+
+        let config_str = serde_json::to_string(&json!({
+            "low_cardinality_headers": ["X-User-Id"],
+            "low_cardinality_buckets": 0
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.low_cardinality_headers, vec!["x-user-id"]);
+        assert_eq!(config.low_cardinality_buckets, 0);
+    }
+
+    #[test]
+    fn test_config_parse_max_retries() {
+        let mut config = Config::default();
+        assert_eq!(config.max_retries, DEFAULT_MAX_RETRIES);
+
+        let config_str = serde_json::to_string(&json!({ "max_retries": 0 })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.max_retries, 0);
+    }
+
+    #[test]
+    fn test_config_parse_grpc_message_spans() {
+        let mut config = Config::default();
+        assert!(!config.grpc_message_spans);
+
+        let config_str = serde_json::to_string(&json!({ "grpc_message_spans": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.grpc_message_spans);
+    }
+
+    #[test]
+    fn test_config_parse_batching() {
+        let mut config = Config::default();
+        assert!(!config.batching.enabled);
+
+        let config_str = serde_json::to_string(&json!({
+            "batching": { "enabled": true, "flushIntervalMs": 250, "maxBatchSpans": 20, "maxBatchBytes": 0 }
+        }))
+        .unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.batching.enabled);
+        assert_eq!(config.batching.flush_interval_ms, 250);
+        assert_eq!(config.batching.max_batch_spans, 20);
+        assert_eq!(config.batching.max_batch_bytes, BatchingConfig::default().max_batch_bytes);
+    }
+
+    #[test]
+    fn test_config_parse_duration_unit() {
+        let mut config = Config::default();
+        assert_eq!(config.duration_unit, DurationUnit::Milliseconds);
+
+        let config_str = serde_json::to_string(&json!({ "duration_unit": "ns" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.duration_unit, DurationUnit::Nanoseconds);
+
+        let config_str = serde_json::to_string(&json!({ "duration_unit": "minutes" })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert_eq!(config.duration_unit, DurationUnit::Milliseconds);
+    }
+
+    #[test]
+    fn test_config_parse_drop_mismatched_bodies() {
+        let mut config = Config::default();
+        assert!(!config.drop_mismatched_bodies);
+
+        let config_str = serde_json::to_string(&json!({ "drop_mismatched_bodies": true })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        assert!(config.drop_mismatched_bodies);
     }
-}
\ No newline at end of file
+}
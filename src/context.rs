@@ -1,13 +1,33 @@
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
+use prost::Message;
 use std::collections::HashMap;
 
-use crate::config::Config;
-use crate::otel::{SpanBuilder, serialize_traces_data};
-use crate::headers::{detect_service_name, build_new_tracestate};
+use crate::compression::{BodyDecoding, ContentEncoding};
+use crate::config::{Config, ExportFormat, InflightOverflow, PropagationFormat};
+use crate::otel::{BodySize, GrpcMessage, LogsData, SpanBuilder, TracesData, serialize_logs_data, serialize_traces_data};
+use crate::headers::{detect_service_name, service_name_for_host, build_new_tracestate};
 use crate::http_helpers::{get_backend_authority, get_backend_cluster_name};
+use crate::metrics::ExportError;
+use crate::span_buffer::BufferedSpan;
 use crate::trace_context::extract_and_propagate_trace_context;
-use crate::traffic::TrafficAnalyzer;
+use crate::traffic::{NodeMetadata, TrafficAnalyzer};
+
+/// Result of the injection lookup made before the request went upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectLookupOutcome {
+    Hit,
+    Miss,
+}
+
+impl InjectLookupOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InjectLookupOutcome::Hit => "hit",
+            InjectLookupOutcome::Miss => "miss",
+        }
+    }
+}
 
 pub struct SpHttpContext {
     pub(crate) _context_id: u32,
@@ -15,19 +35,46 @@ pub struct SpHttpContext {
     pub(crate) request_body: Vec<u8>,
     pub(crate) response_headers: HashMap<String, String>,
     pub(crate) response_body: Vec<u8>,
+    pub(crate) request_body_len: usize,  // Bytes seen, including any beyond max_body_bytes
+    pub(crate) response_body_len: usize,
+    pub(crate) request_body_decoding: Option<BodyDecoding>,  // Set once a compressed body is inflated
+    pub(crate) response_body_decoding: Option<BodyDecoding>,
+    pub(crate) bodies_decoded: bool,
+    pub(crate) response_trailers: HashMap<String, String>,  // gRPC carries its status here
+    pub(crate) request_chunk_times: Vec<(usize, u64)>,  // (buffered length, time) per body chunk, for grpc_message_spans
+    pub(crate) response_chunk_times: Vec<(usize, u64)>,
     pub(crate) span_builder: SpanBuilder,
     pub(crate) pending_inject_call_token: Option<u32>,
     pub(crate) pending_save_call_token: Option<u32>,
+    pub(crate) pending_shadow_call_token: Option<u32>,
+    pub(crate) pending_logs_call_token: Option<u32>,
     pub(crate) injected: bool,
     pub(crate) config: Config,
     pub(crate) url_host: Option<String>,
+    pub(crate) url_scheme: Option<String>,
     pub(crate) url_path: Option<String>,
     pub(crate) is_from_ingressgateway: bool,  // Cache to avoid calling get_request_header during response phase
+    pub(crate) node_metadata: NodeMetadata,  // Read once by the root context
     pub(crate) request_start_time: Option<u64>,  // Store request start time in nanoseconds
+    pub(crate) injection_denied: bool,  // Path denylisted or host outside propagate_hosts, skip header injection
+    pub(crate) inject_lookup_outcome: Option<InjectLookupOutcome>,  // Set once the injection lookup responds
+    pub(crate) span_dispatched: bool,  // Extraction save was attempted for this request
+    pub(crate) save_attempts: u32,  // Dispatches of the current save, including retries
+    pub(crate) retry_payload: Option<BufferedSpan>,  // In-flight save kept while retries remain
+    pub(crate) stream_ended: bool,  // on_done ran; the context lives on until its calls settle
+    pub(crate) capture_request_body: bool,  // Request method is in body_capture_methods
+    pub(crate) body_capture_denied: bool,  // Path matched no_body_paths, skip body buffering
+    pub(crate) holds_dispatch_slot: bool,  // Counted in the shared in-flight dispatch total while any call is pending
+    pub(crate) request_in_progress: bool,  // Request headers arrived and the body is still streaming
+    pub(crate) early_response: bool,  // Response started before the request body completed
+    pub(crate) response_complete: bool,  // Response ended; the span waits for the request to settle
 }
 
+/// Response header exposing the server span ID for debugging
+const SPAN_ID_RESPONSE_HEADER: &str = "x-sp-span-id";
+
 impl SpHttpContext {
-   pub fn new(context_id: u32, config: Config) -> Self {
+   pub fn new(context_id: u32, config: Config, node_metadata: NodeMetadata) -> Self {
         let mut span_builder = SpanBuilder::new();
         span_builder = span_builder
             .with_service_name(config.service_name.clone())
@@ -36,7 +83,30 @@ impl SpHttpContext {
                     .traffic_direction
                     .clone()
                     .unwrap_or_else(|| "auto".to_string()),
+            )
+            .with_scope_attributes(&config.scope_attributes)
+            .with_skip_response_headers(config.skip_response_headers.clone())
+            .with_capture_cookies(config.capture_cookies)
+            .with_dual_emit(config.dual_emit)
+            .with_resource_attributes(&node_metadata.attributes)
+            .with_auto_generate_session_id(config.auto_generate_session_id)
+            .with_keep_authorization_scheme(config.keep_authorization_scheme)
+            .with_numeric_header_attributes(config.numeric_header_attributes.clone())
+            .with_low_cardinality_headers(config.low_cardinality_headers.clone(), config.low_cardinality_buckets)
+            .with_text_content_types(config.text_content_types.clone())
+            .with_custom_trace_header(
+                config.custom_traceparent_header.clone(),
+                config.custom_traceparent_authoritative,
+            )
+            .with_fixed_parent_traceparent(config.fixed_parent_traceparent.clone())
+            .with_propagation_format(config.propagation_format)
+            .with_duration_unit(config.duration_unit);
+        if !config.config_hash.is_empty() {
+            span_builder.add_attribute(
+                "sp.config.hash",
+                crate::otel::any_value::Value::StringValue(config.config_hash.clone()),
             );
+        }
         Self {
             _context_id: context_id,
             config,
@@ -44,14 +114,38 @@ impl SpHttpContext {
             request_body: Vec::new(),
             response_headers: HashMap::new(),
             response_body: Vec::new(),
+            request_body_len: 0,
+            response_body_len: 0,
+            request_body_decoding: None,
+            response_body_decoding: None,
+            bodies_decoded: false,
+            response_trailers: HashMap::new(),
+            request_chunk_times: Vec::new(),
+            response_chunk_times: Vec::new(),
             span_builder,
             pending_inject_call_token: None,
             pending_save_call_token: None,
+            pending_shadow_call_token: None,
+            pending_logs_call_token: None,
             injected: false,
             url_host: None,
+            url_scheme: None,
             url_path: None,
             is_from_ingressgateway: false,  // Initialize to false, will be set during request processing
+            node_metadata,
             request_start_time: None,  // Initialize to None, will be set when request starts
+            injection_denied: false,
+            inject_lookup_outcome: None,
+            span_dispatched: false,
+            save_attempts: 0,
+            retry_payload: None,
+            stream_ended: false,
+            capture_request_body: true,
+            body_capture_denied: false,
+            holds_dispatch_slot: false,
+            request_in_progress: false,
+            early_response: false,
+            response_complete: false,
         }
     }
     // Dispatch injection HTTP call (disabled)
@@ -74,6 +168,61 @@ impl SpHttpContext {
             }
         }
 
+        // Pre-rewrite path, read before its header is masked below; only kept
+        // when Envoy actually rewrote the path
+        let original_path = crate::properties::get_original_path(self)
+            .filter(|original| self.request_headers.get(":path") != Some(original));
+
+        // Cap path+query length to keep attributes and span names bounded
+        if let (Some(max_len), Some(path)) = (self.config.max_url_length, self.url_path.as_deref()) {
+            if let Some(truncated) = crate::http_helpers::truncate_url(path, max_len) {
+                crate::sp_debug!("URL length {} exceeds max_url_length {}, truncating", path.len(), max_len);
+                self.url_path = Some(truncated);
+                self.span_builder.add_attribute("url.truncated", crate::otel::any_value::Value::BoolValue(true));
+            }
+        }
+
+        // Token-like path segments (reset links, signed URLs) are never captured as-is,
+        // including in the pre-rewrite path Envoy passes in x-envoy-original-path
+        if self.config.mask_path_secrets {
+            if let Some(path) = self.url_path.as_deref() {
+                let masked = crate::masking::mask_path_secrets(path, &self.config.masking);
+                if masked != path {
+                    crate::sp_debug!("Masked secret-looking path segments");
+                    self.url_path = Some(masked);
+                }
+            }
+            if let Some(original_path) = self.request_headers.get_mut("x-envoy-original-path") {
+                *original_path = crate::masking::mask_path_secrets(original_path, &self.config.masking);
+            }
+        }
+
+        // http.route with array indices and numeric IDs collapsed to keep cardinality low
+        if self.config.collapse_numeric_segments {
+            if let Some(path) = self.url_path.as_deref() {
+                let route = crate::http_helpers::collapse_numeric_segments(path);
+                self.span_builder.add_attribute("http.route", crate::otel::any_value::Value::StringValue(route));
+            }
+        }
+
+        // url.path.original when Envoy rewrote the path, capped like url.path
+        if let Some(mut original_path) = original_path {
+            if let Some(truncated) = self
+                .config
+                .max_url_length
+                .and_then(|max_len| crate::http_helpers::truncate_url(&original_path, max_len))
+            {
+                original_path = truncated;
+            }
+            if self.config.mask_path_secrets {
+                original_path = crate::masking::mask_path_secrets(&original_path, &self.config.masking);
+            }
+            self.span_builder.add_attribute(
+                "url.path.original",
+                crate::otel::any_value::Value::StringValue(original_path),
+            );
+        }
+
         // url.host from :authority or host header
         let authority_or_host = self
             .request_headers
@@ -87,10 +236,39 @@ impl SpHttpContext {
                 self.url_host = Some(authority_value);
             }
         }
+
+        // url.scheme from :scheme, request.scheme, or the listener port
+        self.url_scheme = crate::properties::get_request_scheme(self);
+        if let Some(scheme) = self.url_scheme.clone() {
+            self.span_builder.add_attribute("url.scheme", crate::otel::any_value::Value::StringValue(scheme));
+        }
+
+        if let Some(url_full) = self.build_url_full() {
+            self.span_builder.add_attribute("url.full", crate::otel::any_value::Value::StringValue(url_full));
+        }
+    }
+
+    /// `{scheme}://{host}{path}{?query}` without credentials or sensitive query values
+    fn build_url_full(&self) -> Option<String> {
+        let (Some(scheme), Some(host), Some(path)) = (&self.url_scheme, &self.url_host, &self.url_path) else {
+            return None;
+        };
+        let url_full = crate::http_helpers::build_url_full(scheme, host, path);
+        let url_full = crate::masking::mask_url_query(&url_full, &self.config.masking);
+        match self.config.max_url_length {
+            Some(max_len) => Some(crate::http_helpers::truncate_url(&url_full, max_len).unwrap_or(url_full)),
+            None => Some(url_full),
+        }
     }
 
     fn dispatch_async_extraction_save(&mut self) {
         crate::sp_debug!("Starting async extraction save (host={:?}, path={:?})", self.url_host, self.url_path);
+        self.span_dispatched = true;
+
+        if self.config.propagation_only {
+            crate::sp_debug!("Propagation-only mode, skipping trace upload");
+            return;
+        }
 
         // Early skip: Next.js RSC / prefetch requests
         if self.is_rsc_or_prefetch() {
@@ -104,6 +282,72 @@ impl SpHttpContext {
             return;
         }
 
+        if self.config.require_body_for_capture && !self.has_captured_body() {
+            crate::sp_debug!("No request or response body captured, skipping trace upload");
+            return;
+        }
+
+        if !self.is_outbound_port_traced() {
+            crate::sp_debug!("Outbound port not in outbound_ports, skipping trace upload");
+            return;
+        }
+
+        if self.is_infra_port_traffic() {
+            crate::sp_debug!("Istio infrastructure port traffic, skipping trace upload");
+            return;
+        }
+
+        // Exempted requests produce no span, or only a marker span when configured
+        let exemption = self
+            .matching_exemption_rule(&self.config, &self.request_headers)
+            .map(|rule| rule.describe());
+        if let Some(rule) = exemption {
+            if self.config.emit_exemption_marker {
+                crate::sp_debug!("Request exempted, emitting marker span");
+                let traces_data = self.span_builder.create_exemption_marker_span(
+                    self.url_host.as_deref(),
+                    self.url_path.as_deref(),
+                    &rule,
+                );
+                self.export_traces_data(&traces_data);
+            } else {
+                crate::sp_debug!("Request exempted, skipping trace upload");
+            }
+            return;
+        }
+
+        // A truthy force-capture header bypasses sampling
+        let forced = self.is_forced_capture();
+        if forced {
+            crate::sp_debug!("Force capture header present, bypassing sampling");
+            self.span_builder.add_attribute("sp.forced", crate::otel::any_value::Value::BoolValue(true));
+        }
+
+        // A session's decision covers all of its requests, so sampled-in
+        // sessions are traced in full and sampled-out ones not at all
+        let session_sampled = self.session_sampling_decision();
+        if !forced && session_sampled == Some(false) {
+            crate::sp_debug!("Session not sampled, skipping trace upload");
+            return;
+        }
+        let sampled_by_session = session_sampled == Some(true);
+
+        // Respect an upstream decision not to sample
+        if !forced && !sampled_by_session && !self.span_builder.sampled() {
+            crate::sp_debug!("Incoming trace context is not sampled, skipping trace upload");
+            return;
+        }
+
+        if !forced && !sampled_by_session && !crate::sampling::should_sample(
+            self.config.sampling_rate,
+            self.config.sampling_key,
+            self.span_builder.get_trace_id(),
+            self.span_builder.get_session_id(),
+        ) {
+            crate::sp_debug!("Request not sampled, skipping trace upload");
+            return;
+        }
+
         // Check if session_id was parsed
         let has_session_id = self.span_builder.has_session_id();
         crate::sp_debug!("Session ID present: {}", has_session_id);
@@ -111,63 +355,235 @@ impl SpHttpContext {
         // If no session_id found, force trace upload for isolation
         if !has_session_id {
             crate::sp_debug!("No session ID found, forcing trace upload for isolation");
-        } else {
+        } else if !forced && !self.should_collect_by_rules(&self.config, &self.request_headers) {
             // Check collection rules
-            if !self.should_collect_by_rules(&self.config, &self.request_headers) {
-                crate::sp_debug!("Data extraction skipped based on collection rules");
-            }
+            crate::sp_debug!("Data extraction skipped based on collection rules");
+            return;
         }
 
         crate::sp_debug!("Storing agent data asynchronously (backend={})", self.config.sp_backend_url);
 
-        // Create extract span using references to avoid cloning
-        let traces_data = self.span_builder.create_extract_span(
-            &self.request_headers,
-            &self.request_body,
-            &self.response_headers,
-            &self.response_body,
-            self.url_host.as_deref(),
-            self.url_path.as_deref(),
-            self.request_start_time,  // Pass the stored request start time
-        );
+        let traces_data = self.build_extract_traces_data();
+        if let Some(summary) = self.payload_size_summary(&traces_data) {
+            crate::sp_debug!("{}", summary);
+        }
+        if !self.export_traces_data(&traces_data) {
+            return;
+        }
+
+        if self.config.bodies_as_logs {
+            self.dispatch_body_logs();
+        }
+    }
+
+    /// `x-sp-span-id` response header carrying the server span ID, when expose_span_id_header is set
+    fn span_id_response_header(&self) -> Option<(&'static str, String)> {
+        if !self.config.expose_span_id_header {
+            return None;
+        }
+        Some((SPAN_ID_RESPONSE_HEADER, self.span_builder.get_current_span_id_hex()))
+    }
+
+    /// Whether any body was buffered on a captured side; bodies of methods outside
+    /// body_capture_methods are never buffered
+    fn has_captured_body(&self) -> bool {
+        (self.config.capture_sides.captures_request() && !self.request_body.is_empty())
+            || (self.config.capture_sides.captures_response() && !self.response_body.is_empty())
+    }
+
+    /// Outbound requests are only traced to ports in outbound_ports, when configured
+    fn is_outbound_port_traced(&self) -> bool {
+        if self.config.outbound_ports.is_empty() || self.span_builder.get_traffic_direction() != "outbound" {
+            return true;
+        }
+        crate::traffic::is_outbound_port_allowed(&self.config, self.outbound_port())
+    }
+
+    /// Whether the :authority or source address port is in infra_ports
+    fn is_infra_port_traffic(&self) -> bool {
+        if self.config.infra_ports.is_empty() {
+            return false;
+        }
+        let authority_port = self
+            .request_headers
+            .get(":authority")
+            .and_then(|authority| crate::traffic::authority_port(authority));
+        let source_port = crate::properties::get_string_property(self, vec!["source", "address"])
+            .and_then(|address| crate::traffic::authority_port(&address));
+        [authority_port, source_port]
+            .into_iter()
+            .flatten()
+            .any(|port| crate::traffic::is_infra_port(&self.config, port))
+    }
+
+    /// Upstream port from the selected upstream address, then :authority, then the scheme default
+    fn outbound_port(&self) -> Option<u16> {
+        crate::properties::get_string_property(self, vec!["upstream", "address"])
+            .and_then(|address| crate::traffic::authority_port(&address))
+            .or_else(|| self.url_host.as_deref().and_then(crate::traffic::authority_port))
+            .or(match self.url_scheme.as_deref() {
+                Some("https") => Some(443),
+                Some("http") => Some(80),
+                _ => None,
+            })
+    }
+
+    /// Session sampling decision, or None when session sampling is off or the
+    /// request has no session and falls back to per-request sampling
+    fn session_sampling_decision(&self) -> Option<bool> {
+        let rate = self.config.session_sampling_rate?;
+        let session_id = self.span_builder.get_session_id();
+        if session_id.is_empty() {
+            return None;
+        }
+        Some(crate::sampling::sample_session(rate, session_id))
+    }
+
+    fn is_forced_capture(&self) -> bool {
+        let Some(header) = self.config.force_capture_header.as_deref() else {
+            return false;
+        };
+        self.request_headers
+            .get(header)
+            .is_some_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+    }
+
+    /// Per-request payload sizes for collector capacity planning, when log_payload_size is set
+    fn payload_size_summary(&self, traces_data: &TracesData) -> Option<String> {
+        if !self.config.log_payload_size {
+            return None;
+        }
+        let attribute_count: usize = traces_data
+            .resource_spans
+            .iter()
+            .flat_map(|resource_spans| &resource_spans.scope_spans)
+            .flat_map(|scope_spans| &scope_spans.spans)
+            .map(|span| span.attributes.len())
+            .sum();
+        Some(format!(
+            "Payload size: {} bytes protobuf, {} attributes, request body {} bytes, response body {} bytes",
+            traces_data.encoded_len(),
+            attribute_count,
+            self.request_body.len(),
+            self.response_body.len()
+        ))
+    }
 
-        // Serialize to protobuf
-        let otel_data = match serialize_traces_data(&traces_data) {
+    /// Serialize and dispatch traces data to the backend (and shadow backend)
+    fn export_traces_data(&mut self, traces_data: &TracesData) -> bool {
+        // Serialize to protobuf, or Zipkin JSON when configured
+        let (path, content_type, serialized) = match self.config.export_format {
+            ExportFormat::Otlp => (
+                "/v1/traces".to_string(),
+                "application/x-protobuf",
+                serialize_traces_data(traces_data).map_err(|e| e.to_string()),
+            ),
+            ExportFormat::Zipkin => (
+                self.config.zipkin_path.clone(),
+                "application/json",
+                crate::zipkin::serialize_zipkin(traces_data).map_err(|e| e.to_string()),
+            ),
+        };
+        let otel_data = match serialized {
             Ok(bytes) => bytes,
             Err(e) => {
                 crate::sp_error!("Serialization error: {}", e);
-                return;
+                return false;
             }
         };
 
-        // Get backend authority from configured URL
-        let authority = get_backend_authority(&self.config.sp_backend_url);
+        // With batching the span waits in shared data for the next flush
+        if self.config.batching.enabled && self.config.export_format == ExportFormat::Otlp {
+            self.add_to_batch(&otel_data);
+        } else if !self.acquire_dispatch_slot() {
+            // Bound the number of saves awaiting a backend response across the VM
+            match self.config.inflight_overflow {
+                InflightOverflow::Drop => {
+                    crate::sp_warn!("Max in-flight dispatches reached, dropping span");
+                }
+                InflightOverflow::Queue => {
+                    crate::sp_debug!("Max in-flight dispatches reached, buffering span");
+                    let span = BufferedSpan {
+                        path,
+                        content_type: content_type.to_string(),
+                        payload: otel_data,
+                    };
+                    let dropped = crate::span_buffer::enqueue(self, span, self.config.max_buffered_spans as usize);
+                    if dropped > 0 {
+                        crate::sp_warn!("Span buffer full, dropped {} oldest span(s)", dropped);
+                        crate::metrics::record_buffered_spans_dropped(dropped as u32);
+                    }
+                }
+            }
+            return false;
+        } else {
+            self.dispatch_traces_payload(&path, content_type, &otel_data);
+        }
 
-        // Prepare HTTP headers for the async save call
-        let content_length = otel_data.len().to_string();
-        let http_headers = vec![
-            (":method", "POST"),
-            (":path", "/v1/traces"),
-            (":authority", &authority),
-            ("content-type", "application/x-protobuf"),
-            ("content-length", &content_length),
-            ("x-public-key", &self.config.public_key),
-        ];
+        // Mirror the payload to the shadow backend; its failures never affect the primary
+        if let Some(shadow_url) = self.config.shadow_backend_url.clone() {
+            if !self.acquire_dispatch_slot() {
+                crate::sp_debug!("Max in-flight dispatches reached, skipping shadow save");
+                return true;
+            }
+            match self.dispatch_post_to(&shadow_url, &path, content_type, &otel_data) {
+                Ok(call_id) => {
+                    crate::sp_debug!("Shadow: HTTP call dispatched (call_id={})", call_id);
+                    self.pending_shadow_call_token = Some(call_id);
+                }
+                Err(status) => {
+                    crate::sp_warn!("Shadow: Failed to dispatch HTTP call, status: {:?}", status);
+                    self.release_dispatch_slot();
+                }
+            }
+        }
 
-        // Fire and forget async call to /v1/traces endpoint for storage
-        let cluster_name = get_backend_cluster_name(&self.config.sp_backend_url);
-        let timeout = std::time::Duration::from_secs(5);
+        true
+    }
 
-        match self.dispatch_http_call(
-            &cluster_name,
-            http_headers,
-            Some(&otel_data),
-            vec![],
-            timeout,
-        ) {
+    /// Queue a serialized span for the batch flush, flushing here once the batch
+    /// reaches max_batch_spans or max_batch_bytes
+    fn add_to_batch(&mut self, payload: &[u8]) {
+        let Some(batch) = crate::batch::append(self, payload) else {
+            return;
+        };
+        let batching = &self.config.batching;
+        if batch.spans < batching.max_batch_spans && batch.payload.len() < batching.max_batch_bytes {
+            return;
+        }
+        if self.pending_save_call_token.is_some() || !self.acquire_dispatch_slot() {
+            crate::sp_debug!("Batch threshold reached without a free dispatch slot, leaving it for the flush timer");
+            return;
+        }
+        match crate::batch::take(self) {
+            Some(batch) => {
+                crate::sp_debug!("Batch threshold reached, flushing {} span(s)", batch.spans);
+                self.dispatch_traces_payload("/v1/traces", "application/x-protobuf", &batch.payload);
+            }
+            None => self.release_dispatch_slot(),
+        }
+    }
+
+    /// Send a serialized span to the backend; the caller holds a dispatch slot
+    fn dispatch_traces_payload(&mut self, path: &str, content_type: &str, payload: &[u8]) {
+        self.save_attempts = 0;
+        self.send_save(BufferedSpan {
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            payload: payload.to_vec(),
+        });
+    }
+
+    /// Dispatch a save. A local dispatch error is not retried, as an immediate
+    /// re-dispatch would fail the same way; only 429/5xx responses are.
+    fn send_save(&mut self, save: BufferedSpan) {
+        self.save_attempts += 1;
+        match self.dispatch_backend_post(&save.path, &save.content_type, &save.payload) {
             Ok(call_id) => {
                 crate::sp_info!("Extraction: HTTP call dispatched successfully (call_id={})", call_id);
                 self.pending_save_call_token = Some(call_id);
+                // Keep the payload only while a failed response could still be retried
+                self.retry_payload = self.has_save_retries_left().then_some(save);
             }
             Err(status) => {
                 let error_msg = format!(
@@ -175,20 +591,374 @@ impl SpHttpContext {
                     status
                 );
                 crate::sp_error!("{}", error_msg);
+                crate::metrics::record_export_error(ExportError::DispatchFailed);
+                self.give_up_save();
+            }
+        }
+    }
+
+    fn has_save_retries_left(&self) -> bool {
+        self.save_attempts <= self.config.max_retries
+    }
+
+    /// Settle the pending save: free its slot on success, retry a 429/5xx while
+    /// attempts remain, or give up
+    fn handle_save_response(&mut self, status_code: u32) {
+        crate::sp_debug!("Processing async save response (status_code={})", status_code);
+        self.pending_save_call_token = None;
+
+        match ExportError::from_status(status_code) {
+            None => {
+                crate::sp_info!("Async save completed (status: {})", status_code);
+                self.retry_payload = None;
+                self.release_dispatch_slot();
+            }
+            Some(error) => {
+                crate::sp_error!("Async save failed with status: {}", status_code);
+                crate::metrics::record_export_error(error);
+                // The retry keeps the dispatch slot
+                if let Some(mut save) = self.retry_payload.take().filter(|_| is_retryable_status(status_code)) {
+                    crate::sp_warn!("Retrying span export (attempt {})", self.save_attempts + 1);
+                    annotate_export_error(&mut save, error);
+                    self.send_save(save);
+                    return;
+                }
+                self.give_up_save();
+            }
+        }
+        self.flush_buffered_spans();
+    }
+
+    /// Backend calls still awaiting a response
+    fn has_pending_calls(&self) -> bool {
+        self.pending_save_call_token.is_some()
+            || self.pending_shadow_call_token.is_some()
+            || self.pending_logs_call_token.is_some()
+    }
+
+    /// Tell the host a context that outlived its stream is finished once none
+    /// of its calls are pending any more
+    fn finish_if_stream_ended(&self) {
+        if self.stream_ended && !self.has_pending_calls() {
+            crate::sp_debug!("Last backend call settled, finishing the context");
+            self.done();
+        }
+    }
+
+    /// Drop the current save and free its dispatch slot
+    fn give_up_save(&mut self) {
+        if self.save_attempts > 1 {
+            crate::sp_error!("Giving up on span export after {} attempts", self.save_attempts);
+        }
+        self.retry_payload = None;
+        self.release_dispatch_slot();
+    }
+
+    /// Take an in-flight dispatch slot; always succeeds without max_inflight_dispatches.
+    /// A context counts once however many of its calls are pending.
+    fn acquire_dispatch_slot(&mut self) -> bool {
+        if self.config.max_inflight_dispatches == 0 || self.holds_dispatch_slot {
+            return true;
+        }
+        self.holds_dispatch_slot = crate::inflight::try_acquire(self, self.config.max_inflight_dispatches);
+        self.holds_dispatch_slot
+    }
+
+    /// Give the slot back once none of this context's calls are awaiting a response
+    fn release_dispatch_slot(&mut self) {
+        if self.holds_dispatch_slot && !self.has_pending_calls() {
+            crate::inflight::release(self);
+            self.holds_dispatch_slot = false;
+        }
+    }
+
+    /// Send the buffered spans in one export request if this context is idle
+    /// and a slot is free
+    fn flush_buffered_spans(&mut self) {
+        if self.config.inflight_overflow != InflightOverflow::Queue
+            || self.pending_save_call_token.is_some()
+            || !self.acquire_dispatch_slot()
+        {
+            return;
+        }
+        match crate::span_buffer::dequeue_batch(self) {
+            Some(span) => {
+                crate::sp_debug!("Dispatching buffered spans");
+                self.dispatch_traces_payload(&span.path, &span.content_type, &span.payload);
+            }
+            None => self.release_dispatch_slot(),
+        }
+    }
+
+    /// Send the captured bodies to the /v1/logs endpoint; failures are only logged
+    fn dispatch_body_logs(&mut self) {
+        let logs_data = self.build_body_logs_data();
+        if logs_data.resource_logs.iter().all(|rl| rl.scope_logs.iter().all(|sl| sl.log_records.is_empty())) {
+            return;
+        }
+
+        let otel_data = match serialize_logs_data(&logs_data) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                crate::sp_error!("Log serialization error: {}", e);
+                return;
+            }
+        };
+
+        if !self.acquire_dispatch_slot() {
+            crate::sp_warn!("Max in-flight dispatches reached, dropping body logs");
+            return;
+        }
+        match self.dispatch_backend_post("/v1/logs", "application/x-protobuf", &otel_data) {
+            Ok(call_id) => {
+                crate::sp_debug!("Body logs dispatched (call_id={})", call_id);
+                self.pending_logs_call_token = Some(call_id);
+            }
+            Err(status) => {
+                crate::sp_error!("Failed to dispatch body logs, status: {:?}", status);
+                self.release_dispatch_slot();
             }
         }
     }
 
+    /// POST an export payload to the configured backend
+    fn dispatch_backend_post(&self, path: &str, content_type: &str, payload: &[u8]) -> Result<u32, Status> {
+        self.dispatch_post_to(&self.config.sp_backend_url, path, content_type, payload)
+    }
+
+    fn dispatch_post_to(&self, backend_url: &str, path: &str, content_type: &str, payload: &[u8]) -> Result<u32, Status> {
+        dispatch_post(self, &self.config, backend_url, path, content_type, payload)
+    }
+
+    fn apply_trace_context_injection(&mut self) {
+        self.injection_denied = crate::traffic::is_injection_denied(&self.config, self.url_path.as_deref());
+        if self.injection_denied {
+            crate::sp_debug!("Path {:?} is in injection denylist, skipping header injection", self.url_path);
+            return;
+        }
+        let traffic_direction = self.span_builder.get_traffic_direction();
+        if !crate::traffic::is_propagation_allowed(&self.config, traffic_direction, self.url_host.as_deref()) {
+            crate::sp_debug!("Host {:?} is not in propagate_hosts, skipping header injection", self.url_host);
+            self.injection_denied = true;
+            return;
+        }
+        self.inject_trace_context_headers();
+    }
+
+    /// Build the extract span for the captured exchange
+    fn build_extract_traces_data(&mut self) -> TracesData {
+        self.decode_captured_bodies();
+
+        // Leave out the request side when only the response is captured
+        let no_request_headers = HashMap::new();
+        let (request_headers, request_body): (&HashMap<String, String>, &[u8]) =
+            if self.config.capture_sides.captures_request() {
+                (&self.request_headers, &self.request_body)
+            } else {
+                (&no_request_headers, &[])
+            };
+
+        // Sizes are the real lengths even when buffering stopped at max_body_bytes
+        let request_body_len = if self.config.capture_sides.captures_request() {
+            self.request_body_len.max(request_body.len())
+        } else {
+            0
+        };
+        self.span_builder.set_request_body_size(captured_body_size(
+            request_body_len,
+            request_body.len(),
+            self.request_body_decoding,
+        ));
+        self.span_builder.set_response_body_size(captured_body_size(
+            self.response_body_len.max(self.response_body.len()),
+            self.response_body.len(),
+            self.response_body_decoding,
+        ));
+
+        let (request_mismatch, response_mismatch) = self.body_length_mismatches();
+        if request_mismatch || response_mismatch {
+            crate::sp_debug!(
+                "Body length differs from content-length (request: {}, response: {})",
+                request_mismatch, response_mismatch
+            );
+            self.span_builder.set_body_length_mismatch();
+        }
+
+        let request_dropped = request_mismatch && self.config.drop_mismatched_bodies;
+        if self.config.hash_bodies && !request_body.is_empty() && !request_dropped {
+            self.span_builder.set_request_body_hash(crate::otel::body_hash(request_body));
+        }
+
+        if let Some(outcome) = self.inject_lookup_outcome {
+            self.span_builder.add_attribute(
+                "sp.inject.lookup",
+                crate::otel::any_value::Value::StringValue(outcome.as_str().to_string()),
+            );
+        }
+
+        // Streaming gRPC carries several length-prefixed messages per body
+        if crate::grpc::is_grpc(request_headers) && !self.body_capture_denied {
+            self.span_builder.set_grpc_message_counts(
+                crate::grpc::count_messages(request_body),
+                crate::grpc::count_messages(&self.response_body),
+            );
+        }
+
+        // gRPC outcome lives in the trailers, or the headers of a trailers-only response
+        if let Some(code) = crate::grpc::status_code(&self.response_trailers)
+            .or_else(|| crate::grpc::status_code(&self.response_headers))
+        {
+            let message = self
+                .response_trailers
+                .get("grpc-message")
+                .or_else(|| self.response_headers.get("grpc-message"))
+                .cloned();
+            self.span_builder.set_grpc_status(code, message);
+        }
+
+        // Bodies exported as log records keep only their sizes on the span
+        let (request_body, response_body): (&[u8], &[u8]) = if self.config.bodies_as_logs {
+            (&[], &[])
+        } else {
+            (
+                self.capturable_body(request_body, request_mismatch),
+                self.capturable_body(&self.response_body, response_mismatch),
+            )
+        };
+
+        // Create extract span using references to avoid cloning
+        let mut traces_data = self.span_builder.create_extract_span(
+            request_headers,
+            request_body,
+            &self.response_headers,
+            response_body,
+            self.url_host.as_deref(),
+            self.url_path.as_deref(),
+            self.span_start_time(),
+        );
+        if self.config.grpc_message_spans && crate::grpc::is_grpc(&self.request_headers) && !self.body_capture_denied {
+            let messages = self.grpc_messages();
+            self.span_builder.add_grpc_message_spans(&mut traces_data, self.url_path.as_deref(), &messages);
+        }
+        crate::masking::mask_traces_data(&mut traces_data, &self.config.masking);
+        traces_data
+    }
+
+    /// Messages of both bodies, timed by the body chunk that completed each one.
+    /// Outbound calls send the request messages; inbound calls receive them.
+    fn grpc_messages(&self) -> Vec<GrpcMessage> {
+        let outbound = self.span_builder.get_traffic_direction() == "outbound";
+        let now = crate::otel::get_current_timestamp_nanos();
+        [
+            (&self.request_body, &self.request_chunk_times, outbound),
+            (&self.response_body, &self.response_chunk_times, !outbound),
+        ]
+        .into_iter()
+        .flat_map(|(body, chunk_times, sent)| {
+            crate::grpc::frames(body).into_iter().enumerate().map(move |(index, frame)| GrpcMessage {
+                sent,
+                sequence: index + 1,
+                size: frame.len,
+                compressed: frame.compressed,
+                timestamp: crate::grpc::arrival_time(chunk_times, frame.end()).unwrap_or(now),
+            })
+        })
+        .collect()
+    }
+
+    /// Inflate gzip/deflate bodies in place so spans, hashes and masking see the
+    /// plain payload; the wire lengths stay in request_body_len/response_body_len
+    fn decode_captured_bodies(&mut self) {
+        if self.bodies_decoded {
+            return;
+        }
+        self.bodies_decoded = true;
+        self.request_body_len = self.request_body_len.max(self.request_body.len());
+        self.response_body_len = self.response_body_len.max(self.response_body.len());
+
+        if let Some((body, decoding)) = self.decompress_body(&self.request_body, &self.request_headers) {
+            self.request_body = body;
+            self.request_body_decoding = Some(decoding);
+        }
+        if let Some((body, decoding)) = self.decompress_body(&self.response_body, &self.response_headers) {
+            self.response_body = body;
+            self.response_body_decoding = Some(decoding);
+        }
+    }
+
+    fn decompress_body(&self, body: &[u8], headers: &HashMap<String, String>) -> Option<(Vec<u8>, BodyDecoding)> {
+        if body.is_empty() {
+            return None;
+        }
+        let encoding = ContentEncoding::from_header(headers.get("content-encoding")?)?;
+        let decompressed = crate::compression::decompress(body, encoding, self.config.max_body_bytes);
+        if decompressed.is_none() {
+            crate::sp_debug!("Failed to decompress {} body, keeping encoded bytes", encoding.as_str());
+        }
+        decompressed
+    }
+
+    /// Span start derived from Envoy's `request.duration` when it is known, else the
+    /// timestamp taken in on_http_request_headers
+    fn span_start_time(&self) -> Option<u64> {
+        span_start_time(
+            crate::otel::get_current_timestamp_nanos(),
+            crate::properties::get_request_duration(self),
+            self.request_start_time,
+        )
+    }
+
+    /// Whether the request and response bodies disagree with their declared content-length
+    fn body_length_mismatches(&self) -> (bool, bool) {
+        let request_mismatch = self.config.capture_sides.captures_request()
+            && body_length_mismatch(&self.request_headers, self.request_body_len);
+        (request_mismatch, body_length_mismatch(&self.response_headers, self.response_body_len))
+    }
+
+    /// Bodies below min_body_capture_bytes are left out, as are bodies that disagree with
+    /// content-length when drop_mismatched_bodies is set; their sizes are still recorded
+    fn capturable_body<'a>(&self, body: &'a [u8], length_mismatch: bool) -> &'a [u8] {
+        if body.len() < self.config.min_body_capture_bytes || (length_mismatch && self.config.drop_mismatched_bodies) {
+            &[]
+        } else {
+            body
+        }
+    }
+
+    /// Build log records carrying the captured bodies, linked to the extract span
+    fn build_body_logs_data(&self) -> LogsData {
+        let request_body: &[u8] = if self.config.capture_sides.captures_request() {
+            &self.request_body
+        } else {
+            &[]
+        };
+        let (request_mismatch, response_mismatch) = self.body_length_mismatches();
+        let mut logs_data = self.span_builder.create_body_logs(
+            &self.request_headers,
+            self.capturable_body(request_body, request_mismatch),
+            &self.response_headers,
+            self.capturable_body(&self.response_body, response_mismatch),
+        );
+        crate::masking::mask_logs_data(&mut logs_data, &self.config.masking);
+        logs_data
+    }
+
     fn inject_trace_context_headers(&mut self) {
 
         // Generate trace context
         let current_span_id_hex = self.span_builder.get_current_span_id_hex();
         let trace_id_hex = self.span_builder.get_trace_id_hex();
-        let traceparent_value = format!("00-{}-{}-01", trace_id_hex, current_span_id_hex);
+        let trace_flags_hex = self.span_builder.get_trace_flags_hex();
+        let traceparent_value = format!("00-{}-{}-{}", trace_id_hex, current_span_id_hex, trace_flags_hex);
 
         // Build new tracestate
         let session_id = self.span_builder.get_session_id().to_string();
-        let new_tracestate = build_new_tracestate(&self.request_headers, &traceparent_value, &session_id);
+        let new_tracestate = build_new_tracestate(
+            &self.request_headers,
+            &traceparent_value,
+            &session_id,
+            self.config.max_tracestate_entries,
+        );
 
         // Update headers
         self.remove_http_request_header("tracestate");
@@ -199,14 +969,27 @@ impl SpHttpContext {
             .iter()
             .any(|(k, _)| k.to_lowercase() == "traceparent");
 
-        if !has_traceparent {
+        if !has_traceparent && self.config.propagation_format == PropagationFormat::W3c {
             self.add_http_request_header("traceparent", &traceparent_value);
             self.request_headers.insert("traceparent".to_string(), traceparent_value.clone());
         }
 
+        // B3 headers always carry this span as the new parent
+        for (name, value) in self.span_builder.b3_headers() {
+            self.set_http_request_header(name, Some(&value));
+            self.request_headers.insert(name.to_string(), value);
+        }
+
         // Update local cache
         self.request_headers.insert("tracestate".to_string(), new_tracestate.clone());
 
+        // Carry the accepted baggage entries to the next hop
+        let baggage = crate::headers::format_baggage(self.span_builder.get_baggage());
+        if !baggage.is_empty() {
+            self.set_http_request_header("baggage", Some(&baggage));
+            self.request_headers.insert("baggage".to_string(), baggage);
+        }
+
         // Handle x-sp-num header
         let current_sp_num = self.request_headers
             .get("x-sp-num")
@@ -228,6 +1011,9 @@ impl SpHttpContext {
         );
 
         // Check response headers for traceparent
+        if self.injection_denied {
+            return;
+        }
         if let Some(traceparent) = self.response_headers.get("traceparent") {
             crate::sp_debug!("Found traceparent in response {}", traceparent);
             self.propagate_trace_context_to_response();
@@ -239,7 +1025,7 @@ impl SpHttpContext {
         let span_id = crate::otel::generate_span_id();
         let traceparent = self.span_builder.generate_traceparent(&span_id);
         crate::sp_debug!("Propagating traceparent to response {}", traceparent);
-        let _ = self.add_http_response_header("traceparent", &traceparent);
+        self.add_http_response_header("traceparent", &traceparent);
     }
 }
 
@@ -252,13 +1038,86 @@ impl crate::traffic::RequestHeadersAccess for SpHttpContext {
     fn get_request_header(&self, name: &str) -> Option<String> {
         // Prefer live headers from host to work before local cache is populated
         self.get_http_request_header(name)
+            .filter(|v| !v.is_empty())
             .or_else(|| self.request_headers.get(name).cloned())
     }
 }
 
-impl Context for SpHttpContext {
-    fn on_http_call_response(
-        &mut self,
+impl crate::inflight::SharedCounterStore for SpHttpContext {
+    fn load_counter(&self, key: &str) -> (u32, Option<u32>) {
+        let (bytes, cas) = self.get_shared_data(key);
+        (crate::inflight::decode_counter(bytes.as_deref()), cas)
+    }
+
+    fn store_counter(&self, key: &str, value: u32, cas: Option<u32>) -> bool {
+        match self.set_shared_data(key, Some(&value.to_le_bytes()), cas) {
+            Ok(()) => true,
+            Err(Status::CasMismatch) => false,
+            Err(status) => {
+                crate::sp_warn!("Failed to store shared counter {}: {:?}", key, status);
+                false
+            }
+        }
+    }
+}
+
+/// POST a payload to a backend from any context; shared by HTTP contexts and the
+/// root context's batch flush
+pub(crate) fn dispatch_post<C: Context + ?Sized>(
+    context: &C,
+    config: &Config,
+    backend_url: &str,
+    path: &str,
+    content_type: &str,
+    payload: &[u8],
+) -> Result<u32, Status> {
+    // Get backend authority from configured URL
+    let authority = get_backend_authority(backend_url);
+
+    // Prepare HTTP headers for the async save call
+    let content_length = payload.len().to_string();
+    let http_headers = vec![
+        (":method", config.traces_method.as_str()),
+        (":path", path),
+        (":authority", &authority),
+        ("content-type", content_type),
+        ("content-length", &content_length),
+        ("x-public-key", &config.public_key),
+    ];
+
+    // Fire and forget async call to the backend for storage
+    let cluster_name = get_backend_cluster_name(backend_url);
+    let timeout = std::time::Duration::from_secs(5);
+
+    context.dispatch_http_call(
+        &cluster_name,
+        http_headers,
+        Some(payload),
+        vec![],
+        timeout,
+    )
+}
+
+impl crate::span_buffer::SharedBufferStore for SpHttpContext {
+    fn load_buffer(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+        self.get_shared_data(key)
+    }
+
+    fn store_buffer(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+        match self.set_shared_data(key, Some(value), cas) {
+            Ok(()) => true,
+            Err(Status::CasMismatch) => false,
+            Err(status) => {
+                crate::sp_warn!("Failed to store shared buffer {}: {:?}", key, status);
+                false
+            }
+        }
+    }
+}
+
+impl Context for SpHttpContext {
+    fn on_http_call_response(
+        &mut self,
         token_id: u32,
         _num_headers: usize,
         body_size: usize,
@@ -280,19 +1139,31 @@ impl Context for SpHttpContext {
             Vec::new()
         };
 
-        // Check if this is the response to our async save call
-        if let Some(pending_save_token) = self.pending_save_call_token {
-            if pending_save_token == token_id {
-                crate::sp_debug!("Processing async save response (status_code={})", status_code);
-                self.pending_save_call_token = None;
-
-                if status_code >= 200 && status_code < 300 {
-                    crate::sp_info!("Async save completed (status: {})", status_code);
-                } else {
-                    crate::sp_error!("Async save failed with status: {}", status_code);
-                }
-                return;
+        // Shadow backend and body log responses are only logged
+        if self.pending_shadow_call_token == Some(token_id) {
+            self.pending_shadow_call_token = None;
+            if !(200..300).contains(&status_code) {
+                crate::sp_warn!("Shadow save failed with status: {}", status_code);
             }
+            self.release_dispatch_slot();
+            self.finish_if_stream_ended();
+            return;
+        }
+        if self.pending_logs_call_token == Some(token_id) {
+            self.pending_logs_call_token = None;
+            if !(200..300).contains(&status_code) {
+                crate::sp_warn!("Body logs save failed with status: {}", status_code);
+            }
+            self.release_dispatch_slot();
+            self.finish_if_stream_ended();
+            return;
+        }
+
+        // Check if this is the response to our async save call
+        if self.pending_save_call_token == Some(token_id) {
+            self.handle_save_response(status_code);
+            self.finish_if_stream_ended();
+            return;
         }
 
         // Check if this is the response to our injection lookup call
@@ -317,6 +1188,8 @@ impl Context for SpHttpContext {
                                 Some(injected_response.body.as_slice())
                             };
 
+                            self.inject_lookup_outcome = Some(InjectLookupOutcome::Hit);
+                            self.injected = true;
                             self.send_http_response(
                                 injected_response.status_code,
                                 headers_refs,
@@ -329,12 +1202,132 @@ impl Context for SpHttpContext {
                         }
                     }
                 }
+                self.inject_lookup_outcome = Some(InjectLookupOutcome::Miss);
 
                 // Resume the paused request
                 self.resume_http_request();
             }
         }
     }
+
+    fn on_done(&mut self) -> bool {
+        // Final flush of a span the stream never got to send
+        if self.has_unsent_span() {
+            crate::sp_debug!("Context done with unsent span, flushing (path={:?})", self.url_path);
+            self.dispatch_async_extraction_save();
+        }
+        self.flush_buffered_spans();
+        self.stream_ended = true;
+        // Envoy cancels the callouts of a finished context, so keep it alive while a
+        // call may still respond, be retried or hold a slot; finish_if_stream_ended
+        // ends it later
+        if self.has_pending_calls() {
+            crate::sp_debug!("Deferring context teardown until pending backend calls settle");
+            return false;
+        }
+        true
+    }
+}
+
+impl SpHttpContext {
+    /// Only buffer request bodies for methods that usually carry a payload
+    fn update_request_body_capture(&mut self) {
+        if let Some(method) = self.request_headers.get(":method") {
+            self.capture_request_body = self.config.captures_body_for_method(method);
+        }
+    }
+
+    /// Response headers, bodies and trailers are captured unless only the request
+    /// side is configured or the filter only propagates trace context
+    fn captures_response_phase(&self) -> bool {
+        self.config.capture_sides.captures_response() && !self.config.propagation_only
+    }
+
+    /// Bytes that may still be buffered for a body of `buffered` bytes; 0 once
+    /// max_body_bytes is reached so further chunks are never copied
+    fn body_buffer_room(&self, buffered: usize) -> usize {
+        match self.config.max_body_bytes {
+            0 => usize::MAX,
+            max => max.saturating_sub(buffered),
+        }
+    }
+
+    /// Fill in `:status` from the `response.code` property when the headers callback
+    /// never captured it, or mark the span so a missing status is visible
+    fn ensure_response_status(&mut self) {
+        if self.response_headers.contains_key(":status") {
+            return;
+        }
+        match crate::properties::get_response_code(self) {
+            Some(code) => {
+                crate::sp_debug!("No :status captured, using response.code {}", code);
+                self.response_headers.insert(":status".to_string(), code.to_string());
+            }
+            None => {
+                crate::sp_debug!("Response status unknown, dispatching without it");
+                self.span_builder.add_attribute("sp.response.status_unknown", crate::otel::any_value::Value::BoolValue(true));
+            }
+        }
+    }
+
+    fn capture_response_trailers(&mut self) {
+        for (key, value) in self.get_http_response_trailers() {
+            self.response_trailers.insert(key, value);
+        }
+    }
+
+    /// Uploads and streams on no_body_paths keep headers and timing but never buffer bodies
+    fn update_body_capture_denied(&mut self) {
+        self.body_capture_denied = crate::traffic::is_body_capture_denied(&self.config, self.url_path.as_deref());
+        if self.body_capture_denied {
+            crate::sp_debug!("Path {:?} is in no_body_paths, skipping body capture", self.url_path);
+            return;
+        }
+        if crate::grpc::is_grpc(&self.request_headers)
+            && !crate::grpc::is_body_capture_allowed(&self.config.grpc_capture_methods, self.url_path.as_deref())
+        {
+            crate::sp_debug!("gRPC method {:?} is not in grpc_capture_methods, skipping body capture", self.url_path);
+            self.body_capture_denied = true;
+        }
+    }
+
+    fn strip_configured_request_headers(&mut self) {
+        for name in self.config.strip_request_headers.clone() {
+            if name.starts_with(':') {
+                continue;
+            }
+            if self.request_headers.remove(&name).is_some() {
+                crate::sp_debug!("Stripping request header: {}", name);
+                self.remove_http_request_header(&name);
+            }
+        }
+    }
+
+    /// Send the span once the response has ended, unless the request body is
+    /// still arriving; it is then sent when the body completes, or on_done
+    /// flushes it if the body never does
+    fn complete_response(&mut self) {
+        if self.span_dispatched {
+            return;
+        }
+        if self.request_in_progress {
+            crate::sp_debug!("Response complete before the request body, deferring span");
+            self.response_complete = true;
+            return;
+        }
+        self.dispatch_async_extraction_save();
+    }
+
+    /// A captured request whose span was never sent, e.g. the stream was reset
+    /// before the response completed
+    fn has_unsent_span(&self) -> bool {
+        !self.span_dispatched
+            && !self.injected
+            && self.inject_lookup_outcome != Some(InjectLookupOutcome::Hit)
+            && self.request_start_time.is_some()
+            && self.url_path.is_some()
+            && !self.should_skip_ingress()
+    }
 }
 
 impl HttpContext for SpHttpContext {
@@ -356,22 +1349,34 @@ impl HttpContext for SpHttpContext {
 
         // Copy to request_headers cache
         self.request_headers = initial_headers.clone();
+
+        // Remove operator-configured headers before the request goes upstream
+        self.strip_configured_request_headers();
+
+        self.update_request_body_capture();
         
         // Cache the ingressgateway check result to avoid calling get_request_header during response phase
-        self.is_from_ingressgateway = crate::traffic::TrafficAnalyzer::is_from_istio_ingressgateway(self);
+        self.is_from_ingressgateway = self.node_metadata.is_ingressgateway
+            || crate::traffic::TrafficAnalyzer::is_from_istio_ingressgateway(self);
         
-        // Check if from istio-ingressgateway, skip if so
-        if self.is_from_ingressgateway {
+        // Check if from istio-ingressgateway, skip if so unless configured to annotate it
+        if self.should_skip_ingress() {
             crate::sp_debug!("Skipping processing for traffic from istio-ingressgateway");
             return Action::Continue;
         }
+        self.annotate_ingress_origin();
 
         // Detect service name
-        let detected_service_name = detect_service_name(&self.request_headers, &self.config.service_name);
+        let local_spiffe_id = crate::properties::get_string_property(self, vec!["connection", "uri_san_local_certificate"]);
+        let detected_service_name = match service_name_for_host(&self.request_headers, &self.config.service_name_by_host) {
+            Some(service_name) => service_name.to_string(),
+            None => detect_service_name(&self.request_headers, &self.config.service_name, local_spiffe_id.as_deref()),
+        };
         let public_key = self.config.public_key.clone();
 
         // Update url info
         self.update_url_info();
+        self.update_body_capture_denied();
 
         // Update span builder
         self.span_builder = self
@@ -382,8 +1387,10 @@ impl HttpContext for SpHttpContext {
             .with_public_key(public_key)
             .with_context(&initial_headers);
 
-        // Inject trace context headers
-        self.inject_trace_context_headers();
+        // Inject trace context headers unless the path is denylisted
+        self.apply_trace_context_injection();
+
+        self.request_in_progress = !end_of_stream;
 
         // If no body, perform injection lookup now
         if end_of_stream {
@@ -396,22 +1403,52 @@ impl HttpContext for SpHttpContext {
                     crate::sp_error!("Injection lookup error: {}, continuing", e);
                 }
             }
+
+            // Request-only capture emits the span at request end
+            if !self.config.capture_sides.captures_response() {
+                self.dispatch_async_extraction_save();
+            }
         }
 
         Action::Continue
     }
 
     fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
-        if self.is_from_ingressgateway {
+        if self.should_skip_ingress() {
             return Action::Continue;
         }
 
         // Buffer request body
-        if let Some(body) = self.get_http_request_body(0, body_size) {
-            self.request_body.extend_from_slice(&body);
+        if self.config.capture_sides.captures_request()
+            && self.capture_request_body
+            && !self.body_capture_denied
+            && !self.config.propagation_only
+        {
+            self.request_body_len += body_size;
+            let room = self.body_buffer_room(self.request_body.len());
+            if room > 0 {
+                if let Some(body) = self.get_http_request_body(0, body_size.min(room)) {
+                    self.request_body.extend_from_slice(&body);
+                }
+            }
+            if self.config.grpc_message_spans {
+                self.request_chunk_times.push((self.request_body.len(), crate::otel::get_current_timestamp_nanos()));
+            }
         }
 
         if end_of_stream {
+            self.request_in_progress = false;
+
+            // The response is already on its way, so there is nothing to inject;
+            // a span held back for the request body is sent now
+            if self.early_response {
+                if self.response_complete && !self.span_dispatched {
+                    crate::sp_debug!("Request body completed after the response, dispatching span");
+                    self.dispatch_async_extraction_save();
+                }
+                return Action::Continue;
+            }
+
             match self.dispatch_injection_lookup() {
                 Ok(call_id) => {
                     self.pending_inject_call_token = Some(call_id);
@@ -421,6 +1458,11 @@ impl HttpContext for SpHttpContext {
                     crate::sp_error!("Injection lookup error: {}, continuing", e);
                 }
             }
+
+            // Request-only capture emits the span at request end
+            if !self.config.capture_sides.captures_response() {
+                self.dispatch_async_extraction_save();
+            }
         }
 
         Action::Continue
@@ -428,14 +1470,32 @@ impl HttpContext for SpHttpContext {
 
     fn on_http_response_headers(&mut self, num_headers: usize, end_of_stream: bool) -> Action {
         crate::sp_debug!("proxied response headers - num_headers: {}, end_of_stream: {}", num_headers, end_of_stream);
+
+        // Let developers look the span up from the browser
+        if !self.should_skip_ingress() {
+            if let Some((name, span_id)) = self.span_id_response_header() {
+                self.add_http_response_header(name, &span_id);
+            }
+        }
         
-        if self.is_from_ingressgateway || self.injected {
+        if self.should_skip_ingress() || self.injected || !self.captures_response_phase() {
             return Action::Continue;
         }
 
+        // e.g. a 413 sent while the request body is still arriving
+        if self.request_in_progress && !self.early_response {
+            crate::sp_debug!("Response started before the request body completed");
+            self.early_response = true;
+            self.span_builder.add_attribute("sp.early_response", crate::otel::any_value::Value::BoolValue(true));
+        }
+
         // Skip header processing if no headers are expected
         if num_headers == 0 {
             crate::sp_debug!("No response headers to process, skipping header capture");
+            self.ensure_response_status();
+            if end_of_stream {
+                self.complete_response();
+            }
             return Action::Continue;
         }
 
@@ -444,12 +1504,40 @@ impl HttpContext for SpHttpContext {
             self.response_headers.insert(key, value);
         }
 
+        // Envoy answered without reaching the upstream (e.g. a 429 from the local rate limiter)
+        if crate::properties::is_local_reply(self) {
+            crate::sp_debug!("Response is an Envoy local reply");
+            self.span_builder.add_attribute("sp.local_reply", crate::otel::any_value::Value::BoolValue(true));
+        }
+
+        // Downstream and upstream protocol versions
+        for (key, protocol) in crate::properties::get_protocol_attributes(self) {
+            self.span_builder.add_attribute(key, crate::otel::any_value::Value::StringValue(protocol));
+        }
+
+        // Downstream TLS posture of inbound calls
+        if self.span_builder.get_traffic_direction() == "inbound" {
+            for (key, value) in crate::properties::get_downstream_tls_attributes(self) {
+                self.span_builder.add_attribute(key, crate::otel::any_value::Value::StringValue(value));
+            }
+        }
+
+        // Upstream connection addresses and routed cluster for network debugging of outbound calls
+        if self.span_builder.get_traffic_direction() == "outbound" {
+            for (key, address) in crate::properties::get_upstream_address_attributes(self) {
+                self.span_builder.add_attribute(key, crate::otel::any_value::Value::StringValue(address));
+            }
+            if let Some(cluster) = crate::properties::get_upstream_cluster(self) {
+                self.span_builder.add_attribute("sp.upstream.cluster", crate::otel::any_value::Value::StringValue(cluster));
+            }
+        }
+
         // Extract and propagate trace context
         self.extract_and_propagate_trace_context_impl();
 
         // If there's no response body, perform async extraction save now, fire and forget
         if end_of_stream {
-            self.dispatch_async_extraction_save();   
+            self.complete_response();
         }
 
         Action::Continue
@@ -458,20 +1546,46 @@ impl HttpContext for SpHttpContext {
     fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
         crate::sp_debug!("proxied response body - body_size: {}, end_of_stream: {}", body_size, end_of_stream);
 
-        if self.is_from_ingressgateway || self.injected {
+        if self.should_skip_ingress() || self.injected || !self.captures_response_phase() {
             return Action::Continue;
         }
 
         // Buffer response body
-        if let Some(body) = self.get_http_response_body(0, body_size) {
-            self.response_body.extend_from_slice(&body);
+        if !self.body_capture_denied {
+            self.response_body_len += body_size;
+            let room = self.body_buffer_room(self.response_body.len());
+            if room > 0 {
+                if let Some(body) = self.get_http_response_body(0, body_size.min(room)) {
+                    self.response_body.extend_from_slice(&body);
+                }
+            }
+            if self.config.grpc_message_spans {
+                self.response_chunk_times.push((self.response_body.len(), crate::otel::get_current_timestamp_nanos()));
+            }
         }
 
         if end_of_stream {
-            if let Some(status) = self.response_headers.get(":status") {
-                crate::sp_debug!("Processing response (status: {})", status);
-                self.dispatch_async_extraction_save();
-            }   
+            self.capture_response_trailers();
+            self.ensure_response_status();
+            crate::sp_debug!("Processing response (status: {:?})", self.response_headers.get(":status"));
+            self.complete_response();
+        }
+
+        Action::Continue
+    }
+
+    fn on_http_response_trailers(&mut self, num_trailers: usize) -> Action {
+        crate::sp_debug!("proxied response trailers - num_trailers: {}", num_trailers);
+
+        if self.should_skip_ingress() || self.injected || !self.captures_response_phase() {
+            return Action::Continue;
+        }
+
+        // With trailers the body never sees end_of_stream, so the span is saved here
+        self.capture_response_trailers();
+        if !self.span_dispatched {
+            self.ensure_response_status();
+            self.complete_response();
         }
 
         Action::Continue
@@ -479,6 +1593,19 @@ impl HttpContext for SpHttpContext {
 }
 
 impl SpHttpContext {
+    /// Ingress gateway traffic is skipped unless annotate_ingress_traffic is set
+    fn should_skip_ingress(&self) -> bool {
+        self.is_from_ingressgateway && !self.config.annotate_ingress_traffic
+    }
+
+    /// Record on the span whether the request came through the mesh ingress
+    fn annotate_ingress_origin(&mut self) {
+        self.span_builder.add_attribute(
+            "sp.from_ingress",
+            crate::otel::any_value::Value::BoolValue(self.is_from_ingressgateway),
+        );
+    }
+
     /// Check if the current request is for static resources based on URL path and Content-Type
     fn is_static_resource(&self) -> bool {
         is_static_resource(self.url_path.as_deref(), &self.response_headers)
@@ -513,8 +1640,8 @@ impl SpHttpContext {
             }
         }
         // Headers-based detection (may vary by framework/version)
-        let has_rsc_header = self.request_headers.get("rsc").is_some() || self.request_headers.get("Rsc").is_some();
-        let has_next_prefetch = self.request_headers.get("next-router-prefetch").is_some() || self.request_headers.get("Next-Router-Prefetch").is_some();
+        let has_rsc_header = self.request_headers.contains_key("rsc") || self.request_headers.contains_key("Rsc");
+        let has_next_prefetch = self.request_headers.contains_key("next-router-prefetch") || self.request_headers.contains_key("Next-Router-Prefetch");
         let has_purpose_prefetch = self
             .request_headers
             .get("purpose")
@@ -526,8 +1653,8 @@ impl SpHttpContext {
             .map(|v| v.eq_ignore_ascii_case("prefetch"))
             .or_else(|| self.request_headers.get("Sec-Purpose").map(|v| v.eq_ignore_ascii_case("prefetch")))
             .unwrap_or(false);
-        let has_next_state_tree = self.request_headers.get("next-router-state-tree").is_some()
-            || self.request_headers.get("Next-Router-State-Tree").is_some();
+        let has_next_state_tree = self.request_headers.contains_key("next-router-state-tree")
+            || self.request_headers.contains_key("Next-Router-State-Tree");
 
         if has_rsc_header || has_next_prefetch || has_purpose_prefetch || has_sec_purpose_prefetch || has_next_state_tree {
             crate::sp_debug!(
@@ -576,4 +1703,1775 @@ fn is_static_resource(url_path: Option<&str>, response_headers: &HashMap<String,
     }
     
     false
-}
\ No newline at end of file
+}
+
+/// Sizes of a captured body: the decoded length when it was decompressed, otherwise
+/// the wire length, with truncation when less than that was kept
+fn captured_body_size(wire_len: usize, captured_len: usize, decoding: Option<BodyDecoding>) -> BodySize {
+    match decoding {
+        Some(decoding) => BodySize {
+            decoded: captured_len,
+            encoded: wire_len,
+            truncated: decoding.truncated,
+            encoding: Some(decoding.encoding.as_str()),
+        },
+        None => BodySize {
+            decoded: wire_len,
+            encoded: wire_len,
+            truncated: wire_len > captured_len,
+            encoding: None,
+        },
+    }
+}
+
+/// Whether `body_len` bytes were seen for a body declaring a different content-length.
+/// Bodies that were not buffered, or carry no content-length (chunked), are not checked.
+fn body_length_mismatch(headers: &HashMap<String, String>, body_len: usize) -> bool {
+    if body_len == 0 {
+        return false;
+    }
+    headers
+        .get("content-length")
+        .and_then(|declared| declared.trim().parse::<usize>().ok())
+        .is_some_and(|declared| declared != body_len)
+}
+
+/// Record the last export failure on the spans of a save about to be re-sent,
+/// so the backend sees the failure history when the retry succeeds
+fn annotate_export_error(save: &mut BufferedSpan, error: ExportError) {
+    let annotated = if save.content_type == "application/json" {
+        crate::zipkin::annotate_serialized_zipkin(&save.payload, "sp.export.error", error.as_str())
+    } else {
+        crate::otel::annotate_serialized_traces(&save.payload, "sp.export.error", error.as_str())
+    };
+    match annotated {
+        Some(payload) => save.payload = payload,
+        None => {
+            crate::sp_warn!("Could not record the export error on the re-sent payload");
+        }
+    }
+}
+
+/// Backend statuses worth re-sending a save for: throttling and server errors
+pub(crate) fn is_retryable_status(status_code: u32) -> bool {
+    status_code == 429 || (500..=599).contains(&status_code)
+}
+
+/// Start time of a span ending at `now`, preferring Envoy's measured duration
+fn span_start_time(now: u64, envoy_duration: Option<u64>, manual_start: Option<u64>) -> Option<u64> {
+    envoy_duration.map(|duration| now.saturating_sub(duration)).or(manual_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CaptureSides, MaskingConfig, DEFAULT_MAX_RETRIES};
+    use crate::traffic::CompiledPattern;
+    use crate::otel::any_value::Value::StringValue;
+
+    fn context_for_path(config: Config, path: &str) -> SpHttpContext {
+        let mut ctx = SpHttpContext::new(1, config, NodeMetadata::default());
+        ctx.request_headers.insert(":path".to_string(), path.to_string());
+        ctx.url_path = Some(path.to_string());
+        ctx
+    }
+
+    fn extract_span_attribute(ctx: &mut SpHttpContext, key: &str) -> Option<crate::otel::any_value::Value> {
+        let traces_data = ctx.build_extract_traces_data();
+        crate::otel::attribute(crate::otel::span_of(&traces_data), key).cloned()
+    }
+
+    #[test]
+    fn test_update_url_info_truncates_long_url() {
+        let config = Config {
+            max_url_length: Some(32),
+            ..Default::default()
+        };
+        let mut ctx = SpHttpContext::new(1, config, NodeMetadata::default());
+        let long_path = format!("/download?token={}", "a".repeat(500));
+        ctx.request_headers.insert(":path".to_string(), long_path);
+
+        ctx.update_url_info();
+
+        let path = ctx.url_path.clone().unwrap();
+        assert_eq!(path.len(), 32);
+        assert!(path.ends_with(crate::http_helpers::URL_TRUNCATION_MARKER));
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "url.truncated"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_update_url_info_keeps_short_url() {
+        let config = Config {
+            max_url_length: Some(32),
+            ..Default::default()
+        };
+        let mut ctx = SpHttpContext::new(1, config, NodeMetadata::default());
+        ctx.request_headers.insert(":path".to_string(), "/api/orders".to_string());
+
+        ctx.update_url_info();
+
+        assert_eq!(ctx.url_path.as_deref(), Some("/api/orders"));
+        assert_eq!(extract_span_attribute(&mut ctx, "url.truncated"), None);
+    }
+
+    #[test]
+    fn test_extract_span_records_inject_lookup_miss() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.inject_lookup_outcome = Some(InjectLookupOutcome::Miss);
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.inject.lookup"),
+            Some(crate::otel::any_value::Value::StringValue("miss".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_extract_span_records_inject_lookup_hit() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.inject_lookup_outcome = Some(InjectLookupOutcome::Hit);
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.inject.lookup"),
+            Some(crate::otel::any_value::Value::StringValue("hit".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rebuilt_span_records_inject_lookup_once() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.inject_lookup_outcome = Some(InjectLookupOutcome::Miss);
+
+        ctx.build_extract_traces_data();
+        let traces_data = ctx.build_extract_traces_data();
+
+        let span = crate::otel::span_of(&traces_data);
+        assert_eq!(span.attributes.iter().filter(|kv| kv.key == "sp.inject.lookup").count(), 1);
+    }
+
+    #[test]
+    fn test_extract_span_without_inject_lookup() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        assert_eq!(extract_span_attribute(&mut ctx, "sp.inject.lookup"), None);
+    }
+
+    #[test]
+    fn test_inject_lookup_response_without_injection_is_miss() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.pending_inject_call_token = Some(7);
+
+        ctx.on_http_call_response(7, 0, 0, 0);
+
+        assert_eq!(ctx.inject_lookup_outcome, Some(InjectLookupOutcome::Miss));
+        assert!(ctx.pending_inject_call_token.is_none());
+    }
+
+    #[test]
+    fn test_ingress_traffic_skipped_by_default() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.is_from_ingressgateway = true;
+
+        assert!(ctx.should_skip_ingress());
+    }
+
+    #[test]
+    fn test_ingress_traffic_annotated_when_configured() {
+        let config = Config {
+            annotate_ingress_traffic: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.is_from_ingressgateway = true;
+
+        assert!(!ctx.should_skip_ingress());
+        ctx.annotate_ingress_origin();
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.from_ingress"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_mesh_traffic_annotated_as_not_from_ingress() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        assert!(!ctx.should_skip_ingress());
+        ctx.annotate_ingress_origin();
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.from_ingress"),
+            Some(crate::otel::any_value::Value::BoolValue(false))
+        );
+    }
+
+    #[test]
+    fn test_request_capture_emits_span_at_request_end() {
+        let config = Config {
+            capture_sides: CaptureSides::Request,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.on_http_request_body(0, true);
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_request_capture_skips_response_phase() {
+        let config = Config {
+            capture_sides: CaptureSides::Request,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        assert_eq!(ctx.on_http_response_headers(1, true), Action::Continue);
+        assert!(ctx.response_headers.is_empty());
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_both_capture_waits_for_response() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        ctx.on_http_request_body(0, true);
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_denylisted_path_receives_no_injected_headers() {
+        let config = Config {
+            injection_path_denylist: CompiledPattern::compile_all(&["^/signed/".to_string()]),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/signed/upload");
+
+        ctx.apply_trace_context_injection();
+
+        assert!(ctx.injection_denied);
+        assert!(!ctx.request_headers.contains_key("traceparent"));
+        assert!(!ctx.request_headers.contains_key("tracestate"));
+        assert!(!ctx.request_headers.contains_key("x-sp-num"));
+    }
+
+    fn outbound_context_for_host(host: &str) -> SpHttpContext {
+        let config = Config {
+            propagate_hosts: vec![".svc.cluster.local".to_string()],
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/v1/charge");
+        ctx.url_host = Some(host.to_string());
+        ctx.span_builder = ctx.span_builder.clone().with_traffic_direction("outbound".to_string());
+        ctx
+    }
+
+    #[test]
+    fn test_external_host_outside_propagate_hosts_receives_no_headers() {
+        let mut ctx = outbound_context_for_host("api.payments-vendor.com");
+
+        ctx.apply_trace_context_injection();
+
+        assert!(ctx.injection_denied);
+        assert!(!ctx.request_headers.contains_key("traceparent"));
+    }
+
+    #[test]
+    fn test_mesh_host_in_propagate_hosts_receives_headers() {
+        let mut ctx = outbound_context_for_host("payments.billing.svc.cluster.local");
+
+        ctx.apply_trace_context_injection();
+
+        assert!(!ctx.injection_denied);
+        assert!(ctx.request_headers.contains_key("traceparent"));
+    }
+
+    #[test]
+    fn test_no_session_tracestate_entry_without_auto_generation() {
+        let config = Config {
+            auto_generate_session_id: false,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.span_builder = ctx.span_builder.clone().with_context(&ctx.request_headers);
+
+        ctx.apply_trace_context_injection();
+
+        let tracestate = ctx.request_headers.get("tracestate").unwrap();
+        assert!(tracestate.starts_with("x-sp-traceparent="));
+        assert!(!tracestate.contains("x-sp-session-id="));
+    }
+
+    #[test]
+    fn test_other_path_receives_injected_headers() {
+        let config = Config {
+            injection_path_denylist: CompiledPattern::compile_all(&["^/signed/".to_string()]),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.apply_trace_context_injection();
+
+        assert!(!ctx.injection_denied);
+        assert!(ctx.request_headers.contains_key("traceparent"));
+        assert!(ctx.request_headers.contains_key("tracestate"));
+        assert_eq!(ctx.request_headers.get("x-sp-num"), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn test_xml_request_body_is_masked_as_xml() {
+        let config = Config {
+            masking: MaskingConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/soap/login");
+        ctx.request_headers.insert("content-type".to_string(), "text/xml; charset=utf-8".to_string());
+        ctx.request_body = b"<login><password>hunter2secret</password></login>".to_vec();
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body"),
+            Some(StringValue("<login><password>hun******cret</password></login>".to_string()))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.content_type"),
+            Some(StringValue("text/xml; charset=utf-8".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_masking_configured_from_plugin_json_masks_span() {
+        let mut config = Config::default();
+        let config_str = r#"{"masking":{"enabled":true,"maskRequestHeaders":["x-user-phone"]}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert("x-user-phone".to_string(), "13812345678".to_string());
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.header.x-user-phone"),
+            Some(StringValue("138****5678".to_string()))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.masking.applied"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_span_start_time_prefers_envoy_duration() {
+        let now = 1_700_000_000_000_000_000;
+
+        assert_eq!(span_start_time(now, Some(12_500_000), Some(now - 20_000_000)), Some(now - 12_500_000));
+        assert_eq!(span_start_time(now, None, Some(now - 20_000_000)), Some(now - 20_000_000));
+        assert_eq!(span_start_time(now, None, None), None);
+    }
+
+    #[test]
+    fn test_propagation_only_injects_headers_without_dispatch() {
+        let config = Config {
+            propagation_only: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.on_http_request_headers(0, false);
+        ctx.on_http_request_body(0, true);
+        ctx.on_http_response_headers(1, false);
+        ctx.on_http_response_body(0, true);
+
+        assert!(ctx.request_headers.contains_key("traceparent"));
+        assert_eq!(ctx.request_headers.get("x-sp-num").map(String::as_str), Some("1"));
+        assert!(ctx.response_headers.is_empty());
+        assert!(ctx.pending_save_call_token.is_none());
+
+        assert!(ctx.on_done());
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_gzip_response_body_is_decompressed() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let body = br#"{"order":"A-1","items":[1,2,3]}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let compressed_len = compressed.len() as i64;
+
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.response_headers.insert("content-type".to_string(), "application/json".to_string());
+        ctx.response_headers.insert("content-encoding".to_string(), "gzip".to_string());
+        ctx.response_body = compressed;
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body"),
+            Some(StringValue(String::from_utf8(body.to_vec()).unwrap()))
+        );
+        assert_eq!(extract_span_attribute(&mut ctx, "http.response.body.encoding"), Some(StringValue("gzip".to_string())));
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body.encoded_size"),
+            Some(crate::otel::any_value::Value::IntValue(compressed_len))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(body.len() as i64))
+        );
+    }
+
+    #[test]
+    fn test_gzip_body_larger_than_max_body_bytes_is_decompressed() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        // Incompressible bytes, so the cut-off stream runs out before max_body_bytes of output
+        let body: Vec<u8> = (0..8192u64)
+            .scan(0x9e37_79b9_7f4a_7c15u64, |state, _| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                Some((*state >> 56) as u8)
+            })
+            .collect();
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let max_body_bytes = compressed.len() / 2;
+
+        let config = Config {
+            max_body_bytes,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/export");
+        ctx.response_headers.insert("content-type".to_string(), "application/octet-stream".to_string());
+        ctx.response_headers.insert("content-encoding".to_string(), "gzip".to_string());
+        // Capture stopped at max_body_bytes, mid-stream
+        ctx.response_body = compressed[..max_body_bytes].to_vec();
+        ctx.response_body_len = compressed.len();
+
+        assert_eq!(extract_span_attribute(&mut ctx, "http.response.body.encoding"), Some(StringValue("gzip".to_string())));
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body.truncated"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+        assert!(!ctx.response_body.is_empty());
+        assert!(body.starts_with(&ctx.response_body));
+    }
+
+    #[test]
+    fn test_invalid_gzip_body_is_kept_as_received() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.response_headers.insert("content-encoding".to_string(), "gzip".to_string());
+        ctx.response_body = b"plain".to_vec();
+
+        assert_eq!(extract_span_attribute(&mut ctx, "http.response.body.encoding"), None);
+        assert_eq!(ctx.response_body, b"plain");
+    }
+
+    #[test]
+    fn test_unsampled_request_still_injects_headers() {
+        let config = Config {
+            sampling_rate: 0.0,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.on_http_request_headers(0, false);
+        ctx.response_headers.insert(":status".to_string(), "200".to_string());
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.request_headers.contains_key("traceparent"));
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_full_sampling_rate_dispatches() {
+        let config = Config {
+            sampling_rate: 1.0,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_unsent_span_is_dispatched_on_done() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_start_time = Some(1);
+
+        assert!(!ctx.on_done());
+
+        assert!(ctx.span_dispatched);
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_on_done_does_not_resend_dispatched_span() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_start_time = Some(1);
+        ctx.span_dispatched = true;
+
+        assert!(ctx.on_done());
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_configured_headers_are_stripped_from_request() {
+        let config = Config {
+            strip_request_headers: vec!["x-internal-token".to_string(), ":path".to_string()],
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert("x-internal-token".to_string(), "secret".to_string());
+        ctx.request_headers.insert("accept".to_string(), "*/*".to_string());
+
+        ctx.strip_configured_request_headers();
+
+        assert!(!ctx.request_headers.contains_key("x-internal-token"));
+        assert!(ctx.request_headers.contains_key("accept"));
+        assert!(ctx.request_headers.contains_key(":path"));
+    }
+
+    #[test]
+    fn test_unsampled_request_is_not_dispatched() {
+        let config = Config {
+            sampling_rate: 0.0,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_bodies_as_logs_keeps_span_lean() {
+        let config = Config {
+            bodies_as_logs: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert("content-type".to_string(), "application/json".to_string());
+        ctx.request_body = br#"{"id":1}"#.to_vec();
+
+        assert_eq!(extract_span_attribute(&mut ctx, "http.request.body"), None);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(8))
+        );
+
+        let logs_data = ctx.build_body_logs_data();
+        let record = &logs_data.resource_logs[0].scope_logs[0].log_records[0];
+        assert_eq!(record.body.as_ref().and_then(|b| b.value.clone()), Some(StringValue(r#"{"id":1}"#.to_string())));
+    }
+
+    #[test]
+    fn test_cached_node_metadata_marks_ingress_traffic() {
+        let node_metadata = NodeMetadata {
+            is_ingressgateway: true,
+            ..Default::default()
+        };
+        let mut ctx = SpHttpContext::new(1, Config::default(), node_metadata);
+
+        ctx.on_http_request_headers(0, false);
+
+        assert!(ctx.is_from_ingressgateway);
+        assert!(ctx.should_skip_ingress());
+    }
+
+    fn context_for_method(method: &str) -> SpHttpContext {
+        let mut ctx = SpHttpContext::new(1, Config::default(), NodeMetadata::default());
+        ctx.request_headers.insert(":method".to_string(), method.to_string());
+        ctx.update_request_body_capture();
+        ctx
+    }
+
+    #[test]
+    fn test_get_skips_request_body_capture() {
+        let ctx = context_for_method("GET");
+        assert!(!ctx.capture_request_body);
+    }
+
+    #[test]
+    fn test_post_captures_request_body() {
+        let ctx = context_for_method("POST");
+        assert!(ctx.capture_request_body);
+    }
+
+    #[test]
+    fn test_no_body_path_skips_body_capture() {
+        let config = Config {
+            no_body_paths: CompiledPattern::compile_all(&["^/upload/".to_string()]),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/upload/video");
+
+        ctx.update_body_capture_denied();
+        ctx.on_http_request_body(16, false);
+
+        assert!(ctx.body_capture_denied);
+        assert!(ctx.request_body.is_empty());
+    }
+
+    #[test]
+    fn test_other_path_keeps_body_capture() {
+        let config = Config {
+            no_body_paths: CompiledPattern::compile_all(&["^/upload/".to_string()]),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.update_body_capture_denied();
+
+        assert!(!ctx.body_capture_denied);
+    }
+
+    #[test]
+    fn test_grpc_capture_methods_allowlist() {
+        let config = Config {
+            grpc_capture_methods: vec!["orders.v1.OrderService/CreateOrder".to_string()],
+            ..Default::default()
+        };
+
+        let mut listed = context_for_path(config.clone(), "/orders.v1.OrderService/CreateOrder");
+        listed.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        listed.update_body_capture_denied();
+        assert!(!listed.body_capture_denied);
+
+        let mut unlisted = context_for_path(config, "/orders.v1.OrderService/ListOrders");
+        unlisted.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        unlisted.update_body_capture_denied();
+        assert!(unlisted.body_capture_denied);
+    }
+
+    #[test]
+    fn test_grpc_status_trailer_sets_error_status() {
+        let mut ctx = context_for_path(Config::default(), "/orders.v1.OrderService/CreateOrder");
+        ctx.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        ctx.response_headers.insert(":status".to_string(), "200".to_string());
+        ctx.response_trailers.insert("grpc-status".to_string(), "14".to_string());
+        ctx.response_trailers.insert("grpc-message".to_string(), "upstream unavailable".to_string());
+
+        let traces_data = ctx.build_extract_traces_data();
+        let span = &traces_data.resource_spans[0].scope_spans[0].spans[0];
+        let attribute = |key: &str| span.attributes.iter().find(|kv| kv.key == key).and_then(|kv| kv.value.clone()).and_then(|v| v.value);
+
+        assert_eq!(attribute("rpc.grpc.status_code"), Some(crate::otel::any_value::Value::IntValue(14)));
+        assert_eq!(
+            attribute("grpc.message"),
+            Some(crate::otel::any_value::Value::StringValue("upstream unavailable".to_string()))
+        );
+        let status = span.status.as_ref().unwrap();
+        assert_eq!(status.code, 2);
+        assert_eq!(status.message, "upstream unavailable");
+    }
+
+    #[test]
+    fn test_grpc_ok_status_keeps_ok_span() {
+        let mut ctx = context_for_path(Config::default(), "/orders.v1.OrderService/CreateOrder");
+        ctx.response_headers.insert(":status".to_string(), "200".to_string());
+        ctx.response_headers.insert("grpc-status".to_string(), "0".to_string());
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "rpc.grpc.status_code"),
+            Some(crate::otel::any_value::Value::IntValue(0))
+        );
+        let traces_data = ctx.build_extract_traces_data();
+        assert_eq!(traces_data.resource_spans[0].scope_spans[0].spans[0].status.as_ref().unwrap().code, 1);
+    }
+
+    /// A request whose headers arrived and whose body is still streaming
+    fn context_with_request_in_progress() -> SpHttpContext {
+        let mut ctx = context_for_path(Config::default(), "/api/upload");
+        ctx.request_start_time = Some(1);
+        ctx.request_in_progress = true;
+        ctx.on_http_request_body(16, false);
+        ctx
+    }
+
+    #[test]
+    fn test_early_response_waits_for_request_body() {
+        let mut ctx = context_with_request_in_progress();
+
+        ctx.on_http_response_headers(0, true);
+
+        assert!(ctx.early_response);
+        assert!(!ctx.span_dispatched);
+
+        ctx.on_http_request_body(16, true);
+
+        assert!(ctx.span_dispatched);
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(ctx.pending_inject_call_token.is_none());
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.early_response"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_early_response_is_dispatched_once() {
+        let mut ctx = context_with_request_in_progress();
+
+        ctx.on_http_response_headers(0, false);
+        ctx.on_http_response_body(0, true);
+        ctx.on_http_request_body(0, true);
+        let token = ctx.pending_save_call_token;
+        ctx.on_http_response_trailers(0);
+        assert!(!ctx.on_done());
+
+        assert!(token.is_some());
+        assert_eq!(ctx.pending_save_call_token, token);
+    }
+
+    #[test]
+    fn test_early_response_without_request_end_flushes_on_done() {
+        let mut ctx = context_with_request_in_progress();
+
+        ctx.on_http_response_headers(0, true);
+        assert!(!ctx.span_dispatched);
+
+        assert!(!ctx.on_done());
+
+        assert!(ctx.span_dispatched);
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_response_after_request_end_is_not_early() {
+        let mut ctx = context_with_request_in_progress();
+        ctx.on_http_request_body(0, true);
+
+        ctx.on_http_response_headers(0, true);
+
+        assert!(!ctx.early_response);
+        assert!(ctx.span_dispatched);
+        assert_eq!(extract_span_attribute(&mut ctx, "sp.early_response"), None);
+    }
+
+    #[test]
+    fn test_missing_status_still_dispatches_span() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        ctx.on_http_response_headers(0, false);
+        ctx.on_http_response_body(0, true);
+
+        assert!(ctx.span_dispatched);
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(!ctx.response_headers.contains_key(":status"));
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.response.status_unknown"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_missing_status_headers_only_response_dispatches() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        ctx.on_http_response_headers(0, true);
+
+        assert!(ctx.span_dispatched);
+        assert_eq!(extract_span_attribute(&mut ctx, "http.response.status_code"), None);
+    }
+
+    #[test]
+    fn test_hash_bodies_emits_equal_hashes_for_identical_bodies() {
+        let config = Config {
+            hash_bodies: true,
+            ..Default::default()
+        };
+        let mut first = context_for_path(config.clone(), "/api/orders");
+        first.request_body = br#"{"sku":"A-1","qty":2}"#.to_vec();
+        let mut second = context_for_path(config, "/api/orders");
+        second.request_body = br#"{"sku":"A-1","qty":2}"#.to_vec();
+
+        let first_hash = extract_span_attribute(&mut first, "http.request.body.hash");
+        assert!(first_hash.is_some());
+        assert_eq!(first_hash, extract_span_attribute(&mut second, "http.request.body.hash"));
+
+        let mut unhashed = context_for_path(Config::default(), "/api/orders");
+        unhashed.request_body = br#"{"sku":"A-1","qty":2}"#.to_vec();
+        assert_eq!(extract_span_attribute(&mut unhashed, "http.request.body.hash"), None);
+    }
+
+    #[test]
+    fn test_body_larger_than_limit_is_marked_truncated() {
+        let config = Config {
+            max_body_bytes: 16,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/upload");
+        ctx.request_body = vec![b'a'; 16];
+        ctx.request_body_len = 40;
+
+        assert_eq!(ctx.body_buffer_room(ctx.request_body.len()), 0);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.truncated"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(40))
+        );
+    }
+
+    fn context_with_response_body(config: Config, content_length: &str, body: &[u8]) -> SpHttpContext {
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.response_headers.insert("content-type".to_string(), "application/json".to_string());
+        ctx.response_headers.insert("content-length".to_string(), content_length.to_string());
+        ctx.response_body = body.to_vec();
+        ctx.response_body_len = body.len();
+        ctx
+    }
+
+    #[test]
+    fn test_body_matching_content_length_is_not_flagged() {
+        let mut ctx = context_with_response_body(Config::default(), "8", b"{\"id\":1}");
+
+        assert_eq!(extract_span_attribute(&mut ctx, "sp.body.length_mismatch"), None);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body"),
+            Some(StringValue("{\"id\":1}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_body_shorter_than_content_length_is_flagged() {
+        let mut ctx = context_with_response_body(Config::default(), "100", b"{\"id\":1}");
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.body.length_mismatch"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body"),
+            Some(StringValue("{\"id\":1}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_mismatched_body_is_dropped_when_configured() {
+        let config = Config {
+            drop_mismatched_bodies: true,
+            ..Default::default()
+        };
+        let mut ctx = context_with_response_body(config, "100", b"{\"id\":1}");
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.body.length_mismatch"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+        assert_eq!(extract_span_attribute(&mut ctx, "http.response.body"), None);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.response.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(8))
+        );
+    }
+
+    #[test]
+    fn test_body_length_mismatch_ignores_unbuffered_and_chunked_bodies() {
+        let mut headers = HashMap::new();
+        assert!(!body_length_mismatch(&headers, 8));
+
+        headers.insert("content-length".to_string(), "100".to_string());
+        assert!(!body_length_mismatch(&headers, 0));
+        assert!(body_length_mismatch(&headers, 8));
+        assert!(!body_length_mismatch(&headers, 100));
+    }
+
+    #[test]
+    fn test_body_exactly_at_limit_is_not_truncated() {
+        let config = Config {
+            max_body_bytes: 16,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/upload");
+        ctx.request_body = vec![b'a'; 16];
+        ctx.request_body_len = 16;
+
+        assert_eq!(ctx.body_buffer_room(0), 16);
+        assert_eq!(extract_span_attribute(&mut ctx, "http.request.body.truncated"), None);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(16))
+        );
+    }
+
+    #[test]
+    fn test_payload_size_summary_when_enabled() {
+        let config = Config {
+            log_payload_size: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_body = b"{\"id\":1}".to_vec();
+        let traces_data = ctx.build_extract_traces_data();
+
+        let summary = ctx.payload_size_summary(&traces_data).unwrap();
+
+        assert!(summary.contains(&format!("{} bytes protobuf", traces_data.encoded_len())));
+        assert!(summary.contains("request body 8 bytes"));
+        assert!(summary.contains("response body 0 bytes"));
+    }
+
+    #[test]
+    fn test_payload_size_summary_disabled_by_default() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        let traces_data = ctx.build_extract_traces_data();
+
+        assert!(ctx.payload_size_summary(&traces_data).is_none());
+    }
+
+    #[test]
+    fn test_url_scheme_from_pseudo_header() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_headers.insert(":scheme".to_string(), "https".to_string());
+
+        ctx.update_url_info();
+
+        assert_eq!(ctx.url_scheme.as_deref(), Some("https"));
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "url.scheme"),
+            Some(crate::otel::any_value::Value::StringValue("https".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_url_full_composed_from_parts() {
+        let mut ctx = context_for_path(Config::default(), "/login?user=alice&token=abcdefghijkl");
+        ctx.request_headers.insert(":scheme".to_string(), "https".to_string());
+        ctx.request_headers.insert(":authority".to_string(), "bob:secret@shop.example.com".to_string());
+
+        ctx.update_url_info();
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "url.full"),
+            Some(crate::otel::any_value::Value::StringValue(
+                "https://shop.example.com/login?user=alice&token=abc*****ijkl".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_url_full_respects_max_url_length() {
+        let config = Config {
+            max_url_length: Some(32),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert(":scheme".to_string(), "https".to_string());
+        ctx.request_headers.insert(":authority".to_string(), "shop.example.com".to_string());
+
+        ctx.update_url_info();
+        let url_full = ctx.build_url_full().unwrap();
+
+        assert!(url_full.len() <= 32);
+        assert!(url_full.ends_with(crate::http_helpers::URL_TRUNCATION_MARKER));
+    }
+
+    fn session_sampled_context(session_id: &str, request: u64, trace_flags: &str) -> SpHttpContext {
+        let config = Config {
+            sampling_rate: 0.5,
+            session_sampling_rate: Some(0.5),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert("x-sp-session-id".to_string(), session_id.to_string());
+        ctx.request_headers.insert(
+            "traceparent".to_string(),
+            format!("00-4bf92f3577b34da6{:016x}-00f067aa0ba902b7-{}", request.wrapping_mul(u64::MAX / 7) | 1, trace_flags),
+        );
+        ctx.span_builder = ctx.span_builder.clone().with_context(&ctx.request_headers);
+        ctx
+    }
+
+    /// Find a session ID whose sampling decision is `sampled`
+    fn session_with_decision(sampled: bool) -> String {
+        (0..)
+            .map(|n| format!("sp-session-{}", n))
+            .find(|id| crate::sampling::should_sample(0.5, crate::config::SamplingKey::Session, &[], id) == sampled)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sampled_in_session_keeps_all_requests() {
+        let session_id = session_with_decision(true);
+        for (request, trace_flags) in ["01", "00", "01", "00"].into_iter().enumerate() {
+            let mut ctx = session_sampled_context(&session_id, request as u64, trace_flags);
+
+            ctx.dispatch_async_extraction_save();
+
+            assert!(ctx.pending_save_call_token.is_some());
+        }
+    }
+
+    #[test]
+    fn test_sampled_out_session_drops_all_requests() {
+        let session_id = session_with_decision(false);
+        for request in 0..4 {
+            let mut ctx = session_sampled_context(&session_id, request, "01");
+
+            ctx.dispatch_async_extraction_save();
+
+            assert!(ctx.pending_save_call_token.is_none());
+        }
+    }
+
+    fn collection_rules_config(rules: serde_json::Value) -> Config {
+        let mut config = Config::default();
+        let config_str = serde_json::to_string(&serde_json::json!({ "collectionRules": rules })).unwrap();
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        config
+    }
+
+    /// Collection rules only apply to requests that carry a session ID
+    fn session_context_for_path(config: Config, path: &str) -> SpHttpContext {
+        let mut ctx = context_for_path(config, path);
+        ctx.request_headers.insert("x-sp-session-id".to_string(), "sp-session-rules".to_string());
+        ctx.span_builder = ctx.span_builder.clone().with_context(&ctx.request_headers);
+        ctx
+    }
+
+    fn dispatched_with_method(config: Config, method: &str) -> bool {
+        let mut ctx = session_context_for_path(config, "/api/orders");
+        ctx.request_headers.insert(":method".to_string(), method.to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        ctx.pending_save_call_token.is_some()
+    }
+
+    #[test]
+    fn test_collection_rule_methods_gate_dispatch() {
+        let config = collection_rules_config(serde_json::json!({
+            "http": { "server": [{ "path": "^/api/orders$", "methods": ["POST"] }] }
+        }));
+
+        assert!(!dispatched_with_method(config.clone(), "GET"));
+        assert!(dispatched_with_method(config, "POST"));
+    }
+
+    fn dispatched_with_headers(config: Config, headers: &[(&str, &str)]) -> bool {
+        let mut ctx = session_context_for_path(config, "/api/orders");
+        for (name, value) in headers {
+            ctx.request_headers.insert(name.to_string(), value.to_string());
+        }
+
+        ctx.dispatch_async_extraction_save();
+
+        ctx.pending_save_call_token.is_some()
+    }
+
+    #[test]
+    fn test_collection_rule_header_patterns_gate_dispatch() {
+        let config = collection_rules_config(serde_json::json!({
+            "http": { "server": [{ "path": "^/api/orders$", "headerPatterns": { "x-tenant": "^premium$" } }] }
+        }));
+
+        assert!(dispatched_with_headers(config.clone(), &[("x-tenant", "premium")]));
+        assert!(!dispatched_with_headers(config.clone(), &[("x-tenant", "basic")]));
+        assert!(!dispatched_with_headers(config, &[]));
+    }
+
+    #[test]
+    fn test_client_rule_header_patterns_gate_dispatch() {
+        let config = collection_rules_config(serde_json::json!({
+            "http": { "client": [{ "host": "app\\.example\\.com", "headerPatterns": { "x-tenant": "^premium$" } }] }
+        }));
+        let referer = ("referer", "https://app.example.com/checkout");
+
+        assert!(dispatched_with_headers(config.clone(), &[referer, ("x-tenant", "premium")]));
+        assert!(!dispatched_with_headers(config, &[referer, ("x-tenant", "basic")]));
+    }
+
+    fn unmatched_rules_config() -> Config {
+        Config {
+            auto_generate_session_id: false,
+            ..collection_rules_config(serde_json::json!({
+                "http": { "server": [{ "path": "^/api/payments$" }] }
+            }))
+        }
+    }
+
+    #[test]
+    fn test_unmatched_collection_rules_skip_requests_with_session() {
+        let mut ctx = session_context_for_path(unmatched_rules_config(), "/api/orders");
+        assert!(ctx.span_builder.has_session_id());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_requests_without_session_uploaded_regardless_of_rules() {
+        let mut ctx = context_for_path(unmatched_rules_config(), "/api/orders");
+        ctx.span_builder = ctx.span_builder.clone().with_context(&ctx.request_headers);
+        assert!(!ctx.span_builder.has_session_id());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    fn force_capture_config() -> Config {
+        Config {
+            sampling_rate: 0.0,
+            force_capture_header: Some("x-sp-force".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_force_capture_header_overrides_sampling() {
+        let mut ctx = context_for_path(force_capture_config(), "/api/orders");
+        ctx.request_headers.insert("x-sp-force".to_string(), "true".to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.forced"),
+            Some(crate::otel::any_value::Value::BoolValue(true))
+        );
+    }
+
+    #[test]
+    fn test_falsy_force_capture_header_keeps_sampling() {
+        let mut ctx = context_for_path(force_capture_config(), "/api/orders");
+        ctx.request_headers.insert("x-sp-force".to_string(), "0".to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    fn outbound_context_for_authority(authority: &str) -> SpHttpContext {
+        let config = Config {
+            outbound_ports: vec![8080],
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.url_host = Some(authority.to_string());
+        ctx.span_builder = ctx.span_builder.clone().with_traffic_direction("outbound".to_string());
+        ctx
+    }
+
+    #[test]
+    fn test_allowlisted_outbound_port_is_traced() {
+        let mut ctx = outbound_context_for_authority("orders.svc:8080");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_other_outbound_port_is_skipped() {
+        let mut ctx = outbound_context_for_authority("istiod.istio-system.svc:15012");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_batching_defers_the_per_request_dispatch() {
+        let config = Config {
+            batching: crate::config::BatchingConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Config::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    fn min_body_context(request_body: &[u8]) -> SpHttpContext {
+        let config = Config {
+            min_body_capture_bytes: 8,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_headers.insert("content-type".to_string(), "application/json".to_string());
+        ctx.request_body = request_body.to_vec();
+        ctx
+    }
+
+    #[test]
+    fn test_body_below_minimum_is_skipped_but_sized() {
+        let mut ctx = min_body_context(b"{}");
+
+        assert_eq!(extract_span_attribute(&mut ctx, "http.request.body"), None);
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body.size"),
+            Some(crate::otel::any_value::Value::IntValue(2))
+        );
+    }
+
+    #[test]
+    fn test_body_at_or_above_minimum_is_captured() {
+        let mut ctx = min_body_context(b"{\"id\":42}");
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.request.body"),
+            Some(crate::otel::any_value::Value::StringValue("{\"id\":42}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_grpc_message_counts_on_span() {
+        let mut ctx = context_for_path(Config::default(), "/shop.Orders/Watch");
+        ctx.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        ctx.request_body = vec![0, 0, 0, 0, 1, 0x08];
+        ctx.response_body = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "rpc.grpc.request.messages"),
+            Some(crate::otel::any_value::Value::IntValue(1))
+        );
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "rpc.grpc.response.messages"),
+            Some(crate::otel::any_value::Value::IntValue(2))
+        );
+    }
+
+    #[test]
+    fn test_grpc_stream_emits_a_child_span_per_message() {
+        let config = Config {
+            grpc_message_spans: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/shop.Orders/Watch");
+        ctx.span_builder = ctx.span_builder.clone().with_traffic_direction("inbound".to_string());
+        ctx.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        ctx.response_body = vec![0, 0, 0, 0, 1, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0x08, 0x01];
+        ctx.response_chunk_times = vec![(6, 100), (18, 300)];
+
+        let traces_data = ctx.build_extract_traces_data();
+        let spans = &traces_data.resource_spans[0].scope_spans[0].spans;
+        assert_eq!(spans.len(), 4);
+
+        let parent = &spans[0];
+        let children = &spans[1..];
+        let attribute = |span: &crate::otel::Span, key: &str| {
+            span.attributes.iter().find(|kv| kv.key == key).and_then(|kv| kv.value.clone()?.value)
+        };
+        for (index, child) in children.iter().enumerate() {
+            assert_eq!(child.parent_span_id, parent.span_id);
+            assert_eq!(child.trace_id, parent.trace_id);
+            assert_eq!(attribute(child, "rpc.message.type"), Some(StringValue("SENT".to_string())));
+            assert_eq!(attribute(child, "rpc.message.id"), Some(crate::otel::any_value::Value::IntValue(index as i64 + 1)));
+        }
+        assert_eq!(
+            children.iter().map(|child| child.start_time_unix_nano).collect::<Vec<_>>(),
+            vec![100, 300, 300]
+        );
+        assert_eq!(
+            attribute(&children[2], "rpc.message.uncompressed_size"),
+            Some(crate::otel::any_value::Value::IntValue(2))
+        );
+    }
+
+    #[test]
+    fn test_grpc_message_spans_are_opt_in() {
+        let mut ctx = context_for_path(Config::default(), "/shop.Orders/Watch");
+        ctx.request_headers.insert("content-type".to_string(), "application/grpc".to_string());
+        ctx.response_body = vec![0, 0, 0, 0, 1, 0x08];
+
+        let traces_data = ctx.build_extract_traces_data();
+        assert_eq!(traces_data.resource_spans[0].scope_spans[0].spans.len(), 1);
+    }
+
+    #[test]
+    fn test_save_response_releases_dispatch_slot() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+        assert!(ctx.holds_dispatch_slot);
+
+        let token = ctx.pending_save_call_token.unwrap();
+        ctx.on_http_call_response(token, 0, 0, 0);
+
+        assert!(!ctx.holds_dispatch_slot);
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_on_done_with_pending_save_keeps_slot_until_response() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.dispatch_async_extraction_save();
+        assert!(ctx.holds_dispatch_slot);
+
+        assert!(!ctx.on_done());
+        assert!(ctx.holds_dispatch_slot);
+
+        let token = ctx.pending_save_call_token.unwrap();
+        ctx.on_http_call_response(token, 0, 0, 0);
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_on_done_without_pending_save_finishes_at_once() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.span_dispatched = true;
+
+        assert!(ctx.on_done());
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_slot_held_until_save_and_shadow_respond() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            shadow_backend_url: Some("https://shadow.softprobe.ai".to_string()),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.dispatch_async_extraction_save();
+        let save_token = ctx.pending_save_call_token.unwrap();
+        let shadow_token = ctx.pending_shadow_call_token.unwrap();
+        assert!(!ctx.on_done());
+
+        ctx.on_http_call_response(save_token, 0, 0, 0);
+        assert!(ctx.holds_dispatch_slot);
+
+        ctx.on_http_call_response(shadow_token, 0, 0, 0);
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_batched_span_shadow_save_takes_a_slot() {
+        let mut config = Config {
+            max_inflight_dispatches: 4,
+            shadow_backend_url: Some("https://shadow.softprobe.ai".to_string()),
+            ..Default::default()
+        };
+        config.batching.enabled = true;
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+        assert!(ctx.pending_shadow_call_token.is_some());
+        assert!(ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_body_logs_take_a_slot() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            bodies_as_logs: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.request_body = b"{\"id\":1}".to_vec();
+        ctx.dispatch_async_extraction_save();
+        let save_token = ctx.pending_save_call_token.unwrap();
+        let logs_token = ctx.pending_logs_call_token.unwrap();
+
+        ctx.on_http_call_response(save_token, 0, 0, 0);
+        assert!(ctx.holds_dispatch_slot);
+
+        ctx.on_http_call_response(logs_token, 0, 0, 0);
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_unlimited_dispatches_take_no_slot() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_http_route_collapses_numeric_segments() {
+        let config = Config {
+            collapse_numeric_segments: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/items/0/sku");
+
+        ctx.update_url_info();
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "http.route"),
+            Some(crate::otel::any_value::Value::StringValue("/items/{n}/sku".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_no_http_route_by_default() {
+        let mut ctx = context_for_path(Config::default(), "/items/0/sku");
+
+        ctx.update_url_info();
+
+        assert_eq!(extract_span_attribute(&mut ctx, "http.route"), None);
+    }
+
+    #[test]
+    fn test_retried_save_carries_the_export_error() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.dispatch_async_extraction_save();
+
+        ctx.handle_save_response(503);
+
+        // The retry payload holds the bytes just re-dispatched
+        let payload = &ctx.retry_payload.as_ref().unwrap().payload;
+        let traces_data = TracesData::decode(payload.as_slice()).unwrap();
+        assert_eq!(
+            crate::otel::string_attribute(crate::otel::span_of(&traces_data), "sp.export.error"),
+            Some("server_error")
+        );
+    }
+
+    #[test]
+    fn test_retried_zipkin_save_carries_the_export_error() {
+        let config = Config {
+            export_format: ExportFormat::Zipkin,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.dispatch_async_extraction_save();
+
+        ctx.handle_save_response(429);
+
+        let payload = &ctx.retry_payload.as_ref().unwrap().payload;
+        let spans: serde_json::Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(spans[0]["tags"]["sp.export.error"], "client_error");
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable_status(429));
+        assert!(is_retryable_status(503));
+        assert!(!is_retryable_status(0));
+        assert!(!is_retryable_status(400));
+        assert!(!is_retryable_status(302));
+    }
+
+    #[test]
+    fn test_save_payload_kept_only_while_retries_remain() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.dispatch_async_extraction_save();
+        assert_eq!(ctx.save_attempts, 1);
+        assert!(ctx.retry_payload.is_some());
+
+        let config = Config {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.dispatch_async_extraction_save();
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(ctx.retry_payload.is_none());
+    }
+
+    #[test]
+    fn test_retries_stop_at_max_retries() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.save_attempts = DEFAULT_MAX_RETRIES;
+        assert!(ctx.has_save_retries_left());
+
+        ctx.save_attempts = DEFAULT_MAX_RETRIES + 1;
+        assert!(!ctx.has_save_retries_left());
+    }
+
+    #[test]
+    fn test_non_retryable_failure_gives_up() {
+        let config = Config {
+            max_inflight_dispatches: 4,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+        ctx.dispatch_async_extraction_save();
+
+        // No :status, as on a timeout, is not retried
+        let token = ctx.pending_save_call_token.unwrap();
+        ctx.on_http_call_response(token, 0, 0, 0);
+
+        assert!(ctx.pending_save_call_token.is_none());
+        assert!(ctx.retry_payload.is_none());
+        assert!(!ctx.holds_dispatch_slot);
+    }
+
+    #[test]
+    fn test_save_sent_on_done_is_retried_after_stream_end() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_start_time = Some(1);
+
+        // The span is sent on teardown, which waits for the save to settle
+        assert!(!ctx.on_done());
+        let first_token = ctx.pending_save_call_token.unwrap();
+
+        ctx.handle_save_response(503);
+        assert_eq!(ctx.save_attempts, 2);
+        assert!(ctx.pending_save_call_token.is_some_and(|token| token != first_token));
+        assert!(ctx.retry_payload.is_some());
+
+        ctx.handle_save_response(200);
+        assert!(ctx.pending_save_call_token.is_none());
+        assert!(ctx.retry_payload.is_none());
+    }
+
+    fn require_body_config() -> Config {
+        Config {
+            require_body_for_capture: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bodyless_exchange_skipped_when_body_required() {
+        let mut ctx = context_for_path(require_body_config(), "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_exchange_with_body_captured_when_body_required() {
+        let mut ctx = context_for_path(require_body_config(), "/api/orders");
+        ctx.request_body = b"{\"id\":1}".to_vec();
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_span_id_response_header_when_enabled() {
+        let config = Config {
+            expose_span_id_header: true,
+            ..Default::default()
+        };
+        let ctx = context_for_path(config, "/api/orders");
+
+        let (name, value) = ctx.span_id_response_header().unwrap();
+
+        assert_eq!(name, "x-sp-span-id");
+        assert_eq!(value, ctx.span_builder.get_current_span_id_hex());
+        assert_eq!(value.len(), 16);
+    }
+
+    #[test]
+    fn test_no_span_id_response_header_by_default() {
+        let ctx = context_for_path(Config::default(), "/api/orders");
+        assert!(ctx.span_id_response_header().is_none());
+    }
+
+    #[test]
+    fn test_unsampled_incoming_traceparent_emits_no_span() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_headers.insert(
+            "traceparent".to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00".to_string(),
+        );
+        ctx.span_builder = ctx.span_builder.clone().with_context(&ctx.request_headers);
+
+        ctx.apply_trace_context_injection();
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.request_headers["traceparent"].ends_with("-00"));
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_shadow_backend_receives_copy_of_span() {
+        let config = Config {
+            shadow_backend_url: Some("https://shadow.softprobe.ai".to_string()),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(ctx.pending_shadow_call_token.is_some());
+    }
+
+    #[test]
+    fn test_no_shadow_dispatch_without_shadow_backend() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+        assert!(ctx.pending_shadow_call_token.is_none());
+    }
+
+    #[test]
+    fn test_config_hash_attribute_on_span() {
+        let config = Config {
+            config_hash: "0a1b2c3d4e5f".to_string(),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/api/orders");
+
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "sp.config.hash"),
+            Some(StringValue("0a1b2c3d4e5f".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_istio_health_port_traffic_is_exempted() {
+        let mut ctx = context_for_path(Config::default(), "/healthz/ready");
+        ctx.request_headers.insert(":authority".to_string(), "10.0.0.7:15021".to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_application_port_traffic_is_not_infra_exempted() {
+        let mut ctx = context_for_path(Config::default(), "/healthz/ready");
+        ctx.request_headers.insert(":authority".to_string(), "orders.svc:8080".to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_infra_port_exemption_can_be_disabled() {
+        let config = Config {
+            infra_ports: vec![],
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/healthz/ready");
+        ctx.request_headers.insert(":authority".to_string(), "10.0.0.7:15021".to_string());
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+
+    #[test]
+    fn test_path_secret_segments_are_masked_when_enabled() {
+        let config = Config {
+            mask_path_secrets: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/reset/9f86d081884c7d659a2feaa0c55ad015a3bf4f1b");
+        ctx.url_path = None;
+        ctx.update_url_info();
+
+        assert_eq!(ctx.url_path.as_deref(), Some("/reset/9f8*********************************4f1b"));
+
+        let mut unmasked = context_for_path(Config::default(), "/reset/9f86d081884c7d659a2feaa0c55ad015a3bf4f1b");
+        unmasked.url_path = None;
+        unmasked.update_url_info();
+        assert_eq!(unmasked.url_path.as_deref(), Some("/reset/9f86d081884c7d659a2feaa0c55ad015a3bf4f1b"));
+    }
+
+    #[test]
+    fn test_original_path_header_is_masked_when_enabled() {
+        let config = Config {
+            mask_path_secrets: true,
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/reset");
+        ctx.request_headers.insert(
+            "x-envoy-original-path".to_string(),
+            "/public/reset/9f86d081884c7d659a2feaa0c55ad015a3bf4f1b".to_string(),
+        );
+        ctx.update_url_info();
+
+        let traces_data = ctx.build_extract_traces_data();
+        assert_eq!(
+            crate::otel::string_attribute(crate::otel::span_of(&traces_data), "http.request.header.x-envoy-original-path"),
+            Some("/public/reset/9f8*********************************4f1b")
+        );
+    }
+
+    #[test]
+    fn test_original_path_emitted_only_when_rewritten() {
+        let mut ctx = context_for_path(Config::default(), "/api/orders");
+        ctx.request_headers.insert("x-envoy-original-path".to_string(), "/api/orders".to_string());
+        ctx.update_url_info();
+        assert_eq!(extract_span_attribute(&mut ctx, "url.path.original"), None);
+
+        let mut ctx = context_for_path(Config::default(), "/orders");
+        ctx.request_headers.insert("x-envoy-original-path".to_string(), "/api/orders".to_string());
+        ctx.update_url_info();
+        assert_eq!(
+            extract_span_attribute(&mut ctx, "url.path.original"),
+            Some(crate::otel::any_value::Value::StringValue("/api/orders".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_original_path_respects_max_url_length() {
+        let config = Config {
+            max_url_length: Some(24),
+            ..Default::default()
+        };
+        let mut ctx = context_for_path(config, "/orders");
+        let original_path = format!("/api/orders?filter={}", "x".repeat(64));
+        ctx.request_headers.insert("x-envoy-original-path".to_string(), original_path);
+        ctx.update_url_info();
+
+        let traces_data = ctx.build_extract_traces_data();
+        let emitted = crate::otel::string_attribute(crate::otel::span_of(&traces_data), "url.path.original").unwrap();
+        assert!(emitted.len() <= 24);
+        assert!(emitted.ends_with(crate::http_helpers::URL_TRUNCATION_MARKER));
+    }
+
+    fn exempted_health_config(emit_exemption_marker: bool) -> Config {
+        Config {
+            exemption_rules: vec![crate::config::ExemptionRule::new(vec![], vec!["^/healthz$".to_string()])],
+            emit_exemption_marker,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_exempted_request_produces_no_span() {
+        let mut ctx = context_for_path(exempted_health_config(false), "/healthz");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_none());
+    }
+
+    #[test]
+    fn test_exempted_request_produces_marker_span_when_enabled() {
+        let mut ctx = context_for_path(exempted_health_config(true), "/healthz");
+
+        ctx.dispatch_async_extraction_save();
+
+        assert!(ctx.pending_save_call_token.is_some());
+    }
+}
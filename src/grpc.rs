@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+/// Length of the gRPC message prefix: 1-byte compressed flag + 4-byte big-endian length
+const GRPC_FRAME_HEADER_LEN: usize = 5;
+
+/// Whether the request is a gRPC call, based on its content-type
+pub fn is_grpc(request_headers: &HashMap<String, String>) -> bool {
+    request_headers
+        .get("content-type")
+        .is_some_and(|content_type| content_type.to_ascii_lowercase().starts_with("application/grpc"))
+}
+
+/// Numeric `grpc-status` from trailers (or headers of a trailers-only response)
+pub fn status_code(headers: &HashMap<String, String>) -> Option<i64> {
+    headers.get("grpc-status").and_then(|status| status.trim().parse::<i64>().ok())
+}
+
+/// `service/method` of a gRPC `:path` such as `/pkg.Service/Method`
+pub fn method_from_path(path: &str) -> Option<&str> {
+    let method = path.strip_prefix('/')?;
+    match method.split_once('/') {
+        Some((service, name)) if !service.is_empty() && !name.is_empty() && !name.contains('/') => Some(method),
+        _ => None,
+    }
+}
+
+/// Whether message bodies are captured for the call; an empty allowlist captures all methods
+pub fn is_body_capture_allowed(capture_methods: &[String], path: Option<&str>) -> bool {
+    if capture_methods.is_empty() {
+        return true;
+    }
+    path.and_then(method_from_path)
+        .is_some_and(|method| capture_methods.iter().any(|allowed| allowed == method))
+}
+
+/// A complete length-prefixed gRPC message within a body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub offset: usize,  // Start of the 5-byte prefix
+    pub len: usize,  // Message length, excluding the prefix
+    pub compressed: bool,
+}
+
+impl Frame {
+    /// Offset just past the message
+    pub fn end(&self) -> usize {
+        self.offset + GRPC_FRAME_HEADER_LEN + self.len
+    }
+}
+
+/// The complete length-prefixed gRPC messages in a body, in order.
+/// A trailing partial frame (e.g. a truncated capture) is not returned.
+pub fn frames(body: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while body.len() - offset >= GRPC_FRAME_HEADER_LEN {
+        let length_bytes = [body[offset + 1], body[offset + 2], body[offset + 3], body[offset + 4]];
+        let frame = Frame {
+            offset,
+            len: u32::from_be_bytes(length_bytes) as usize,
+            compressed: body[offset] & 1 == 1,
+        };
+        if body.len() < frame.end() {
+            break;
+        }
+        offset = frame.end();
+        frames.push(frame);
+    }
+    frames
+}
+
+/// Count the complete length-prefixed gRPC messages in a body
+pub fn count_messages(body: &[u8]) -> usize {
+    frames(body).len()
+}
+
+/// When the byte at `end - 1` arrived, given `(buffered length, timestamp)` pairs
+/// recorded after each body chunk
+pub fn arrival_time(chunk_times: &[(usize, u64)], end: usize) -> Option<u64> {
+    chunk_times
+        .iter()
+        .find(|(buffered, _)| *buffered >= end)
+        .map(|(_, timestamp)| *timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut framed = vec![0];
+        framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        framed.extend_from_slice(payload);
+        framed
+    }
+
+    #[test]
+    fn test_count_messages_in_multi_message_body() {
+        let mut body = frame(b"\x08\x01");
+        body.extend(frame(b""));
+        body.extend(frame(b"\x0a\x03abc"));
+
+        assert_eq!(count_messages(&body), 3);
+    }
+
+    #[test]
+    fn test_count_messages_ignores_partial_frame() {
+        let mut body = frame(b"\x08\x01");
+        body.extend_from_slice(&[0, 0, 0, 0, 9, 1, 2]);
+
+        assert_eq!(count_messages(&body), 1);
+        assert_eq!(count_messages(&[]), 0);
+    }
+
+    #[test]
+    fn test_frames_report_offsets_and_compression() {
+        let mut body = frame(b"\x08\x01");
+        let mut compressed = frame(b"abc");
+        compressed[0] = 1;
+        body.extend(compressed);
+
+        assert_eq!(
+            frames(&body),
+            vec![
+                Frame { offset: 0, len: 2, compressed: false },
+                Frame { offset: 7, len: 3, compressed: true },
+            ]
+        );
+        assert_eq!(frames(&body)[1].end(), body.len());
+    }
+
+    #[test]
+    fn test_arrival_time_uses_first_chunk_covering_the_frame() {
+        let chunk_times = [(4, 100), (12, 200), (20, 300)];
+
+        assert_eq!(arrival_time(&chunk_times, 4), Some(100));
+        assert_eq!(arrival_time(&chunk_times, 5), Some(200));
+        assert_eq!(arrival_time(&chunk_times, 20), Some(300));
+        assert_eq!(arrival_time(&chunk_times, 21), None);
+    }
+
+    #[test]
+    fn test_is_grpc() {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/grpc+proto".to_string());
+        assert!(is_grpc(&headers));
+
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        assert!(!is_grpc(&headers));
+    }
+
+    #[test]
+    fn test_method_from_path() {
+        assert_eq!(method_from_path("/orders.v1.OrderService/CreateOrder"), Some("orders.v1.OrderService/CreateOrder"));
+        assert_eq!(method_from_path("/orders.v1.OrderService"), None);
+        assert_eq!(method_from_path("/a/b/c"), None);
+        assert_eq!(method_from_path("orders.v1.OrderService/CreateOrder"), None);
+    }
+
+    #[test]
+    fn test_is_body_capture_allowed() {
+        let methods = vec!["orders.v1.OrderService/CreateOrder".to_string()];
+
+        assert!(is_body_capture_allowed(&methods, Some("/orders.v1.OrderService/CreateOrder")));
+        assert!(!is_body_capture_allowed(&methods, Some("/orders.v1.OrderService/ListOrders")));
+        assert!(!is_body_capture_allowed(&methods, None));
+        assert!(is_body_capture_allowed(&[], Some("/orders.v1.OrderService/ListOrders")));
+    }
+
+    #[test]
+    fn test_status_code() {
+        let mut trailers = HashMap::new();
+        assert_eq!(status_code(&trailers), None);
+
+        trailers.insert("grpc-status".to_string(), "14".to_string());
+        assert_eq!(status_code(&trailers), Some(14));
+
+        trailers.insert("grpc-status".to_string(), "unavailable".to_string());
+        assert_eq!(status_code(&trailers), None);
+    }
+}
@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 
-/// Detect service name from headers or configuration
+/// Detect service name from configuration, headers, or the workload's SPIFFE identity
 pub fn detect_service_name(
     request_headers: &HashMap<String, String>,
     config_service_name: &str,
+    spiffe_id: Option<&str>,
 ) -> String {
     // Use configured service_name if it's not default
     if !config_service_name.is_empty() && config_service_name != "default-service" {
@@ -20,14 +21,56 @@ pub fn detect_service_name(
             }
         }
     }
+
+    if let Some(service_name) = spiffe_id.and_then(service_name_from_spiffe) {
+        crate::sp_debug!("Got service_name from SPIFFE identity: {}", service_name);
+        return service_name;
+    }
     config_service_name.to_string()
 }
 
+/// Service name configured in `service_name_by_host` for the request's authority.
+/// The full authority is tried first, then the host without its port.
+pub fn service_name_for_host<'a>(
+    request_headers: &HashMap<String, String>,
+    service_name_by_host: &'a HashMap<String, String>,
+) -> Option<&'a str> {
+    if service_name_by_host.is_empty() {
+        return None;
+    }
+    let authority = request_headers
+        .get(":authority")
+        .or_else(|| request_headers.get("host"))?
+        .to_ascii_lowercase();
+    let service_name = service_name_by_host
+        .get(&authority)
+        .or_else(|| service_name_by_host.get(crate::traffic::strip_port(&authority)))?;
+    crate::sp_debug!("Got service_name from service_name_by_host: {} -> {}", authority, service_name);
+    Some(service_name)
+}
+
+/// Derive `<service-account>.<namespace>` from an Istio SPIFFE URI
+/// (`spiffe://<trust-domain>/ns/<namespace>/sa/<service-account>`)
+pub fn service_name_from_spiffe(spiffe_id: &str) -> Option<String> {
+    let path = spiffe_id.trim().strip_prefix("spiffe://")?;
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+    match segments.as_slice() {
+        ["ns", namespace, "sa", service_account] if !namespace.is_empty() && !service_account.is_empty() => {
+            Some(format!("{}.{}", service_account, namespace))
+        }
+        _ => None,
+    }
+}
+
+/// W3C trace-context limit on tracestate list members
+pub const DEFAULT_MAX_TRACESTATE_ENTRIES: usize = 32;
+
 /// Build new tracestate with x-sp-traceparent entry
 pub fn build_new_tracestate(
     request_headers: &HashMap<String, String>,
     traceparent_value: &str,
     session_id: &str,
+    max_entries: usize,
 ) -> String {
     let mut tracestate_entries = Vec::new();
     let mut has_sp_session_id = false;
@@ -36,6 +79,9 @@ pub fn build_new_tracestate(
         // Parse existing tracestate, preserve other entries
         for entry in existing_tracestate.split(',') {
             let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
             if entry.starts_with("x-sp-session-id=") {
                 has_sp_session_id = true;
                 tracestate_entries.push(entry.to_string());
@@ -53,12 +99,87 @@ pub fn build_new_tracestate(
         tracestate_entries.insert(1, format!("x-sp-session-id={}", session_id));
     }
 
+    // Our entries lead the list, so dropping from the right sheds other vendors first
+    let max_entries = max_entries.max(1);
+    if tracestate_entries.len() > max_entries {
+        crate::sp_debug!(
+            "Dropping {} tracestate entries beyond the limit of {}",
+            tracestate_entries.len() - max_entries,
+            max_entries
+        );
+        tracestate_entries.truncate(max_entries);
+    }
+
     let new_tracestate = tracestate_entries.join(",");
     crate::sp_debug!("Adding x-sp-traceparent/x-sp-session-id to tracestate: {}", new_tracestate);
 
     new_tracestate
 }
 
+/// W3C baggage limits on list members and on the size of the header
+pub const MAX_BAGGAGE_ENTRIES: usize = 180;
+pub const MAX_BAGGAGE_BYTES: usize = 8192;
+
+/// A `baggage` list member; `entry` is the member as received, properties included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaggageEntry {
+    pub key: String,
+    pub value: String,
+    pub entry: String,
+}
+
+/// Parse a W3C `baggage` header, dropping malformed members and any beyond
+/// MAX_BAGGAGE_ENTRIES or MAX_BAGGAGE_BYTES
+pub fn parse_baggage(header: &str) -> Vec<BaggageEntry> {
+    let mut entries: Vec<BaggageEntry> = Vec::new();
+    let mut bytes = 0;
+    for member in header.split(',').map(str::trim).filter(|member| !member.is_empty()) {
+        let Some(entry) = parse_baggage_member(member) else {
+            crate::sp_debug!("Dropping malformed baggage entry: {}", member);
+            continue;
+        };
+        // Counted as re-emitted, joined with ','
+        let joined_bytes = bytes + usize::from(!entries.is_empty()) + member.len();
+        if entries.len() == MAX_BAGGAGE_ENTRIES || joined_bytes > MAX_BAGGAGE_BYTES {
+            crate::sp_debug!("Dropping baggage entries beyond the W3C limits");
+            break;
+        }
+        bytes = joined_bytes;
+        entries.push(entry);
+    }
+    entries
+}
+
+/// The `baggage` header carrying the given entries
+pub fn format_baggage(entries: &[BaggageEntry]) -> String {
+    entries.iter().map(|entry| entry.entry.as_str()).collect::<Vec<_>>().join(",")
+}
+
+/// `key=value` followed by optional `;property` parts, which are kept but not parsed
+fn parse_baggage_member(member: &str) -> Option<BaggageEntry> {
+    let key_value = member.split(';').next()?;
+    let (key, value) = key_value.split_once('=')?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || !key.bytes().all(is_token_char) || !value.bytes().all(is_baggage_octet) {
+        return None;
+    }
+    Some(BaggageEntry {
+        key: key.to_string(),
+        value: value.to_string(),
+        entry: member.to_string(),
+    })
+}
+
+/// RFC 7230 token characters, allowed in baggage keys
+fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+/// Printable US-ASCII except space, '"', ',', ';' and '\', allowed in baggage values
+fn is_baggage_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,7 +190,7 @@ mod tests {
         let headers = HashMap::new();
         let config_name = "my-service";
         
-        let result = detect_service_name(&headers, config_name);
+        let result = detect_service_name(&headers, config_name, None);
         assert_eq!(result, "my-service");
     }
 
@@ -78,7 +199,7 @@ mod tests {
         let headers = HashMap::new();
         let config_name = "default-service";
         
-        let result = detect_service_name(&headers, config_name);
+        let result = detect_service_name(&headers, config_name, None);
         assert_eq!(result, "default-service");
     }
 
@@ -88,7 +209,7 @@ mod tests {
         headers.insert("x-sp-service-name".to_string(), "header-service".to_string());
         let config_name = "default-service";
         
-        let result = detect_service_name(&headers, config_name);
+        let result = detect_service_name(&headers, config_name, None);
         assert_eq!(result, "header-service");
     }
 
@@ -98,7 +219,7 @@ mod tests {
         headers.insert("x-sp-service-name".to_string(), "header-service".to_string());
         let config_name = "my-service";
         
-        let result = detect_service_name(&headers, config_name);
+        let result = detect_service_name(&headers, config_name, None);
         assert_eq!(result, "my-service"); // Config takes precedence if not default
     }
 
@@ -108,15 +229,39 @@ mod tests {
         headers.insert("x-sp-service-name".to_string(), "".to_string());
         let config_name = "default-service";
         
-        let result = detect_service_name(&headers, config_name);
+        let result = detect_service_name(&headers, config_name, None);
         assert_eq!(result, "default-service");
     }
 
     #[test]
-    fn test_build_new_tracestate_with_no_existing() {
+    fn test_service_name_from_spiffe() {
+        assert_eq!(
+            service_name_from_spiffe("spiffe://cluster.local/ns/shop/sa/orders"),
+            Some("orders.shop".to_string())
+        );
+        assert_eq!(service_name_from_spiffe("spiffe://cluster.local/ns/shop"), None);
+        assert_eq!(service_name_from_spiffe("spiffe://cluster.local/ns//sa/orders"), None);
+        assert_eq!(service_name_from_spiffe("https://cluster.local/ns/shop/sa/orders"), None);
+    }
+
+    #[test]
+    fn test_detect_service_name_falls_back_to_spiffe() {
+        let headers = HashMap::new();
+        let spiffe_id = Some("spiffe://cluster.local/ns/shop/sa/orders");
+
+        assert_eq!(detect_service_name(&headers, "default-service", spiffe_id), "orders.shop");
+        assert_eq!(detect_service_name(&headers, "payments", spiffe_id), "payments");
+
         let mut headers = HashMap::new();
+        headers.insert("x-sp-service-name".to_string(), "header-service".to_string());
+        assert_eq!(detect_service_name(&headers, "default-service", spiffe_id), "header-service");
+    }
+
+    #[test]
+    fn test_build_new_tracestate_with_no_existing() {
+        let headers = HashMap::new();
         let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
-        let result = build_new_tracestate(&headers, traceparent, "");
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
         assert!(result.starts_with("x-sp-traceparent="));
     }
 
@@ -125,7 +270,7 @@ mod tests {
         let mut headers = HashMap::new();
         headers.insert("tracestate".to_string(), "vendor1=value1,vendor2=value2".to_string());
         let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
-        let result = build_new_tracestate(&headers, traceparent, "");
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
         assert!(result.contains("vendor1=value1"));
         assert!(result.contains("vendor2=value2"));
         assert!(result.starts_with("x-sp-traceparent="));
@@ -136,7 +281,7 @@ mod tests {
         let mut headers = HashMap::new();
         headers.insert("tracestate".to_string(), "x-sp-traceparent=old-value,vendor1=value1".to_string());
         let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
-        let result = build_new_tracestate(&headers, traceparent, "");
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
         assert!(result.starts_with("x-sp-traceparent="));
         assert!(result.contains("vendor1=value1"));
         assert!(!result.contains("old-value"));
@@ -147,7 +292,7 @@ mod tests {
         let mut headers = HashMap::new();
         headers.insert("tracestate".to_string(), " vendor1=value1 , vendor2=value2 ".to_string());
         let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
-        let result = build_new_tracestate(&headers, traceparent, "");
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
         assert!(result.contains("vendor1=value1"));
         assert!(result.contains("vendor2=value2"));
     }
@@ -157,7 +302,92 @@ mod tests {
         let mut headers = HashMap::new();
         headers.insert("tracestate".to_string(), "".to_string());
         let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
-        let result = build_new_tracestate(&headers, traceparent, "");
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
         assert!(result.starts_with("x-sp-traceparent="));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_build_new_tracestate_caps_entries() {
+        let mut headers = HashMap::new();
+        let existing: Vec<String> = (0..10).map(|i| format!("vendor{}=v{}", i, i)).collect();
+        headers.insert("tracestate".to_string(), existing.join(","));
+        let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
+
+        let result = build_new_tracestate(&headers, traceparent, "sp-session-1", 5);
+        let entries: Vec<&str> = result.split(',').collect();
+
+        assert_eq!(entries.len(), 5);
+        assert!(entries[0].starts_with("x-sp-traceparent="));
+        assert_eq!(entries[1], "x-sp-session-id=sp-session-1");
+        assert_eq!(&entries[2..], &["vendor0=v0", "vendor1=v1", "vendor2=v2"]);
+    }
+
+    #[test]
+    fn test_service_name_for_host_maps_hosts_to_services() {
+        let by_host: HashMap<String, String> = [
+            ("orders.shop.local", "orders"),
+            ("payments.shop.local", "payments"),
+            ("payments.shop.local:8443", "payments-admin"),
+        ]
+        .iter()
+        .map(|(host, name)| (host.to_string(), name.to_string()))
+        .collect();
+
+        let headers_for = |authority: &str| {
+            let mut headers = HashMap::new();
+            headers.insert(":authority".to_string(), authority.to_string());
+            headers
+        };
+
+        assert_eq!(service_name_for_host(&headers_for("orders.shop.local"), &by_host), Some("orders"));
+        assert_eq!(service_name_for_host(&headers_for("Orders.Shop.Local:8080"), &by_host), Some("orders"));
+        assert_eq!(service_name_for_host(&headers_for("payments.shop.local"), &by_host), Some("payments"));
+        assert_eq!(service_name_for_host(&headers_for("payments.shop.local:8443"), &by_host), Some("payments-admin"));
+        assert_eq!(service_name_for_host(&headers_for("catalog.shop.local"), &by_host), None);
+        assert_eq!(service_name_for_host(&HashMap::new(), &by_host), None);
+    }
+
+    #[test]
+    fn test_build_new_tracestate_under_cap_keeps_all_entries() {
+        let mut headers = HashMap::new();
+        headers.insert("tracestate".to_string(), "vendor1=value1,vendor2=value2".to_string());
+        let traceparent = "00-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbbbbbbbbbbbbbbb-01";
+
+        let result = build_new_tracestate(&headers, traceparent, "", DEFAULT_MAX_TRACESTATE_ENTRIES);
+
+        assert_eq!(result.split(',').count(), 3);
+    }
+
+    #[test]
+    fn test_parse_baggage() {
+        let entries = parse_baggage("userId=123, tenant=acme;ttl=60");
+
+        assert_eq!(
+            entries,
+            vec![
+                BaggageEntry { key: "userId".to_string(), value: "123".to_string(), entry: "userId=123".to_string() },
+                BaggageEntry { key: "tenant".to_string(), value: "acme".to_string(), entry: "tenant=acme;ttl=60".to_string() },
+            ]
+        );
+        assert_eq!(format_baggage(&entries), "userId=123,tenant=acme;ttl=60");
+    }
+
+    #[test]
+    fn test_parse_baggage_drops_malformed_entries() {
+        let entries = parse_baggage("novalue,=empty-key,bad key=1,quoted=\"x\",ok=%20yes,,");
+
+        assert_eq!(format_baggage(&entries), "ok=%20yes");
+    }
+
+    #[test]
+    fn test_parse_baggage_respects_limits() {
+        let many = (0..200).map(|i| format!("k{}=v", i)).collect::<Vec<_>>().join(",");
+        assert_eq!(parse_baggage(&many).len(), MAX_BAGGAGE_ENTRIES);
+
+        let large_value = "x".repeat(5000);
+        let header = format!("a={},b={}", large_value, large_value);
+        let entries = parse_baggage(&header);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, "a");
+    }
+}
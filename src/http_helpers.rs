@@ -59,8 +59,76 @@ pub fn extract_client_info(request_headers: &HashMap<String, String>) -> (Option
     (client_host, client_path)
 }
 
+/// Marker appended to URLs cut down to max_url_length
+pub const URL_TRUNCATION_MARKER: &str = "...";
+
+/// Truncate a path+query to at most max_len bytes (marker included).
+/// Returns None when the URL already fits.
+pub fn truncate_url(url: &str, max_len: usize) -> Option<String> {
+    if url.len() <= max_len {
+        return None;
+    }
+
+    let mut cut = max_len.saturating_sub(URL_TRUNCATION_MARKER.len());
+    while cut > 0 && !url.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    Some(format!("{}{}", &url[..cut], URL_TRUNCATION_MARKER))
+}
+
+/// Route of a path with every all-digit segment replaced by `{n}`, query dropped
+pub fn collapse_numeric_segments(path_and_query: &str) -> String {
+    let path = path_and_query.split(['?', '#']).next().unwrap_or_default();
+    path.split('/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+                "{n}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Compose `url.full` from its parts, dropping any `user:password@` from the host
+pub fn build_url_full(scheme: &str, host: &str, path_and_query: &str) -> String {
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    format!("{}://{}{}", scheme, host, path_and_query)
+}
+
+/// Scheme prefix for backends reached over a Unix domain socket
+pub const UNIX_SOCKET_SCHEME: &str = "unix://";
+
+/// Authority sent to a Unix domain socket backend, which has no host of its own
+pub const UNIX_SOCKET_AUTHORITY: &str = "localhost";
+
+/// Longest socket path that fits in `sockaddr_un.sun_path` with its terminator
+const MAX_UNIX_SOCKET_PATH: usize = 107;
+
+/// Socket path of a `unix:///path/to.sock` backend URL, None if not a valid UDS URL
+pub fn unix_socket_path(backend_url: &str) -> Option<&str> {
+    let path = backend_url.strip_prefix(UNIX_SOCKET_SCHEME)?;
+    let valid = path.starts_with('/')
+        && path.len() <= MAX_UNIX_SOCKET_PATH
+        && !path.ends_with('/')
+        && !path.contains(['\0', '?', '#'])
+        && !path.split('/').any(|segment| segment == "..");
+    if !valid {
+        crate::sp_warn!("Invalid Unix socket backend path: {}", path);
+        return None;
+    }
+    Some(path)
+}
+
 /// Get backend authority from URL
 pub fn get_backend_authority(backend_url: &str) -> String {
+    if backend_url.starts_with(UNIX_SOCKET_SCHEME) {
+        return match unix_socket_path(backend_url) {
+            Some(_) => UNIX_SOCKET_AUTHORITY.to_string(),
+            None => "o.softprobe.ai".to_string(),
+        };
+    }
     match Url::parse(backend_url) {
         Ok(url) => {
             if let Some(host) = url.host_str() {
@@ -89,8 +157,16 @@ pub fn get_backend_authority(backend_url: &str) -> String {
     }
 }
 
-/// Build Envoy cluster name from backend URL
+/// Build Envoy cluster name from backend URL.
+/// A `unix://` backend maps to `unix:<path>`, the name of the static cluster
+/// whose endpoint is the pipe address at that path.
 pub fn get_backend_cluster_name(backend_url: &str) -> String {
+    if backend_url.starts_with(UNIX_SOCKET_SCHEME) {
+        return match unix_socket_path(backend_url) {
+            Some(path) => format!("unix:{}", path),
+            None => "outbound|443||o.softprobe.ai".to_string(),
+        };
+    }
     match Url::parse(backend_url) {
         Ok(url) => {
             if let Some(host) = url.host_str() {
@@ -247,6 +323,26 @@ mod tests {
         assert_eq!(cluster, "outbound|443||o.softprobe.ai");
     }
 
+    #[test]
+    fn test_unix_socket_backend() {
+        let backend_url = "unix:///var/run/otel.sock";
+
+        assert_eq!(unix_socket_path(backend_url), Some("/var/run/otel.sock"));
+        assert_eq!(get_backend_cluster_name(backend_url), "unix:/var/run/otel.sock");
+        assert_eq!(get_backend_authority(backend_url), "localhost");
+    }
+
+    #[test]
+    fn test_unix_socket_backend_invalid_path() {
+        for backend_url in ["unix://var/run/otel.sock", "unix://", "unix:///var/run/", "unix:///var/../otel.sock"] {
+            assert_eq!(unix_socket_path(backend_url), None, "{}", backend_url);
+            assert_eq!(get_backend_cluster_name(backend_url), "outbound|443||o.softprobe.ai");
+            assert_eq!(get_backend_authority(backend_url), "o.softprobe.ai");
+        }
+        let too_long = format!("unix:///{}", "a".repeat(MAX_UNIX_SOCKET_PATH));
+        assert_eq!(unix_socket_path(&too_long), None);
+    }
+
     #[test]
     fn test_extract_client_info_no_headers() {
         let headers = HashMap::new();
@@ -255,4 +351,53 @@ mod tests {
         assert_eq!(host, None);
         assert_eq!(path, None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_truncate_url_within_limit() {
+        assert_eq!(truncate_url("/api/v1/users?id=1", 64), None);
+        assert_eq!(truncate_url("/exact", 6), None);
+    }
+
+    #[test]
+    fn test_truncate_url_very_long_query() {
+        let url = format!("/upload?data={}", "QUJD".repeat(1000));
+        let truncated = truncate_url(&url, 64).unwrap();
+
+        assert_eq!(truncated.len(), 64);
+        assert!(truncated.starts_with("/upload?data=QUJD"));
+        assert!(truncated.ends_with(URL_TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_truncate_url_respects_char_boundaries() {
+        let url = "/search?q=日本語日本語";
+        let truncated = truncate_url(url, 16).unwrap();
+
+        assert!(truncated.len() <= 16);
+        assert!(truncated.ends_with(URL_TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_build_url_full() {
+        assert_eq!(
+            build_url_full("https", "shop.example.com:8443", "/api/orders?page=2"),
+            "https://shop.example.com:8443/api/orders?page=2"
+        );
+    }
+
+    #[test]
+    fn test_build_url_full_strips_credentials() {
+        assert_eq!(
+            build_url_full("http", "alice:hunter2@shop.example.com", "/api/orders"),
+            "http://shop.example.com/api/orders"
+        );
+    }
+
+    #[test]
+    fn test_collapse_numeric_segments() {
+        assert_eq!(collapse_numeric_segments("/items/0/sku"), "/items/{n}/sku");
+        assert_eq!(collapse_numeric_segments("/orders/42/lines/7?expand=true"), "/orders/{n}/lines/{n}");
+        assert_eq!(collapse_numeric_segments("/v2/items/a1"), "/v2/items/a1");
+        assert_eq!(collapse_numeric_segments("/"), "/");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::shared_data::retry_cas;
+
+/// Shared-data key holding the number of backend dispatches awaiting a response
+pub const INFLIGHT_DISPATCHES_KEY: &str = "sp.inflight_dispatches";
+
+/// Counter storage shared by all contexts of the VM
+pub trait SharedCounterStore {
+    /// Current value and its CAS token
+    fn load_counter(&self, key: &str) -> (u32, Option<u32>);
+    /// Store the value if the CAS token still matches; false on mismatch
+    fn store_counter(&self, key: &str, value: u32, cas: Option<u32>) -> bool;
+}
+
+/// Take a dispatch slot unless `limit` dispatches are already in flight
+pub fn try_acquire<S: SharedCounterStore + ?Sized>(store: &S, limit: u32) -> bool {
+    let acquired = retry_cas(|| {
+        let (inflight, cas) = store.load_counter(INFLIGHT_DISPATCHES_KEY);
+        if inflight >= limit {
+            return Some(false);
+        }
+        store.store_counter(INFLIGHT_DISPATCHES_KEY, inflight + 1, cas).then_some(true)
+    });
+    acquired.unwrap_or_else(|| {
+        crate::sp_warn!("Could not update in-flight dispatch count, treating limit as reached");
+        false
+    })
+}
+
+/// Give back a slot taken by `try_acquire`
+pub fn release<S: SharedCounterStore + ?Sized>(store: &S) {
+    let released = retry_cas(|| {
+        let (inflight, cas) = store.load_counter(INFLIGHT_DISPATCHES_KEY);
+        store.store_counter(INFLIGHT_DISPATCHES_KEY, inflight.saturating_sub(1), cas).then_some(())
+    });
+    if released.is_none() {
+        crate::sp_warn!("Could not release in-flight dispatch slot");
+    }
+}
+
+/// Decode a counter stored as little-endian u32; anything else reads as 0
+pub fn decode_counter(bytes: Option<&[u8]>) -> u32 {
+    bytes
+        .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+        .map(u32::from_le_bytes)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_data::testing::MockStore;
+
+    #[test]
+    fn test_limit_gates_dispatches() {
+        let store = MockStore::default();
+
+        assert!(try_acquire(&store, 2));
+        assert!(try_acquire(&store, 2));
+        assert!(!try_acquire(&store, 2));
+        assert_eq!(store.load_counter(INFLIGHT_DISPATCHES_KEY).0, 2);
+    }
+
+    #[test]
+    fn test_release_frees_a_slot() {
+        let store = MockStore::default();
+        assert!(try_acquire(&store, 1));
+        assert!(!try_acquire(&store, 1));
+
+        release(&store);
+
+        assert!(try_acquire(&store, 1));
+    }
+
+    #[test]
+    fn test_release_never_goes_negative() {
+        let store = MockStore::default();
+        release(&store);
+        assert_eq!(store.load_counter(INFLIGHT_DISPATCHES_KEY).0, 0);
+    }
+
+    #[test]
+    fn test_decode_counter() {
+        assert_eq!(decode_counter(Some(&3u32.to_le_bytes())), 3);
+        assert_eq!(decode_counter(Some(b"x")), 0);
+        assert_eq!(decode_counter(None), 0);
+    }
+}
@@ -1,5 +1,6 @@
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
+use std::collections::HashMap;
 
 mod otel;
 mod config;
@@ -10,9 +11,23 @@ mod context;
 mod http_helpers;
 mod trace_context;
 mod logging;
+mod properties;
+mod masking;
+mod sampling;
+mod zipkin;
+mod grpc;
+mod inflight;
+mod shared_data;
+mod metrics;
+mod compression;
+mod span_buffer;
+mod batch;
 
+use crate::batch::Batch;
 use crate::config::Config;
 use crate::context::SpHttpContext;
+use crate::metrics::ExportError;
+use crate::traffic::{NodeMetadata, RequestHeadersAccess};
 // Main entry point for the WASM module
 proxy_wasm::main! {{
     // It's required to set the log level explicitly for the WASM module log to work correctly
@@ -25,17 +40,140 @@ proxy_wasm::main! {{
 
 struct SpRootContext {
     config: Config,
+    node_metadata: Option<NodeMetadata>,  // Node metadata never changes, read on first configure
+    node_metadata_keys: Vec<String>,  // node_metadata_attributes the cached metadata was read with
+    pending_flushes: HashMap<u32, PendingFlush>,  // Batch POSTs that may still be retried, by call token
+}
+
+/// A flushed batch kept until its response in case it needs re-sending
+struct PendingFlush {
+    batch: Batch,
+    attempts: u32,
 }
 
 impl SpRootContext {
     fn new() -> Self {
         Self {
             config: Config::default(),
+            node_metadata: None,
+            node_metadata_keys: vec![],
+            pending_flushes: HashMap::new(),
+        }
+    }
+
+    fn ensure_node_metadata(&mut self) {
+        // Re-read only when the configured attribute keys change
+        if self.node_metadata.is_none() || self.node_metadata_keys != self.config.node_metadata_attributes {
+            let node_metadata = NodeMetadata::read(self, &self.config.node_metadata_attributes);
+            sp_debug!("Cached node metadata: {:?}", node_metadata);
+            self.node_metadata = Some(node_metadata);
+            self.node_metadata_keys = self.config.node_metadata_attributes.clone();
+        }
+    }
+
+    /// Run the batch flush timer only while batching is enabled
+    fn tick_period(&self) -> std::time::Duration {
+        if self.config.batching.enabled {
+            std::time::Duration::from_millis(self.config.batching.flush_interval_ms)
+        } else {
+            std::time::Duration::ZERO
+        }
+    }
+
+    /// Send every span batched by the HTTP contexts in one /v1/traces POST.
+    /// Timer flushes are not counted against max_inflight_dispatches.
+    fn flush_batch(&mut self) {
+        let Some(batch) = crate::batch::take(self) else {
+            return;
+        };
+        self.send_batch(batch, 1);
+    }
+
+    /// POST a batch, keeping it for a retry on a 429/5xx while attempts remain
+    fn send_batch(&mut self, batch: Batch, attempts: u32) {
+        match crate::context::dispatch_post(
+            self,
+            &self.config,
+            &self.config.sp_backend_url,
+            "/v1/traces",
+            "application/x-protobuf",
+            &batch.payload,
+        ) {
+            Ok(call_id) => {
+                sp_debug!("Flushed batch of {} span(s) (call_id={})", batch.spans, call_id);
+                if attempts <= self.config.max_retries {
+                    self.pending_flushes.insert(call_id, PendingFlush { batch, attempts });
+                }
+            }
+            Err(status) => {
+                sp_error!("Failed to dispatch batch of {} span(s), status: {:?}", batch.spans, status);
+                crate::metrics::record_export_error(ExportError::DispatchFailed);
+            }
+        }
+    }
+
+    /// Handle the backend status for a batch POST, re-sending it on 429/5xx
+    fn handle_flush_response(&mut self, token_id: u32, status_code: u32) {
+        let pending = self.pending_flushes.remove(&token_id);
+        match ExportError::from_status(status_code) {
+            None => {
+                sp_debug!("Batch save completed (token={}, status: {})", token_id, status_code);
+            }
+            Some(error) => {
+                sp_error!("Batch save failed with status: {}", status_code);
+                crate::metrics::record_export_error(error);
+                if let Some(mut pending) = pending.filter(|_| crate::context::is_retryable_status(status_code)) {
+                    sp_warn!("Retrying batch export (attempt {})", pending.attempts + 1);
+                    // Batches are always OTLP, so the failure can be recorded on each span
+                    if let Some(payload) =
+                        crate::otel::annotate_serialized_traces(&pending.batch.payload, "sp.export.error", error.as_str())
+                    {
+                        pending.batch.payload = payload;
+                    }
+                    self.send_batch(pending.batch, pending.attempts + 1);
+                }
+            }
         }
     }
 }
 
-impl Context for SpRootContext {}
+impl Context for SpRootContext {
+    fn on_http_call_response(&mut self, token_id: u32, _num_headers: usize, _body_size: usize, _num_trailers: usize) {
+        let status_code = self
+            .get_http_call_response_header(":status")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(0);
+        self.handle_flush_response(token_id, status_code);
+    }
+}
+
+impl crate::span_buffer::SharedBufferStore for SpRootContext {
+    fn load_buffer(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+        self.get_shared_data(key)
+    }
+
+    fn store_buffer(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+        match self.set_shared_data(key, Some(value), cas) {
+            Ok(()) => true,
+            Err(Status::CasMismatch) => false,
+            Err(status) => {
+                sp_warn!("Failed to store shared buffer {}: {:?}", key, status);
+                false
+            }
+        }
+    }
+}
+
+// Lets node metadata be read through the same property helpers as HTTP contexts
+impl RequestHeadersAccess for SpRootContext {
+    fn get_context_property(&self, path: Vec<&str>) -> Option<Vec<u8>> {
+        self.get_property(path)
+    }
+
+    fn get_request_header(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
 
 impl RootContext for SpRootContext {
     fn get_type(&self) -> Option<ContextType> {
@@ -46,20 +184,30 @@ impl RootContext for SpRootContext {
         Some(Box::new(SpHttpContext::new(
             context_id,
             self.config.clone(),
+            self.node_metadata.clone().unwrap_or_default(),
         )))
     }
 
     fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
         if let Some(config_bytes) = self.get_plugin_configuration() {
-            self.config.parse_from_json(&config_bytes);
+            if self.config.parse_from_json(&config_bytes) {
+                crate::metrics::record_config_conflicts(&self.config.conflicts);
+            }
         }
+        self.ensure_node_metadata();
+        self.set_tick_period(self.tick_period());
         true
     }
+
+    fn on_tick(&mut self) {
+        self.flush_batch();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use prost::Message;
 
     #[test]
     fn test_sp_root_context_creation() {
@@ -80,4 +228,103 @@ mod tests {
         // Test with empty configuration
         assert!(root_context.on_configure(0));
     }
-}
\ No newline at end of file
+
+    fn test_batch() -> Batch {
+        let traces_data = crate::otel::SpanBuilder::new().create_extract_span(
+            &HashMap::new(), &[], &HashMap::new(), &[], None, Some("/api/orders"), None,
+        );
+        Batch {
+            spans: 1,
+            payload: crate::otel::serialize_traces_data(&traces_data).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_batch_retried_on_retryable_status() {
+        let mut root_context = SpRootContext::new();
+        root_context.send_batch(test_batch(), 1);
+        let token = *root_context.pending_flushes.keys().next().unwrap();
+
+        root_context.handle_flush_response(token, 503);
+        assert!(!root_context.pending_flushes.contains_key(&token));
+        let retry = root_context.pending_flushes.values().next().unwrap();
+        assert_eq!(retry.attempts, 2);
+        assert_eq!(retry.batch.spans, 1);
+
+        // The re-sent batch records why the first attempt failed
+        let traces_data = crate::otel::TracesData::decode(retry.batch.payload.as_slice()).unwrap();
+        assert_eq!(
+            crate::otel::string_attribute(crate::otel::span_of(&traces_data), "sp.export.error"),
+            Some("server_error")
+        );
+    }
+
+    #[test]
+    fn test_batch_not_retried_on_success_or_client_error() {
+        let mut root_context = SpRootContext::new();
+        root_context.send_batch(test_batch(), 1);
+        let token = *root_context.pending_flushes.keys().next().unwrap();
+        root_context.handle_flush_response(token, 200);
+        assert!(root_context.pending_flushes.is_empty());
+
+        root_context.send_batch(test_batch(), 1);
+        let token = *root_context.pending_flushes.keys().next().unwrap();
+        root_context.handle_flush_response(token, 400);
+        assert!(root_context.pending_flushes.is_empty());
+    }
+
+    #[test]
+    fn test_batch_retries_stop_at_max_retries() {
+        let mut root_context = SpRootContext::new();
+        root_context.config.max_retries = 1;
+        root_context.send_batch(test_batch(), 1);
+        let token = *root_context.pending_flushes.keys().next().unwrap();
+
+        // The retry is the last attempt, so it is not kept
+        root_context.handle_flush_response(token, 429);
+        assert!(root_context.pending_flushes.is_empty());
+    }
+
+    #[test]
+    fn test_tick_period_follows_batching() {
+        let mut root_context = SpRootContext::new();
+        assert_eq!(root_context.tick_period(), std::time::Duration::ZERO);
+
+        root_context.config.batching.enabled = true;
+        root_context.config.batching.flush_interval_ms = 250;
+        assert_eq!(root_context.tick_period(), std::time::Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_node_metadata_cached_on_configure() {
+        let mut root_context = SpRootContext::new();
+        assert!(root_context.node_metadata.is_none());
+
+        assert!(root_context.on_configure(0));
+        assert!(root_context.node_metadata.is_some());
+
+        // A later configure reuses the cached value instead of re-reading it
+        let cached = NodeMetadata {
+            is_ingressgateway: true,
+            ..Default::default()
+        };
+        root_context.node_metadata = Some(cached.clone());
+        assert!(root_context.on_configure(0));
+        assert_eq!(root_context.node_metadata, Some(cached));
+    }
+
+    #[test]
+    fn test_node_metadata_reread_when_attribute_keys_change() {
+        let mut root_context = SpRootContext::new();
+        root_context.node_metadata = Some(NodeMetadata {
+            is_ingressgateway: true,
+            ..Default::default()
+        });
+
+        root_context.config.node_metadata_attributes = vec!["MESH_ID".to_string()];
+        root_context.ensure_node_metadata();
+
+        assert_eq!(root_context.node_metadata, Some(NodeMetadata::default()));
+        assert_eq!(root_context.node_metadata_keys, vec!["MESH_ID".to_string()]);
+    }
+}
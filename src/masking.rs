@@ -0,0 +1,1053 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::config::{MaskingConfig, MaskingMode};
+use crate::otel::{any_value, AnyValue, KeyValue, LogsData, TracesData};
+
+/// Field names whose values are masked inside bodies
+const SENSITIVE_FIELDS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "api_key",
+    "apikey",
+    "authorization",
+    "credit_card",
+    "card_number",
+    "cvv",
+    "ssn",
+    "id_card",
+    "phone",
+    "mobile",
+    "email",
+];
+
+/// Suffix of the attribute carrying a body's content-type
+pub const BODY_CONTENT_TYPE_SUFFIX: &str = ".content_type";
+
+/// Body format used to pick a masking strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Json,
+    Xml,
+    Form,
+    Plain,
+}
+
+impl BodyFormat {
+    /// Classify a content-type header value, ignoring parameters such as charset
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        let Some(content_type) = content_type else {
+            // Unknown type: bodies were historically assumed to be JSON
+            return BodyFormat::Json;
+        };
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            BodyFormat::Json
+        } else if media_type == "application/xml" || media_type == "text/xml" || media_type.ends_with("+xml") {
+            BodyFormat::Xml
+        } else if media_type == "application/x-www-form-urlencoded" {
+            BodyFormat::Form
+        } else {
+            BodyFormat::Plain
+        }
+    }
+}
+
+/// Hex digits of the SHA-256 kept by the hash masking mode
+const MASK_HASH_LENGTH: usize = 8;
+
+/// Mask a value: the middle is starred out, keeping the configured prefix and
+/// suffix, or in hash mode the whole value becomes `hash:` and a short SHA-256
+pub fn mask_string(value: &str, config: &MaskingConfig) -> String {
+    if config.mode == MaskingMode::Hash {
+        let digest = crate::otel::body_hash(value.as_bytes());
+        return format!("hash:{}", &digest[..MASK_HASH_LENGTH]);
+    }
+
+    let chars: Vec<char> = value.chars().collect();
+    let keep = config.keep_prefix_length + config.keep_suffix_length;
+    if chars.len() <= keep {
+        return "*".repeat(chars.len());
+    }
+
+    let prefix: String = chars[..config.keep_prefix_length].iter().collect();
+    let suffix: String = chars[chars.len() - config.keep_suffix_length..].iter().collect();
+    format!("{}{}{}", prefix, "*".repeat(chars.len() - keep), suffix)
+}
+
+/// Built-in sensitive field names merged with the configured custom ones
+fn sensitive_field_names(custom_field_names: &[String]) -> impl Iterator<Item = &str> {
+    SENSITIVE_FIELDS
+        .iter()
+        .copied()
+        .chain(custom_field_names.iter().map(|field| field.as_str()))
+}
+
+fn is_sensitive_field(name: &str, config: &MaskingConfig) -> bool {
+    sensitive_field_names(&config.custom_field_names).any(|field| field.eq_ignore_ascii_case(name))
+}
+
+/// JSON and XML field regexes for the sensitive field names, compiled once per
+/// masking config rather than for every body
+#[derive(Debug, Clone)]
+pub struct FieldPatterns {
+    json: Option<Regex>,
+    xml: Option<Regex>,
+}
+
+impl FieldPatterns {
+    pub fn new(custom_field_names: &[String]) -> Self {
+        let alternation = sensitive_field_names(custom_field_names)
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join("|");
+        let compile = |kind: &str, pattern: String| match Regex::new(&pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                crate::sp_error!("Invalid {} masking pattern: {}", kind, e);
+                None
+            }
+        };
+        Self {
+            json: compile("JSON", format!(r#"(?i)("(?:{})"\s*:\s*")([^"]*)(")"#, alternation)),
+            xml: compile("XML", format!(r"(?i)(<({})(?:\s[^>]*)?>)([^<]*)(</[^>]+>)", alternation)),
+        }
+    }
+}
+
+/// Mask `"field":"value"` pairs for sensitive field names
+pub fn mask_json_body(body: &str, config: &MaskingConfig) -> String {
+    let Some(re) = &config.field_patterns.json else {
+        return body.to_string();
+    };
+    re.replace_all(body, |caps: &regex::Captures| {
+        format!("{}{}{}", &caps[1], mask_string(&caps[2], config), &caps[3])
+    })
+    .to_string()
+}
+
+/// Mask every value below a sensitive key of a JSON document, at any depth and
+/// whatever its type; numbers are masked as strings. Bodies that don't parse
+/// fall back to `mask_json_body`. A document is only re-serialized (compact,
+/// keys sorted) when something was masked.
+pub fn mask_json_body_structured(body: &str, config: &MaskingConfig) -> String {
+    let Ok(mut document) = serde_json::from_str::<serde_json::Value>(body) else {
+        return mask_json_body(body, config);
+    };
+    if mask_json_value(&mut document, false, config) == 0 {
+        return body.to_string();
+    }
+    document.to_string()
+}
+
+/// Mask the scalars of a JSON value in place, returning how many were masked
+fn mask_json_value(value: &mut serde_json::Value, sensitive: bool, config: &MaskingConfig) -> usize {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(fields) => fields
+            .iter_mut()
+            .map(|(key, child)| mask_json_value(child, sensitive || is_sensitive_field(key, config), config))
+            .sum(),
+        Value::Array(items) => items.iter_mut().map(|item| mask_json_value(item, sensitive, config)).sum(),
+        Value::String(text) if sensitive => {
+            *text = mask_string(text, config);
+            1
+        }
+        Value::Number(number) if sensitive => {
+            let masked = mask_string(&number.to_string(), config);
+            *value = Value::String(masked);
+            1
+        }
+        Value::String(text) if config.auto_detect_values && detect_masked_value(text).is_some() => {
+            *text = mask_string(text, config);
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Mask `<field>value</field>` elements for sensitive field names
+pub fn mask_xml_body(body: &str, config: &MaskingConfig) -> String {
+    let Some(re) = &config.field_patterns.xml else {
+        return body.to_string();
+    };
+    re.replace_all(body, |caps: &regex::Captures| {
+        format!("{}{}{}", &caps[1], mask_string(&caps[3], config), &caps[4])
+    })
+    .to_string()
+}
+
+/// Kinds of sensitive values recognised by their shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensitiveType {
+    Phone,
+    Email,
+    IdCard,
+    BankCard,
+    Token,
+    IpAddress,
+}
+
+/// Classify a value by its shape. Checks run from most to least specific so an
+/// 18-digit ID card or 11-digit phone is not reported as a bank card. Canonical
+/// UUIDs are resource IDs, not secrets, although they are long enough for a token.
+pub fn detect_sensitive_type(value: &str) -> Option<SensitiveType> {
+    let value = value.trim();
+    if CANONICAL_UUID.is_match(value) {
+        return None;
+    }
+    SENSITIVE_VALUE_PATTERNS
+        .iter()
+        .find_map(|(kind, re)| re.is_match(value).then_some(*kind))
+}
+
+static CANONICAL_UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}$").expect("valid UUID pattern")
+});
+
+/// Shapes checked by `detect_sensitive_type`, compiled on first use
+static SENSITIVE_VALUE_PATTERNS: LazyLock<Vec<(SensitiveType, Regex)>> = LazyLock::new(|| {
+    const PATTERNS: &[(SensitiveType, &str)] = &[
+        (SensitiveType::Email, r"^[^@\s]+@[^@\s]+\.[A-Za-z]{2,}$"),
+        (SensitiveType::Phone, r"^(\+?86)?1[3-9]\d{9}$"),
+        (SensitiveType::IdCard, r"^\d{17}[\dXx]$"),
+        (SensitiveType::BankCard, r"^\d{13,19}$"),
+        (SensitiveType::IpAddress, r"^\d{1,3}(\.\d{1,3}){3}$"),
+        (SensitiveType::Token, r"^eyJ[\w-]+\.[\w-]+\.[\w-]+$"),
+        (SensitiveType::Token, r"^[A-Za-z0-9_\-]{32,}$"),
+    ];
+    PATTERNS
+        .iter()
+        .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("valid sensitive value pattern")))
+        .collect()
+});
+
+/// Sensitive type of a value under auto_detect_values. `\d{13,19}` alone also matches
+/// order numbers, snowflake IDs and nanosecond timestamps, so bank cards must pass
+/// the Luhn checksum too. That rejects about nine in ten such IDs; the rest are
+/// masked anyway, which is the cost of catching card numbers in unlisted fields.
+fn detect_masked_value(value: &str) -> Option<SensitiveType> {
+    match detect_sensitive_type(value)? {
+        SensitiveType::BankCard if !passes_luhn(value.trim()) => None,
+        kind => Some(kind),
+    }
+}
+
+/// Luhn checksum of a string of ASCII digits
+fn passes_luhn(digits: &str) -> bool {
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| {
+            let digit = u32::from(b - b'0');
+            match i % 2 {
+                0 => digit,
+                _ if digit > 4 => digit * 2 - 9,
+                _ => digit * 2,
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Trace correlation headers look like tokens but must stay readable
+const AUTO_DETECT_SKIPPED_HEADERS: &[&str] = &["traceparent", "tracestate", "x-request-id", "x-b3-traceid", "x-b3-spanid", "b3"];
+
+/// Whether a captured header value is masked by auto_detect_values
+fn is_detected_header_value(name: &str, value: &str) -> bool {
+    !AUTO_DETECT_SKIPPED_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)) && detect_masked_value(value).is_some()
+}
+
+/// Bank card detection is left out of form masking: long numeric IDs match it too
+fn is_sensitive_form_value(value: &str, config: &MaskingConfig) -> bool {
+    config.auto_detect_values
+        && matches!(
+            detect_sensitive_type(value),
+            Some(kind) if kind != SensitiveType::BankCard
+        )
+}
+
+/// Mask `field=value` pairs of a urlencoded form. Names and values are
+/// percent-decoded before classification, so encoding can't hide a secret, and
+/// masked values are re-encoded.
+pub fn mask_form_body(body: &str, config: &MaskingConfig) -> String {
+    body.split('&')
+        .map(|pair| {
+            let Some((name, value)) = pair.split_once('=') else {
+                return pair.to_string();
+            };
+            let decoded_name = form_decode(name);
+            let decoded_value = form_decode(value);
+            if is_sensitive_field(&decoded_name, config) || is_sensitive_form_value(&decoded_value, config) {
+                format!("{}={}", name, form_encode(&mask_string(&decoded_value, config)))
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn form_decode(value: &str) -> String {
+    url::form_urlencoded::parse(format!("v={}", value).as_bytes())
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_default()
+}
+
+fn form_encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// Mask sensitive query parameters of a URL, leaving the rest untouched
+pub fn mask_url_query(url: &str, config: &MaskingConfig) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!("{}?{}", base, mask_form_body(query, config)),
+        None => url.to_string(),
+    }
+}
+
+/// Mask path segments that look like tokens or long hex secrets, e.g. `/reset/<token>`.
+/// The query string is left to `mask_url_query`.
+pub fn mask_path_secrets(path: &str, config: &MaskingConfig) -> String {
+    let (path_part, query) = match path.split_once('?') {
+        Some((path_part, query)) => (path_part, Some(query)),
+        None => (path, None),
+    };
+    let masked = path_part
+        .split('/')
+        .map(|segment| match detect_sensitive_type(segment) {
+            Some(SensitiveType::Token) => mask_string(segment, config),
+            _ => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    match query {
+        Some(query) => format!("{}?{}", masked, query),
+        None => masked,
+    }
+}
+
+/// Mask a body according to its format, then apply the custom patterns to the result
+pub fn mask_body(body: &str, format: BodyFormat, config: &MaskingConfig) -> String {
+    let masked = match format {
+        BodyFormat::Json if uses_deep_json_masking(body, config) => mask_json_body_structured(body, config),
+        BodyFormat::Json => mask_json_body(body, config),
+        BodyFormat::Xml => mask_xml_body(body, config),
+        BodyFormat::Form => mask_form_body(body, config),
+        // Plain text has no fields, so only detected values and custom patterns are masked
+        BodyFormat::Plain => mask_plain_body(body, config),
+    };
+    apply_custom_patterns(masked, config)
+}
+
+/// Mask the words of a plain-text body that auto_detect_values recognises
+fn mask_plain_body(body: &str, config: &MaskingConfig) -> String {
+    if !config.auto_detect_values {
+        return body.to_string();
+    }
+    PLAIN_TEXT_WORD
+        .replace_all(body, |caps: &regex::Captures| match detect_masked_value(&caps[0]) {
+            Some(_) => mask_string(&caps[0], config),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// Candidate values in plain text: runs of characters that are not whitespace,
+/// quotes, brackets or list separators
+static PLAIN_TEXT_WORD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[^\s"'<>()\[\]{},;]+"#).expect("valid plain text word pattern"));
+
+/// Whether a JSON body gets the full-parse masking: only when a mode that needs it
+/// is on, and the body is within deep_masking_max_bytes so parsing stays affordable
+fn uses_deep_json_masking(body: &str, config: &MaskingConfig) -> bool {
+    (config.structured_json || config.auto_detect_values)
+        && (config.deep_masking_max_bytes == 0 || body.len() <= config.deep_masking_max_bytes)
+}
+
+/// Replace every match of the configured custom patterns
+fn apply_custom_patterns(body: String, config: &MaskingConfig) -> String {
+    config.custom_patterns.iter().fold(body, |body, (pattern, replacement)| {
+        pattern.replace_all(&body, replacement.as_str()).into_owned()
+    })
+}
+
+fn string_value(kv: &KeyValue) -> Option<&str> {
+    match kv.value.as_ref()?.value.as_ref()? {
+        any_value::Value::StringValue(s) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+/// Marker attribute added when masking changed at least one span attribute
+pub const MASKING_APPLIED_ATTRIBUTE: &str = "sp.masking.applied";
+/// Number of span attributes whose value masking changed
+pub const MASKING_FIELDS_MASKED_ATTRIBUTE: &str = "sp.masking.fields_masked";
+
+/// Mask header and body attributes of a span in place. When any value changed,
+/// the span is marked with `sp.masking.applied` and `sp.masking.fields_masked`.
+pub fn mask_span_attributes(attributes: &mut Vec<KeyValue>, config: &MaskingConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    // Content-types recorded next to the body attributes
+    let content_type_of = |body_key: &str| -> Option<String> {
+        let key = format!("{}{}", body_key, BODY_CONTENT_TYPE_SUFFIX);
+        attributes.iter().find(|kv| kv.key == key).and_then(string_value).map(|s| s.to_string())
+    };
+    let request_format = BodyFormat::from_content_type(content_type_of("http.request.body").as_deref());
+    let response_format = BodyFormat::from_content_type(content_type_of("http.response.body").as_deref());
+
+    let mut fields_masked: i64 = 0;
+    for kv in attributes.iter_mut() {
+        let masked = match kv.key.as_str() {
+            "http.request.body" if config.mask_request_body => masked_body_value(kv, request_format, config),
+            "http.response.body" if config.mask_response_body => masked_body_value(kv, response_format, config),
+            // The path keeps the query string, which often carries tokens or emails
+            "url.path" | "url.path.original" | "http.target" => {
+                string_value(kv).map(|path| any_value::Value::StringValue(mask_url_query(path, config)))
+            }
+            key => {
+                let header = key
+                    .strip_prefix("http.request.header.")
+                    .map(|name| (name, &config.mask_request_headers))
+                    .or_else(|| key.strip_prefix("http.response.header.").map(|name| (name, &config.mask_response_headers)));
+                let header_masked = header.is_some_and(|(name, listed)| {
+                    listed.iter().any(|h| h.eq_ignore_ascii_case(name))
+                        || (config.auto_detect_values
+                            && string_value(kv).is_some_and(|value| is_detected_header_value(name, value)))
+                });
+                if header_masked {
+                    string_value(kv).map(|value| any_value::Value::StringValue(mask_string(value, config)))
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let (Some(value), Some(any)) = (masked, kv.value.as_mut()) {
+            if any.value.as_ref() != Some(&value) {
+                fields_masked += 1;
+                any.value = Some(value);
+            }
+        }
+    }
+
+    if fields_masked > 0 {
+        attributes.push(KeyValue {
+            key: MASKING_APPLIED_ATTRIBUTE.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::BoolValue(true)),
+            }),
+        });
+        attributes.push(KeyValue {
+            key: MASKING_FIELDS_MASKED_ATTRIBUTE.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(fields_masked)),
+            }),
+        });
+    }
+}
+
+/// Masked value of a body attribute. Bytes bodies are masked as text when they
+/// are UTF-8 and redacted wholesale otherwise.
+fn masked_body_value(kv: &KeyValue, format: BodyFormat, config: &MaskingConfig) -> Option<any_value::Value> {
+    match kv.value.as_ref()?.value.as_ref()? {
+        any_value::Value::StringValue(body) => Some(any_value::Value::StringValue(mask_body(body, format, config))),
+        any_value::Value::BytesValue(body) => {
+            let masked = match std::str::from_utf8(body) {
+                Ok(text) => mask_body(text, format, config).into_bytes(),
+                Err(_) => vec![b'*'; body.len()],
+            };
+            Some(any_value::Value::BytesValue(masked))
+        }
+        _ => None,
+    }
+}
+
+/// Mask the attributes of every span in the traces data
+pub fn mask_traces_data(traces_data: &mut TracesData, config: &MaskingConfig) {
+    if !config.enabled {
+        return;
+    }
+    for resource_spans in &mut traces_data.resource_spans {
+        for scope_spans in &mut resource_spans.scope_spans {
+            for span in &mut scope_spans.spans {
+                mask_span_attributes(&mut span.attributes, config);
+            }
+        }
+    }
+}
+
+/// Mask the bodies carried by body log records
+pub fn mask_logs_data(logs_data: &mut LogsData, config: &MaskingConfig) {
+    if !config.enabled {
+        return;
+    }
+    for resource_logs in &mut logs_data.resource_logs {
+        for scope_logs in &mut resource_logs.scope_logs {
+            for record in &mut scope_logs.log_records {
+                let side = record.attributes.iter().find(|kv| kv.key == "sp.body.side").and_then(string_value);
+                let masked_side = match side {
+                    Some("request") => config.mask_request_body,
+                    Some("response") => config.mask_response_body,
+                    _ => false,
+                };
+                if !masked_side {
+                    continue;
+                }
+
+                let content_type = record
+                    .attributes
+                    .iter()
+                    .find(|kv| kv.key.ends_with(BODY_CONTENT_TYPE_SUFFIX))
+                    .and_then(string_value);
+                let format = BodyFormat::from_content_type(content_type);
+                if let Some(any_value::Value::StringValue(body)) = record.body.as_mut().and_then(|b| b.value.as_mut()) {
+                    *body = mask_body(body, format, config);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_config() -> MaskingConfig {
+        MaskingConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn string_kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    fn value_of<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+        attributes.iter().find(|kv| kv.key == key).and_then(string_value)
+    }
+
+    #[test]
+    fn test_mask_string_keeps_prefix_and_suffix() {
+        let config = enabled_config();
+        assert_eq!(mask_string("13812345678", &config), "138****5678");
+        assert_eq!(mask_string("short", &config), "*****");
+    }
+
+    fn hash_config() -> MaskingConfig {
+        MaskingConfig {
+            enabled: true,
+            mode: MaskingMode::Hash,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hash_mode_is_stable_per_value() {
+        let config = hash_config();
+        assert_eq!(mask_string("13812345678", &config), mask_string("13812345678", &config));
+        assert_ne!(mask_string("13812345678", &config), mask_string("13812345679", &config));
+        assert_eq!(mask_string("", &config), "hash:e3b0c442");
+    }
+
+    #[test]
+    fn test_hash_mode_in_json_body() {
+        let config = hash_config();
+        let first = mask_json_body(r#"{"user":"a","email":"ann@example.com"}"#, &config);
+        let second = mask_json_body(r#"{"email":"ann@example.com","user":"b"}"#, &config);
+        let other = mask_json_body(r#"{"email":"bob@example.com"}"#, &config);
+
+        let hashed = mask_string("ann@example.com", &config);
+        assert!(hashed.starts_with("hash:") && hashed.len() == "hash:".len() + MASK_HASH_LENGTH);
+        assert_eq!(first, format!(r#"{{"user":"a","email":"{}"}}"#, hashed));
+        assert_eq!(second, format!(r#"{{"email":"{}","user":"b"}}"#, hashed));
+        assert!(!other.contains(&hashed));
+    }
+
+    #[test]
+    fn test_body_format_from_content_type() {
+        assert_eq!(BodyFormat::from_content_type(Some("application/json; charset=utf-8")), BodyFormat::Json);
+        assert_eq!(BodyFormat::from_content_type(Some("application/vnd.api+json")), BodyFormat::Json);
+        assert_eq!(BodyFormat::from_content_type(Some("text/xml")), BodyFormat::Xml);
+        assert_eq!(BodyFormat::from_content_type(Some("application/soap+xml")), BodyFormat::Xml);
+        assert_eq!(BodyFormat::from_content_type(Some("application/x-www-form-urlencoded")), BodyFormat::Form);
+        assert_eq!(BodyFormat::from_content_type(Some("text/plain")), BodyFormat::Plain);
+        assert_eq!(BodyFormat::from_content_type(None), BodyFormat::Json);
+    }
+
+    #[test]
+    fn test_mask_json_body() {
+        let masked = mask_json_body(r#"{"user":"alice","password":"hunter2secret"}"#, &enabled_config());
+        assert_eq!(masked, r#"{"user":"alice","password":"hun******cret"}"#);
+    }
+
+    #[test]
+    fn test_mask_form_body() {
+        let masked = mask_form_body("user=alice&token=abcdefghijkl", &enabled_config());
+        assert_eq!(masked, "user=alice&token=abc*****ijkl");
+    }
+
+    #[test]
+    fn test_mask_url_query() {
+        let masked = mask_url_query("https://shop.example.com/login?user=alice&token=abcdefghijkl", &enabled_config());
+        assert_eq!(masked, "https://shop.example.com/login?user=alice&token=abc*****ijkl");
+        assert_eq!(mask_url_query("/api/orders", &enabled_config()), "/api/orders");
+    }
+
+    #[test]
+    fn test_detect_sensitive_type() {
+        assert_eq!(detect_sensitive_type("alice@example.com"), Some(SensitiveType::Email));
+        assert_eq!(detect_sensitive_type("13812345678"), Some(SensitiveType::Phone));
+        assert_eq!(detect_sensitive_type("11010519491231002X"), Some(SensitiveType::IdCard));
+        assert_eq!(detect_sensitive_type("6222020200112233445"), Some(SensitiveType::BankCard));
+        assert_eq!(detect_sensitive_type("192.168.0.1"), Some(SensitiveType::IpAddress));
+        assert_eq!(detect_sensitive_type("eyJhbGciOi.eyJzdWIiOi.c2lnbmF0dXJl"), Some(SensitiveType::Token));
+        assert_eq!(detect_sensitive_type("alice"), None);
+    }
+
+    #[test]
+    fn test_mask_form_body_decodes_before_classification() {
+        // "alice+tag@example.com" with the '+' and '@' percent-encoded
+        let masked = mask_form_body("user=alice&contact=alice%2Btag%40example.com", &auto_detect_config());
+        assert_eq!(masked, "user=alice&contact=ali**************.com");
+    }
+
+    #[test]
+    fn test_form_values_not_detected_without_auto_detect() {
+        let masked = mask_form_body("user=alice&contact=alice%40example.com", &enabled_config());
+        assert_eq!(masked, "user=alice&contact=alice%40example.com");
+    }
+
+    #[test]
+    fn test_mask_form_body_decodes_field_names() {
+        let masked = mask_form_body("pass%77ord=hunter2secret", &enabled_config());
+        assert_eq!(masked, "pass%77ord=hun******cret");
+    }
+
+    #[test]
+    fn test_mask_form_body_reencodes_masked_values() {
+        let config = MaskingConfig {
+            enabled: true,
+            keep_prefix_length: 4,
+            keep_suffix_length: 0,
+            ..Default::default()
+        };
+        let masked = mask_form_body("token=a+b%26cdefgh", &config);
+        assert_eq!(masked, "token=a+b%26******");
+    }
+
+    #[test]
+    fn test_xml_body_routes_to_xml_masking() {
+        let mut attributes = vec![
+            string_kv("http.request.body", "<login><user>alice</user><password>hunter2secret</password></login>"),
+            string_kv("http.request.body.content_type", "application/xml"),
+        ];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(
+            value_of(&attributes, "http.request.body"),
+            Some("<login><user>alice</user><password>hun******cret</password></login>")
+        );
+    }
+
+    #[test]
+    fn test_json_masking_does_not_apply_to_xml_body() {
+        // An XML body containing JSON-looking text must not be treated as JSON
+        let body = r#"<note>"password":"hunter2secret"</note>"#;
+        let mut attributes = vec![
+            string_kv("http.response.body", body),
+            string_kv("http.response.body.content_type", "text/xml"),
+        ];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(value_of(&attributes, "http.response.body"), Some(body));
+    }
+
+    fn structured_config() -> MaskingConfig {
+        MaskingConfig {
+            enabled: true,
+            structured_json: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_structured_json_masks_nested_objects() {
+        let body = r#"{"user":{"name":"Ann","password":{"old":"hunter2secret","new":"swordfish99"}}}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &structured_config()),
+            r#"{"user":{"name":"Ann","password":{"new":"swo****sh99","old":"hun******cret"}}}"#
+        );
+    }
+
+    #[test]
+    fn test_structured_json_masks_arrays_of_strings() {
+        let body = r#"{"phone":["13812345678","13987654321"],"tags":["a","b"]}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &structured_config()),
+            r#"{"phone":["138****5678","139****4321"],"tags":["a","b"]}"#
+        );
+    }
+
+    #[test]
+    fn test_structured_json_masks_integer_values() {
+        let body = r#"{"mobile":13812345678,"qty":2}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &structured_config()),
+            r#"{"mobile":"138****5678","qty":2}"#
+        );
+        // The regex path only sees quoted string values
+        assert_eq!(mask_body(body, BodyFormat::Json, &enabled_config()), body);
+    }
+
+    #[test]
+    fn test_structured_json_falls_back_for_invalid_json() {
+        let body = r#"{"password":"hunter2secret", truncated"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &structured_config()),
+            r#"{"password":"hun******cret", truncated"#
+        );
+        // Untouched documents keep their original formatting
+        assert_eq!(mask_body("{ \"b\": 1, \"a\": 2 }", BodyFormat::Json, &structured_config()), "{ \"b\": 1, \"a\": 2 }");
+    }
+
+    #[test]
+    fn test_deep_masking_limited_to_small_bodies() {
+        let config = MaskingConfig {
+            deep_masking_max_bytes: 64,
+            ..structured_config()
+        };
+        let small = r#"{"mobile":13812345678}"#;
+        let large = format!(r#"{{"mobile":13812345678,"password":"hunter2secret","pad":"{}"}}"#, "x".repeat(64));
+
+        // Small bodies are parsed, so the numeric phone number is masked
+        assert_eq!(mask_body(small, BodyFormat::Json, &config), r#"{"mobile":"138****5678"}"#);
+        // Large ones take the regex path: quoted values only, original layout kept
+        assert_eq!(
+            mask_body(&large, BodyFormat::Json, &config),
+            format!(r#"{{"mobile":13812345678,"password":"hun******cret","pad":"{}"}}"#, "x".repeat(64))
+        );
+    }
+
+    fn auto_detect_config() -> MaskingConfig {
+        MaskingConfig {
+            enabled: true,
+            auto_detect_values: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_auto_detect_masks_sensitive_values_in_unlisted_fields() {
+        let body = r#"{"contact":{"primary":"13812345678","notes":["ann@example.com","call later"]},"client":"10.1.2.3"}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &auto_detect_config()),
+            r#"{"client":"10.*.2.3","contact":{"notes":["ann********.com","call later"],"primary":"138****5678"}}"#
+        );
+        assert_eq!(mask_body(body, BodyFormat::Json, &enabled_config()), body);
+    }
+
+    #[test]
+    fn test_auto_detect_keeps_long_numeric_ids_failing_luhn() {
+        // A Visa test number passes Luhn, an order number of the same shape doesn't
+        let body = r#"{"card":"4111111111111111","order_id":"4111111111111112"}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &auto_detect_config()),
+            r#"{"card":"411*********1111","order_id":"4111111111111112"}"#
+        );
+    }
+
+    #[test]
+    fn test_auto_detect_masks_header_values() {
+        let mut attributes = vec![
+            string_kv("http.request.header.x-contact", "ann@example.com"),
+            string_kv("http.request.header.accept", "application/json"),
+            string_kv("http.request.header.x-request-id", "9f2c1e0a-54d7-4a8e-9b1f-2c6e3d4a5b6c"),
+            string_kv("http.response.header.x-client-ip", "10.1.2.3"),
+        ];
+
+        mask_span_attributes(&mut attributes, &auto_detect_config());
+
+        assert_eq!(value_of(&attributes, "http.request.header.x-contact"), Some("ann********.com"));
+        assert_eq!(value_of(&attributes, "http.request.header.accept"), Some("application/json"));
+        assert_eq!(
+            value_of(&attributes, "http.request.header.x-request-id"),
+            Some("9f2c1e0a-54d7-4a8e-9b1f-2c6e3d4a5b6c")
+        );
+        assert_eq!(value_of(&attributes, "http.response.header.x-client-ip"), Some("10.*.2.3"));
+    }
+
+    #[test]
+    fn test_passes_luhn() {
+        assert!(passes_luhn("4111111111111111"));
+        assert!(passes_luhn("79927398713"));
+        assert!(!passes_luhn("79927398710"));
+    }
+
+    fn custom_config() -> MaskingConfig {
+        MaskingConfig {
+            enabled: true,
+            custom_patterns: vec![(Regex::new(r"\d{3}-\d{2}-\d{4}").unwrap(), "<ssn>".to_string())],
+            ..Default::default()
+        }
+        .with_custom_field_names(vec!["creditCard".to_string(), "member_no".to_string()])
+    }
+
+    #[test]
+    fn test_plain_body_keeps_text_outside_custom_patterns() {
+        let body = "order 42 shipped, ssn 123-45-6789";
+
+        assert_eq!(mask_body(body, BodyFormat::Plain, &custom_config()), "order 42 shipped, ssn <ssn>");
+        assert_eq!(mask_body("<p>hello</p>", BodyFormat::Plain, &MaskingConfig::default()), "<p>hello</p>");
+    }
+
+    #[test]
+    fn test_plain_body_masks_detected_values() {
+        let body = "contact ann@example.com or 13812345678, ref 42";
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Plain, &auto_detect_config()),
+            "contact ann********.com or 138****5678, ref 42"
+        );
+    }
+
+    #[test]
+    fn test_custom_field_names_merge_with_builtin_list() {
+        let body = r#"{"creditcard":"4111111111111111","member_no":"M12345678","password":"hunter2secret","name":"Ann"}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &custom_config()),
+            r#"{"creditcard":"411*********1111","member_no":"M12**5678","password":"hun******cret","name":"Ann"}"#
+        );
+        assert_eq!(
+            mask_body("creditCard=4111111111111111&page=2", BodyFormat::Form, &custom_config()),
+            "creditCard=411*********1111&page=2"
+        );
+    }
+
+    #[test]
+    fn test_custom_patterns_apply_to_whole_body_after_field_masking() {
+        let body = r#"{"note":"ssn 123-45-6789 on file","token":"abcdefghijkl"}"#;
+
+        assert_eq!(
+            mask_body(body, BodyFormat::Json, &custom_config()),
+            r#"{"note":"ssn <ssn> on file","token":"abc*****ijkl"}"#
+        );
+        assert_eq!(
+            mask_body("<note>123-45-6789</note>", BodyFormat::Xml, &custom_config()),
+            "<note><ssn></note>"
+        );
+    }
+
+    #[test]
+    fn test_mask_span_attributes_headers() {
+        let config = MaskingConfig {
+            enabled: true,
+            mask_request_headers: vec!["X-User-Phone".to_string()],
+            ..Default::default()
+        };
+        let mut attributes = vec![
+            string_kv("http.request.header.x-user-phone", "13812345678"),
+            string_kv("http.request.header.accept", "application/json"),
+        ];
+
+        mask_span_attributes(&mut attributes, &config);
+
+        assert_eq!(value_of(&attributes, "http.request.header.x-user-phone"), Some("138****5678"));
+        assert_eq!(value_of(&attributes, "http.request.header.accept"), Some("application/json"));
+    }
+
+    #[test]
+    fn test_mask_span_attributes_masks_path_query() {
+        let mut attributes = vec![
+            string_kv("url.path", "/api/orders?token=abc&user=alice@x.com&page=2"),
+            string_kv("http.target", "/api/orders?token=abc"),
+            string_kv("url.path.original", "/v1/orders?access_token=abcdefghijkl&user=alice@x.com"),
+        ];
+
+        mask_span_attributes(&mut attributes, &auto_detect_config());
+
+        assert_eq!(value_of(&attributes, "url.path"), Some("/api/orders?token=***&user=ali****.com&page=2"));
+        assert_eq!(value_of(&attributes, "http.target"), Some("/api/orders?token=***"));
+        assert_eq!(
+            value_of(&attributes, "url.path.original"),
+            Some("/v1/orders?access_token=abc*****ijkl&user=ali****.com")
+        );
+    }
+
+    #[test]
+    fn test_mask_span_attributes_keeps_path_without_query() {
+        let mut attributes = vec![string_kv("url.path", "/api/users/alice@x.com")];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(value_of(&attributes, "url.path"), Some("/api/users/alice@x.com"));
+        assert_eq!(value_of(&attributes, MASKING_APPLIED_ATTRIBUTE), None);
+    }
+
+    #[test]
+    fn test_mask_path_secrets_masks_token_segments_only() {
+        let config = enabled_config();
+
+        assert_eq!(
+            mask_path_secrets("/reset/9f86d081884c7d659a2feaa0c55ad015a3bf4f1b/confirm?lang=en", &config),
+            "/reset/9f8*********************************4f1b/confirm?lang=en"
+        );
+        assert_eq!(
+            mask_path_secrets("/api/users/42/orders/A-1001", &config),
+            "/api/users/42/orders/A-1001"
+        );
+    }
+
+    #[test]
+    fn test_mask_path_secrets_keeps_uuid_segments() {
+        let config = enabled_config();
+
+        assert_eq!(
+            mask_path_secrets("/orders/550e8400-e29b-41d4-a716-446655440000/items", &config),
+            "/orders/550e8400-e29b-41d4-a716-446655440000/items"
+        );
+        // Hex without the UUID grouping is still a token
+        assert_eq!(
+            mask_path_secrets("/orders/550e8400e29b41d4a716446655440000", &config),
+            "/orders/550*************************0000"
+        );
+    }
+
+    #[test]
+    fn test_mask_span_attributes_disabled() {
+        let mut attributes = vec![string_kv("http.request.body", r#"{"password":"hunter2secret"}"#)];
+
+        mask_span_attributes(&mut attributes, &MaskingConfig::default());
+
+        assert_eq!(value_of(&attributes, "http.request.body"), Some(r#"{"password":"hunter2secret"}"#));
+    }
+
+    fn bytes_kv(key: &str, value: &[u8]) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::BytesValue(value.to_vec())),
+            }),
+        }
+    }
+
+    fn bytes_of<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a [u8]> {
+        match attributes.iter().find(|kv| kv.key == key)?.value.as_ref()?.value.as_ref()? {
+            any_value::Value::BytesValue(bytes) => Some(bytes.as_slice()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_mask_span_attributes_redacts_binary_bytes_body() {
+        let body = [0xff, 0xfe, b't', b'o', b'k', b'e', b'n', 0x00];
+        let mut attributes = vec![bytes_kv("http.request.body", &body)];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(bytes_of(&attributes, "http.request.body"), Some(&[b'*'; 8][..]));
+    }
+
+    #[test]
+    fn test_mask_span_attributes_masks_utf8_bytes_body() {
+        let mut attributes = vec![bytes_kv("http.response.body", br#"{"password":"hunter2secret"}"#)];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(
+            bytes_of(&attributes, "http.response.body"),
+            Some(&br#"{"password":"hun******cret"}"#[..])
+        );
+    }
+
+    #[test]
+    fn test_bytes_body_untouched_when_body_masking_off() {
+        let config = MaskingConfig {
+            enabled: true,
+            mask_request_body: false,
+            ..Default::default()
+        };
+        let mut attributes = vec![bytes_kv("http.request.body", &[0xff, 0x01])];
+
+        mask_span_attributes(&mut attributes, &config);
+
+        assert_eq!(bytes_of(&attributes, "http.request.body"), Some(&[0xff, 0x01][..]));
+    }
+
+    fn attribute_value<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a any_value::Value> {
+        attributes.iter().find(|kv| kv.key == key)?.value.as_ref()?.value.as_ref()
+    }
+
+    #[test]
+    fn test_masking_marker_counts_masked_fields() {
+        let config = MaskingConfig {
+            enabled: true,
+            mask_request_headers: vec!["x-user-phone".to_string()],
+            ..Default::default()
+        };
+        let mut attributes = vec![
+            string_kv("http.request.header.x-user-phone", "13812345678"),
+            string_kv("http.request.body", r#"{"password":"hunter2secret"}"#),
+            string_kv("http.response.body", r#"{"status":"ok"}"#),
+        ];
+
+        mask_span_attributes(&mut attributes, &config);
+
+        assert_eq!(
+            attribute_value(&attributes, MASKING_APPLIED_ATTRIBUTE),
+            Some(&any_value::Value::BoolValue(true))
+        );
+        assert_eq!(
+            attribute_value(&attributes, MASKING_FIELDS_MASKED_ATTRIBUTE),
+            Some(&any_value::Value::IntValue(2))
+        );
+    }
+
+    #[test]
+    fn test_no_masking_marker_when_nothing_masked() {
+        let mut attributes = vec![
+            string_kv("http.request.header.accept", "application/json"),
+            string_kv("http.response.body", r#"{"status":"ok"}"#),
+        ];
+
+        mask_span_attributes(&mut attributes, &enabled_config());
+
+        assert_eq!(attribute_value(&attributes, MASKING_APPLIED_ATTRIBUTE), None);
+        assert_eq!(attribute_value(&attributes, MASKING_FIELDS_MASKED_ATTRIBUTE), None);
+
+        let mut attributes = vec![string_kv("http.request.body", r#"{"password":"hunter2secret"}"#)];
+        mask_span_attributes(&mut attributes, &MaskingConfig::default());
+        assert_eq!(attribute_value(&attributes, MASKING_APPLIED_ATTRIBUTE), None);
+    }
+}
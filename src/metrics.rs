@@ -0,0 +1,98 @@
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::MetricType;
+
+/// Why a span export did not reach the backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportError {
+    /// dispatch_http_call itself failed (unknown cluster, bad headers)
+    DispatchFailed,
+    /// The call completed without a status, e.g. timeout or reset
+    NoResponse,
+    /// The backend answered 4xx
+    ClientError,
+    /// The backend answered 5xx
+    ServerError,
+    /// The backend answered a status outside 2xx/4xx/5xx, e.g. a 3xx redirect
+    UnexpectedStatus,
+}
+
+impl ExportError {
+    /// Classify a backend response status; None for success
+    pub fn from_status(status_code: u32) -> Option<Self> {
+        match status_code {
+            200..=299 => None,
+            0 => Some(ExportError::NoResponse),
+            400..=499 => Some(ExportError::ClientError),
+            500..=599 => Some(ExportError::ServerError),
+            _ => Some(ExportError::UnexpectedStatus),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportError::DispatchFailed => "dispatch_failed",
+            ExportError::NoResponse => "no_response",
+            ExportError::ClientError => "client_error",
+            ExportError::ServerError => "server_error",
+            ExportError::UnexpectedStatus => "unexpected_status",
+        }
+    }
+
+    fn metric_name(&self) -> String {
+        format!("sp.export_errors.{}", self.as_str())
+    }
+}
+
+/// Counter of spans evicted from the shared span buffer
+pub const BUFFERED_SPANS_DROPPED_METRIC: &str = "sp.buffered_spans_dropped";
+
+/// Gauge of conflicting options found in the last plugin configuration
+pub const CONFIG_CONFLICTS_METRIC: &str = "sp.config_conflicts";
+
+/// Count an export failure in a proxy-wasm counter, one per error class
+pub fn record_export_error(error: ExportError) {
+    increment_counter(&error.metric_name(), 1);
+}
+
+/// Count spans dropped because the shared span buffer was full
+pub fn record_buffered_spans_dropped(count: u32) {
+    increment_counter(BUFFERED_SPANS_DROPPED_METRIC, i64::from(count));
+}
+
+/// Report how many options conflicted in the configuration just applied
+pub fn record_config_conflicts(conflicts: &[crate::config::ConfigConflict]) {
+    let result = hostcalls::define_metric(MetricType::Gauge, CONFIG_CONFLICTS_METRIC)
+        .and_then(|metric_id| hostcalls::record_metric(metric_id, conflicts.len() as u64));
+    if let Err(status) = result {
+        crate::sp_debug!("Failed to record metric {}: {:?}", CONFIG_CONFLICTS_METRIC, status);
+    }
+}
+
+fn increment_counter(name: &str, offset: i64) {
+    // Defining an existing metric returns its ID, so no registry is needed
+    let result = hostcalls::define_metric(MetricType::Counter, name)
+        .and_then(|metric_id| hostcalls::increment_metric(metric_id, offset));
+    if let Err(status) = result {
+        crate::sp_debug!("Failed to record metric {}: {:?}", name, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_error_from_status() {
+        assert_eq!(ExportError::from_status(204), None);
+        assert_eq!(ExportError::from_status(0), Some(ExportError::NoResponse));
+        assert_eq!(ExportError::from_status(429), Some(ExportError::ClientError));
+        assert_eq!(ExportError::from_status(503), Some(ExportError::ServerError));
+        assert_eq!(ExportError::from_status(302), Some(ExportError::UnexpectedStatus));
+        assert_eq!(ExportError::from_status(600), Some(ExportError::UnexpectedStatus));
+    }
+
+    #[test]
+    fn test_metric_name_per_error_class() {
+        assert_eq!(ExportError::ServerError.metric_name(), "sp.export_errors.server_error");
+    }
+}
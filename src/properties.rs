@@ -0,0 +1,341 @@
+use crate::traffic::RequestHeadersAccess;
+
+/// Read an Envoy property as a non-empty UTF-8 string
+pub fn get_string_property<T: RequestHeadersAccess + ?Sized>(access: &T, path: Vec<&str>) -> Option<String> {
+    access
+        .get_context_property(path)
+        .and_then(|value| String::from_utf8(value).ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Get the request path as sent by the client, before any Envoy route rewrite
+pub fn get_original_path<T: RequestHeadersAccess + ?Sized>(access: &T) -> Option<String> {
+    get_string_property(access, vec!["request", "original_path"])
+        .or_else(|| access.get_request_header("x-envoy-original-path").filter(|v| !v.is_empty()))
+}
+
+/// Read an integer Envoy property, encoded either as a little-endian int64 or as text
+pub fn get_int_property<T: RequestHeadersAccess + ?Sized>(access: &T, path: Vec<&str>) -> Option<i64> {
+    let value = access.get_context_property(path)?;
+    if let Ok(bytes) = <[u8; 8]>::try_from(value.as_slice()) {
+        return Some(i64::from_le_bytes(bytes));
+    }
+    String::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+/// Determine http vs https from `:scheme`, then the `request.scheme` property,
+/// then the port the listener accepted the connection on
+pub fn get_request_scheme<T: RequestHeadersAccess + ?Sized>(access: &T) -> Option<String> {
+    if let Some(scheme) = access
+        .get_request_header(":scheme")
+        .filter(|v| !v.is_empty())
+        .or_else(|| get_string_property(access, vec!["request", "scheme"]))
+    {
+        return Some(scheme.to_ascii_lowercase());
+    }
+
+    match get_int_property(access, vec!["destination", "port"])? {
+        443 | 8443 => Some("https".to_string()),
+        80 | 8080 => Some("http".to_string()),
+        _ => None,
+    }
+}
+
+/// `network.local.address`/`network.peer.address` from the upstream connection,
+/// only for the addresses Envoy reports
+pub fn get_upstream_address_attributes<T: RequestHeadersAccess + ?Sized>(access: &T) -> Vec<(&'static str, String)> {
+    [
+        ("network.local.address", vec!["upstream", "local_address"]),
+        ("network.peer.address", vec!["upstream", "address"]),
+    ]
+    .into_iter()
+    .filter_map(|(key, path)| get_string_property(access, path).map(|address| (key, address)))
+    .collect()
+}
+
+/// Envoy cluster the request was routed to, e.g. `outbound|8080||orders.shop.svc.cluster.local`
+pub fn get_upstream_cluster<T: RequestHeadersAccess + ?Sized>(access: &T) -> Option<String> {
+    get_string_property(access, vec!["cluster_name"])
+}
+
+/// `network.protocol.version` of the downstream request and `sp.upstream.protocol`,
+/// to spot HTTP/2 to HTTP/1.1 downgrades and the reverse
+pub fn get_protocol_attributes<T: RequestHeadersAccess + ?Sized>(access: &T) -> Vec<(&'static str, String)> {
+    let mut attributes = Vec::new();
+    if let Some(protocol) = get_string_property(access, vec!["request", "protocol"]) {
+        attributes.push(("network.protocol.version", protocol_version(&protocol)));
+    }
+    if let Some(protocol) = get_string_property(access, vec!["upstream", "protocol"]) {
+        attributes.push(("sp.upstream.protocol", protocol));
+    }
+    attributes
+}
+
+/// `tls.cipher` and `network.protocol.name` (from the negotiated ALPN) of the
+/// downstream connection; empty for plaintext connections, which have no cipher
+pub fn get_downstream_tls_attributes<T: RequestHeadersAccess + ?Sized>(access: &T) -> Vec<(&'static str, String)> {
+    let Some(cipher) = get_string_property(access, vec!["connection", "tls_cipher_suite"]) else {
+        return Vec::new();
+    };
+    let mut attributes = vec![("tls.cipher", cipher)];
+    if let Some(alpn) = get_string_property(access, vec!["connection", "alpn"]) {
+        attributes.push(("network.protocol.name", protocol_name_from_alpn(&alpn)));
+    }
+    attributes
+}
+
+/// "h2", "http/1.1" -> "http"; other ALPN IDs are kept, lower-cased
+fn protocol_name_from_alpn(alpn: &str) -> String {
+    let alpn = alpn.trim().to_ascii_lowercase();
+    match alpn.as_str() {
+        "h2" | "h2c" | "h3" | "http/1.0" | "http/1.1" => "http".to_string(),
+        _ => alpn,
+    }
+}
+
+/// "HTTP/1.1" -> "1.1", "HTTP/2" -> "2"; other values are kept as-is
+fn protocol_version(protocol: &str) -> String {
+    protocol
+        .strip_prefix("HTTP/")
+        .unwrap_or(protocol)
+        .to_string()
+}
+
+/// Whether Envoy generated the response itself (direct response or local reply)
+/// rather than proxying it from the upstream
+pub fn is_local_reply<T: RequestHeadersAccess + ?Sized>(access: &T) -> bool {
+    get_string_property(access, vec!["response", "code_details"])
+        .is_some_and(|details| details.contains("direct_response") || details.contains("local_reply"))
+}
+
+/// HTTP status Envoy recorded for the response, for when `:status` was never seen
+pub fn get_response_code<T: RequestHeadersAccess + ?Sized>(access: &T) -> Option<i64> {
+    get_int_property(access, vec!["response", "code"]).filter(|code| *code > 0)
+}
+
+/// Envoy's own measure of the request duration in nanoseconds, set once the
+/// request completes (access-log phase)
+pub fn get_request_duration<T: RequestHeadersAccess + ?Sized>(access: &T) -> Option<u64> {
+    get_int_property(access, vec!["request", "duration"])
+        .filter(|duration| *duration > 0)
+        .map(|duration| duration as u64)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Property/header source for tests, keyed by the property path joined with '.'
+    #[derive(Default)]
+    pub(crate) struct MockAccess {
+        pub properties: HashMap<String, Vec<u8>>,
+        pub headers: HashMap<String, String>,
+    }
+
+    impl MockAccess {
+        pub fn with_property(mut self, path: &str, value: &str) -> Self {
+            self.properties.insert(path.to_string(), value.as_bytes().to_vec());
+            self
+        }
+
+        pub fn with_header(mut self, name: &str, value: &str) -> Self {
+            self.headers.insert(name.to_string(), value.to_string());
+            self
+        }
+    }
+
+    impl proxy_wasm::traits::Context for MockAccess {}
+
+    impl RequestHeadersAccess for MockAccess {
+        fn get_context_property(&self, path: Vec<&str>) -> Option<Vec<u8>> {
+            self.properties.get(&path.join(".")).cloned()
+        }
+
+        fn get_request_header(&self, name: &str) -> Option<String> {
+            self.headers.get(name).cloned()
+        }
+    }
+
+    #[test]
+    fn test_get_string_property_ignores_empty() {
+        let access = MockAccess::default().with_property("request.original_path", "");
+        assert_eq!(get_string_property(&access, vec!["request", "original_path"]), None);
+    }
+
+    #[test]
+    fn test_get_original_path_from_property() {
+        let access = MockAccess::default()
+            .with_property("request.original_path", "/public/orders/42")
+            .with_header("x-envoy-original-path", "/from-header");
+        assert_eq!(get_original_path(&access), Some("/public/orders/42".to_string()));
+    }
+
+    #[test]
+    fn test_get_original_path_from_header() {
+        let access = MockAccess::default().with_header("x-envoy-original-path", "/public/orders/42");
+        assert_eq!(get_original_path(&access), Some("/public/orders/42".to_string()));
+    }
+
+    #[test]
+    fn test_get_original_path_absent() {
+        let access = MockAccess::default();
+        assert_eq!(get_original_path(&access), None);
+    }
+
+    #[test]
+    fn test_is_local_reply_from_code_details() {
+        let access = MockAccess::default().with_property("response.code_details", "direct_response");
+        assert!(is_local_reply(&access));
+
+        let access = MockAccess::default().with_property("response.code_details", "rate_limited_local_reply");
+        assert!(is_local_reply(&access));
+    }
+
+    #[test]
+    fn test_is_local_reply_for_upstream_response() {
+        let access = MockAccess::default().with_property("response.code_details", "via_upstream");
+        assert!(!is_local_reply(&access));
+        assert!(!is_local_reply(&MockAccess::default()));
+    }
+
+    #[test]
+    fn test_get_response_code() {
+        let access = MockAccess::default().with_property("response.code", "503");
+        assert_eq!(get_response_code(&access), Some(503));
+
+        let mut access = MockAccess::default();
+        access.properties.insert("response.code".to_string(), 200i64.to_le_bytes().to_vec());
+        assert_eq!(get_response_code(&access), Some(200));
+
+        assert_eq!(get_response_code(&MockAccess::default().with_property("response.code", "0")), None);
+        assert_eq!(get_response_code(&MockAccess::default()), None);
+    }
+
+    #[test]
+    fn test_get_request_duration() {
+        let mut access = MockAccess::default();
+        access.properties.insert("request.duration".to_string(), 12_500_000i64.to_le_bytes().to_vec());
+        assert_eq!(get_request_duration(&access), Some(12_500_000));
+
+        assert_eq!(get_request_duration(&MockAccess::default().with_property("request.duration", "0")), None);
+        assert_eq!(get_request_duration(&MockAccess::default()), None);
+    }
+
+    #[test]
+    fn test_request_scheme_from_pseudo_header() {
+        let access = MockAccess::default()
+            .with_header(":scheme", "HTTPS")
+            .with_property("request.scheme", "http");
+        assert_eq!(get_request_scheme(&access), Some("https".to_string()));
+    }
+
+    #[test]
+    fn test_request_scheme_from_property() {
+        let access = MockAccess::default()
+            .with_property("request.scheme", "http")
+            .with_property("destination.port", "443");
+        assert_eq!(get_request_scheme(&access), Some("http".to_string()));
+    }
+
+    #[test]
+    fn test_request_scheme_inferred_from_port() {
+        let mut access = MockAccess::default();
+        access.properties.insert("destination.port".to_string(), 443i64.to_le_bytes().to_vec());
+        assert_eq!(get_request_scheme(&access), Some("https".to_string()));
+
+        let access = MockAccess::default().with_property("destination.port", "8080");
+        assert_eq!(get_request_scheme(&access), Some("http".to_string()));
+
+        let access = MockAccess::default().with_property("destination.port", "9000");
+        assert_eq!(get_request_scheme(&access), None);
+        assert_eq!(get_request_scheme(&MockAccess::default()), None);
+    }
+
+    #[test]
+    fn test_upstream_address_attributes() {
+        let access = MockAccess::default()
+            .with_property("upstream.local_address", "10.0.0.5:41234")
+            .with_property("upstream.address", "10.0.3.17:8080");
+        assert_eq!(
+            get_upstream_address_attributes(&access),
+            vec![
+                ("network.local.address", "10.0.0.5:41234".to_string()),
+                ("network.peer.address", "10.0.3.17:8080".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upstream_address_attributes_absent() {
+        let access = MockAccess::default().with_property("upstream.address", "10.0.3.17:8080");
+        assert_eq!(
+            get_upstream_address_attributes(&access),
+            vec![("network.peer.address", "10.0.3.17:8080".to_string())]
+        );
+        assert!(get_upstream_address_attributes(&MockAccess::default()).is_empty());
+    }
+
+    #[test]
+    fn test_upstream_cluster() {
+        let access = MockAccess::default().with_property("cluster_name", "outbound|8080||orders.shop.svc.cluster.local");
+        assert_eq!(
+            get_upstream_cluster(&access),
+            Some("outbound|8080||orders.shop.svc.cluster.local".to_string())
+        );
+        assert_eq!(get_upstream_cluster(&MockAccess::default()), None);
+    }
+
+    #[test]
+    fn test_protocol_attributes_for_downgrade() {
+        let access = MockAccess::default()
+            .with_property("request.protocol", "HTTP/2")
+            .with_property("upstream.protocol", "HTTP/1.1");
+        assert_eq!(
+            get_protocol_attributes(&access),
+            vec![
+                ("network.protocol.version", "2".to_string()),
+                ("sp.upstream.protocol", "HTTP/1.1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_protocol_attributes_without_upstream() {
+        let access = MockAccess::default().with_property("request.protocol", "HTTP/1.1");
+        assert_eq!(
+            get_protocol_attributes(&access),
+            vec![("network.protocol.version", "1.1".to_string())]
+        );
+        assert!(get_protocol_attributes(&MockAccess::default()).is_empty());
+    }
+
+    #[test]
+    fn test_downstream_tls_attributes() {
+        let access = MockAccess::default()
+            .with_property("connection.tls_cipher_suite", "TLS_AES_128_GCM_SHA256")
+            .with_property("connection.alpn", "h2");
+        assert_eq!(
+            get_downstream_tls_attributes(&access),
+            vec![
+                ("tls.cipher", "TLS_AES_128_GCM_SHA256".to_string()),
+                ("network.protocol.name", "http".to_string()),
+            ]
+        );
+
+        let access = MockAccess::default()
+            .with_property("connection.tls_cipher_suite", "ECDHE-RSA-AES128-GCM-SHA256")
+            .with_property("connection.alpn", "Istio-Peer-Exchange");
+        assert_eq!(
+            get_downstream_tls_attributes(&access)[1],
+            ("network.protocol.name", "istio-peer-exchange".to_string())
+        );
+    }
+
+    #[test]
+    fn test_downstream_tls_attributes_omitted_for_plaintext() {
+        let access = MockAccess::default().with_property("connection.alpn", "h2");
+        assert!(get_downstream_tls_attributes(&access).is_empty());
+        assert!(get_downstream_tls_attributes(&MockAccess::default()).is_empty());
+    }
+}
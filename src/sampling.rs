@@ -0,0 +1,154 @@
+use crate::config::SamplingKey;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Session decisions kept per VM before the cache is cleared
+const MAX_CACHED_SESSIONS: usize = 10_000;
+
+thread_local! {
+    static SESSION_SAMPLER: RefCell<SessionSampler> = RefCell::new(SessionSampler::default());
+}
+
+/// Decide deterministically whether a request is sampled.
+///
+/// With `SamplingKey::Session` every request of a session gets the same
+/// decision; requests without a session fall back to the trace ID.
+pub fn should_sample(rate: f64, key: SamplingKey, trace_id: &[u8], session_id: &str) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+
+    let value = match key {
+        SamplingKey::Session if !session_id.is_empty() => hash_session_id(session_id),
+        _ => trace_id_low_bits(trace_id),
+    };
+    value < sampling_threshold(rate)
+}
+
+/// Decide whether a session is traced in full, caching the decision for the
+/// session's later requests. Every worker reaches the same decision.
+pub fn sample_session(rate: f64, session_id: &str) -> bool {
+    SESSION_SAMPLER.with(|sampler| sampler.borrow_mut().is_sampled(rate, session_id))
+}
+
+/// Sampling decisions for sessions seen by this VM
+#[derive(Debug, Default)]
+pub struct SessionSampler {
+    rate: f64,
+    decisions: HashMap<String, bool>,
+}
+
+impl SessionSampler {
+    pub fn is_sampled(&mut self, rate: f64, session_id: &str) -> bool {
+        // Cached decisions only hold for the rate they were made at
+        if self.rate != rate {
+            self.rate = rate;
+            self.decisions.clear();
+        }
+        if let Some(sampled) = self.decisions.get(session_id) {
+            return *sampled;
+        }
+        if self.decisions.len() >= MAX_CACHED_SESSIONS {
+            self.decisions.clear();
+        }
+        let sampled = should_sample(rate, SamplingKey::Session, &[], session_id);
+        self.decisions.insert(session_id.to_string(), sampled);
+        sampled
+    }
+}
+
+fn sampling_threshold(rate: f64) -> u64 {
+    (rate * u64::MAX as f64) as u64
+}
+
+/// Low 64 bits of the trace ID, as used by trace-ID-ratio samplers
+fn trace_id_low_bits(trace_id: &[u8]) -> u64 {
+    let start = trace_id.len().saturating_sub(8);
+    trace_id[start..]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | u64::from(*b))
+}
+
+/// FNV-1a, stable across workers and restarts
+fn hash_session_id(session_id: &str) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    session_id
+        .bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, b| (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace_id(low: u64) -> Vec<u8> {
+        let mut id = vec![0xab; 8];
+        id.extend_from_slice(&low.to_be_bytes());
+        id
+    }
+
+    #[test]
+    fn test_trace_key_uses_trace_id_low_bits() {
+        assert!(should_sample(0.5, SamplingKey::Trace, &trace_id(0), "session-a"));
+        assert!(!should_sample(0.5, SamplingKey::Trace, &trace_id(u64::MAX), "session-a"));
+    }
+
+    #[test]
+    fn test_requests_sharing_session_get_same_decision() {
+        let decisions: Vec<bool> = [0, u64::MAX / 3, u64::MAX / 2, u64::MAX]
+            .iter()
+            .map(|low| should_sample(0.5, SamplingKey::Session, &trace_id(*low), "sp-session-1234"))
+            .collect();
+
+        assert!(decisions.iter().all(|d| *d == decisions[0]));
+    }
+
+    #[test]
+    fn test_session_key_without_session_falls_back_to_trace() {
+        assert!(should_sample(0.5, SamplingKey::Session, &trace_id(0), ""));
+        assert!(!should_sample(0.5, SamplingKey::Session, &trace_id(u64::MAX), ""));
+    }
+
+    #[test]
+    fn test_rate_bounds() {
+        assert!(should_sample(1.0, SamplingKey::Trace, &trace_id(u64::MAX), ""));
+        assert!(!should_sample(0.0, SamplingKey::Trace, &trace_id(0), ""));
+    }
+
+    #[test]
+    fn test_session_sampler_caches_decisions_per_rate() {
+        let mut sampler = SessionSampler::default();
+        let first = sampler.is_sampled(0.5, "sp-session-1234");
+
+        assert_eq!(sampler.is_sampled(0.5, "sp-session-1234"), first);
+        assert_eq!(first, should_sample(0.5, SamplingKey::Session, &[], "sp-session-1234"));
+        assert_eq!(sampler.decisions.len(), 1);
+
+        assert!(sampler.is_sampled(1.0, "sp-session-1234"));
+        assert!(!sampler.is_sampled(0.0, "sp-session-1234"));
+        assert_eq!(sampler.decisions.len(), 1);
+    }
+
+    #[test]
+    fn test_session_sampler_keeps_roughly_the_configured_fraction() {
+        let mut sampler = SessionSampler::default();
+        let sampled = (0..1000)
+            .filter(|n| sampler.is_sampled(0.25, &format!("sp-session-{}", n)))
+            .count();
+
+        assert!((150..350).contains(&sampled), "sampled {} of 1000 sessions", sampled);
+    }
+
+    #[test]
+    fn test_decision_is_deterministic_for_trace_id() {
+        let id = trace_id(u64::MAX / 4);
+        let first = should_sample(0.3, SamplingKey::Trace, &id, "");
+
+        assert!((0..10).all(|_| should_sample(0.3, SamplingKey::Trace, &id, "") == first));
+        assert!(first);
+    }
+}
@@ -0,0 +1,74 @@
+/// Attempts at a compare-and-swap update before giving up
+const MAX_CAS_ATTEMPTS: usize = 8;
+
+/// Run a load-modify-store `attempt` until it finishes. `attempt` returns None
+/// when its store lost a CAS race; None overall means every attempt did.
+pub fn retry_cas<T>(mut attempt: impl FnMut() -> Option<T>) -> Option<T> {
+    (0..MAX_CAS_ATTEMPTS).find_map(|_| attempt())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::inflight::{decode_counter, SharedCounterStore};
+    use crate::span_buffer::SharedBufferStore;
+    use std::cell::RefCell;
+
+    /// In-memory shared data with CAS tokens bumped on every write
+    #[derive(Default)]
+    pub struct MockStore {
+        value: RefCell<(Vec<u8>, u32)>,
+    }
+
+    impl SharedBufferStore for MockStore {
+        fn load_buffer(&self, _key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            let (bytes, cas) = self.value.borrow().clone();
+            (Some(bytes), Some(cas))
+        }
+
+        fn store_buffer(&self, _key: &str, value: &[u8], cas: Option<u32>) -> bool {
+            let mut current = self.value.borrow_mut();
+            if cas.is_some_and(|cas| cas != current.1) {
+                return false;
+            }
+            *current = (value.to_vec(), current.1 + 1);
+            true
+        }
+    }
+
+    impl SharedCounterStore for MockStore {
+        fn load_counter(&self, key: &str) -> (u32, Option<u32>) {
+            let (bytes, cas) = self.load_buffer(key);
+            (decode_counter(bytes.as_deref()), cas)
+        }
+
+        fn store_counter(&self, key: &str, value: u32, cas: Option<u32>) -> bool {
+            self.store_buffer(key, &value.to_le_bytes(), cas)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_cas_stops_at_first_finished_attempt() {
+        let mut attempts = 0;
+        let result = retry_cas(|| {
+            attempts += 1;
+            (attempts == 3).then_some(attempts)
+        });
+        assert_eq!(result, Some(3));
+    }
+
+    #[test]
+    fn test_retry_cas_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+        let result: Option<()> = retry_cas(|| {
+            attempts += 1;
+            None
+        });
+        assert_eq!(result, None);
+        assert_eq!(attempts, MAX_CAS_ATTEMPTS);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::shared_data::retry_cas;
+
+/// Shared-data key holding serialized spans waiting for a dispatch slot
+pub const BUFFERED_SPANS_KEY: &str = "sp.buffered_spans";
+
+/// Serialized export request held in the shared buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferedSpan {
+    pub path: String,
+    pub content_type: String,
+    pub payload: Vec<u8>,
+}
+
+/// Byte storage shared by all contexts of the VM
+pub trait SharedBufferStore {
+    /// Current bytes and their CAS token
+    fn load_buffer(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>);
+    /// Store the bytes if the CAS token still matches; false on mismatch
+    fn store_buffer(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool;
+}
+
+/// Append a span, evicting the oldest ones beyond `max_spans`. Returns how many
+/// spans were dropped, including the new one if the buffer could not be updated.
+pub fn enqueue<S: SharedBufferStore + ?Sized>(store: &S, span: BufferedSpan, max_spans: usize) -> usize {
+    if max_spans == 0 {
+        return 1;
+    }
+    let dropped = retry_cas(|| {
+        let (bytes, cas) = store.load_buffer(BUFFERED_SPANS_KEY);
+        let mut spans = decode_spans(bytes.as_deref().unwrap_or_default());
+        spans.push(span.clone());
+        let dropped = spans.len().saturating_sub(max_spans);
+        spans.drain(..dropped);
+        store.store_buffer(BUFFERED_SPANS_KEY, &encode_spans(&spans), cas).then_some(dropped)
+    });
+    dropped.unwrap_or_else(|| {
+        crate::sp_warn!("Could not update span buffer, dropping span");
+        1
+    })
+}
+
+/// Take the oldest buffered span together with every other span bound for the
+/// same endpoint, merged into one export request; other spans stay queued
+pub fn dequeue_batch<S: SharedBufferStore + ?Sized>(store: &S) -> Option<BufferedSpan> {
+    let spans = retry_cas(|| {
+        let (bytes, cas) = store.load_buffer(BUFFERED_SPANS_KEY);
+        let spans = decode_spans(bytes.as_deref().unwrap_or_default());
+        let Some(oldest) = spans.first().cloned() else {
+            return Some(vec![]);
+        };
+        let (batch, rest): (Vec<_>, Vec<_>) = spans
+            .into_iter()
+            .partition(|span| span.path == oldest.path && span.content_type == oldest.content_type);
+        store.store_buffer(BUFFERED_SPANS_KEY, &encode_spans(&rest), cas).then_some(batch)
+    });
+    match spans {
+        Some(spans) => merge_spans(spans),
+        None => {
+            crate::sp_warn!("Could not update span buffer, leaving spans queued");
+            None
+        }
+    }
+}
+
+/// Join export requests for one endpoint. Concatenated protobuf messages decode
+/// as a single `TracesData`; Zipkin JSON span lists are joined into one list.
+fn merge_spans(spans: Vec<BufferedSpan>) -> Option<BufferedSpan> {
+    let mut spans = spans.into_iter();
+    let mut merged = spans.next()?;
+    if merged.content_type == "application/json" {
+        let mut list: Vec<serde_json::Value> = Vec::new();
+        let payloads = std::iter::once(std::mem::take(&mut merged.payload)).chain(spans.map(|span| span.payload));
+        for payload in payloads {
+            match serde_json::from_slice::<Vec<serde_json::Value>>(&payload) {
+                Ok(entries) => list.extend(entries),
+                Err(e) => {
+                    crate::sp_warn!("Dropping unparsable buffered span: {}", e);
+                }
+            }
+        }
+        merged.payload = serde_json::to_vec(&list).ok()?;
+    } else {
+        for span in spans {
+            merged.payload.extend_from_slice(&span.payload);
+        }
+    }
+    Some(merged)
+}
+
+/// Each field is written as a little-endian u32 length followed by its bytes
+fn encode_spans(spans: &[BufferedSpan]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for span in spans {
+        for field in [span.path.as_bytes(), span.content_type.as_bytes(), &span.payload] {
+            bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(field);
+        }
+    }
+    bytes
+}
+
+/// Decode spans written by `encode_spans`; a corrupt tail is discarded
+fn decode_spans(mut bytes: &[u8]) -> Vec<BufferedSpan> {
+    fn take_field<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+        let field = bytes.get(4..4 + len)?;
+        *bytes = &bytes[4 + len..];
+        Some(field)
+    }
+
+    let mut spans = Vec::new();
+    while !bytes.is_empty() {
+        let (Some(path), Some(content_type), Some(payload)) =
+            (take_field(&mut bytes), take_field(&mut bytes), take_field(&mut bytes))
+        else {
+            crate::sp_warn!("Discarding corrupt span buffer entries");
+            break;
+        };
+        spans.push(BufferedSpan {
+            path: String::from_utf8_lossy(path).into_owned(),
+            content_type: String::from_utf8_lossy(content_type).into_owned(),
+            payload: payload.to_vec(),
+        });
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_data::testing::MockStore;
+
+    fn span(n: u8) -> BufferedSpan {
+        BufferedSpan {
+            path: "/v1/traces".to_string(),
+            content_type: "application/x-protobuf".to_string(),
+            payload: vec![n; 3],
+        }
+    }
+
+    fn payload_of(spans: &[u8]) -> Vec<u8> {
+        spans.iter().flat_map(|&n| vec![n; 3]).collect()
+    }
+
+    #[test]
+    fn test_dequeue_batch_merges_spans_in_order() {
+        let store = MockStore::default();
+        assert_eq!(enqueue(&store, span(1), 4), 0);
+        assert_eq!(enqueue(&store, span(2), 4), 0);
+
+        let batch = dequeue_batch(&store).unwrap();
+        assert_eq!(batch.path, "/v1/traces");
+        assert_eq!(batch.payload, payload_of(&[1, 2]));
+        assert_eq!(dequeue_batch(&store), None);
+    }
+
+    #[test]
+    fn test_dequeue_batch_leaves_other_endpoints_queued() {
+        let store = MockStore::default();
+        let zipkin = |tag: &str| BufferedSpan {
+            path: "/api/v2/spans".to_string(),
+            content_type: "application/json".to_string(),
+            payload: format!(r#"[{{"name":"{}"}}]"#, tag).into_bytes(),
+        };
+        enqueue(&store, span(1), 8);
+        enqueue(&store, zipkin("a"), 8);
+        enqueue(&store, span(2), 8);
+        enqueue(&store, zipkin("b"), 8);
+
+        assert_eq!(dequeue_batch(&store).unwrap().payload, payload_of(&[1, 2]));
+        let batch = dequeue_batch(&store).unwrap();
+        assert_eq!(batch.path, "/api/v2/spans");
+        assert_eq!(batch.payload, br#"[{"name":"a"},{"name":"b"}]"#.to_vec());
+        assert_eq!(dequeue_batch(&store), None);
+    }
+
+    #[test]
+    fn test_enqueue_past_cap_drops_oldest() {
+        let store = MockStore::default();
+        for n in 1..=3 {
+            assert_eq!(enqueue(&store, span(n), 3), 0);
+        }
+
+        assert_eq!(enqueue(&store, span(4), 3), 1);
+
+        assert_eq!(dequeue_batch(&store).unwrap().payload, payload_of(&[2, 3, 4]));
+    }
+
+    #[test]
+    fn test_zero_cap_buffers_nothing() {
+        let store = MockStore::default();
+        assert_eq!(enqueue(&store, span(1), 0), 1);
+        assert_eq!(dequeue_batch(&store), None);
+    }
+
+    #[test]
+    fn test_decode_discards_corrupt_tail() {
+        let mut bytes = encode_spans(&[span(1)]);
+        bytes.extend_from_slice(&[9, 0, 0, 0, 1]);
+
+        assert_eq!(decode_spans(&bytes), vec![span(1)]);
+    }
+}
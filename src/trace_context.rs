@@ -15,7 +15,7 @@ pub fn parse_traceparent_value(traceparent: &str) -> Option<(Vec<u8>, Vec<u8>)>
 
 /// Helper function to decode hex string to bytes
 pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
-    if hex.len() % 2 != 0 {
+    if !hex.len().is_multiple_of(2) {
         return None;
     }
 
@@ -1,4 +1,4 @@
-use crate::config::Config;
+use crate::config::{Config, ExemptionRule};
 use proxy_wasm::traits::Context;
 use regex::Regex;
 use std::collections::HashMap;
@@ -9,6 +9,11 @@ pub trait TrafficAnalyzer {
     fn is_from_istio_ingressgateway(&self) -> bool;
     fn should_collect_by_rules(&self, config: &Config, request_headers: &HashMap<String, String>) -> bool;
     fn is_exempted(&self, config: &Config, request_headers: &HashMap<String, String>) -> bool;
+    fn matching_exemption_rule<'a>(
+        &self,
+        config: &'a Config,
+        request_headers: &HashMap<String, String>,
+    ) -> Option<&'a ExemptionRule>;
 }
 
 pub trait RequestHeadersAccess {
@@ -16,16 +21,59 @@ pub trait RequestHeadersAccess {
     fn get_request_header(&self, name: &str) -> Option<String>;
 }
 
+/// Prefix of resource attributes copied from node metadata
+pub const NODE_METADATA_ATTRIBUTE_PREFIX: &str = "k8s.node.";
+
+/// Values derived from node metadata, constant for the lifetime of the proxy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeMetadata {
+    pub is_ingressgateway: bool,
+    pub attributes: Vec<(String, String)>,  // Configured node_metadata_attributes found on the node
+}
+
+impl NodeMetadata {
+    pub fn read<T: RequestHeadersAccess + ?Sized>(access: &T, attribute_keys: &[String]) -> Self {
+        let ingress_paths: [&[&str]; 5] = [
+            &["node", "metadata", "WORKLOAD_NAME"],
+            &["node", "metadata", "app"],
+            &["node", "metadata", "NAME"],
+            &["node", "id"],
+            &["node", "metadata", "LABELS"],
+        ];
+
+        let is_ingressgateway = ingress_paths.iter().any(|path| {
+            access
+                .get_context_property(path.to_vec())
+                .and_then(|value| String::from_utf8(value).ok())
+                .is_some_and(|value| value.contains("istio-ingressgateway"))
+        });
+
+        let attributes = attribute_keys
+            .iter()
+            .filter_map(|key| {
+                let value = crate::properties::get_string_property(access, vec!["node", "metadata", key])?;
+                Some((format!("{}{}", NODE_METADATA_ATTRIBUTE_PREFIX, key), value))
+            })
+            .collect();
+
+        Self { is_ingressgateway, attributes }
+    }
+}
+
 impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
     fn detect_traffic_direction(&self, config: &Config) -> String {
         // Method 1: Use configured traffic direction if available
+        // (server/inbound → inbound, client/outbound → outbound)
         if let Some(ref direction) = config.traffic_direction {
-            crate::sp_debug!("Using configured traffic direction: {}", direction);
-            return match direction.as_str() {
-                "server" => "inbound".to_string(),
-                "client" => "outbound".to_string(),
-                _ => direction.clone(),
-            };
+            match crate::config::normalize_traffic_direction(direction) {
+                Some(normalized) => {
+                    crate::sp_debug!("Using configured traffic direction: {}", normalized);
+                    return normalized.to_string();
+                }
+                None => {
+                    crate::sp_warn!("Unknown configured traffic direction '{}', using auto detection", direction);
+                }
+            }
         }
 
         // Method 2: Check if this is client or server role
@@ -125,21 +173,7 @@ impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
     }
 
     fn is_from_istio_ingressgateway(&self) -> bool {
-        let ingress_patterns = [
-            ("node", "metadata", "WORKLOAD_NAME"),
-            ("node", "metadata", "app"),
-            ("node", "metadata", "NAME"),
-        ];
-
-        for pattern in &ingress_patterns {
-            if let Some(value) = self.get_context_property(vec![pattern.0, pattern.1, pattern.2]) {
-                if let Ok(value_str) = String::from_utf8(value) {
-                    if value_str.contains("istio-ingressgateway") {
-                        return true;
-                    }
-                }
-            }
-        }
+        // Node-level metadata is read once by the root context, see NodeMetadata
 
         // Check cluster metadata
         if let Some(cluster_metadata) = self.get_context_property(vec!["cluster_metadata"]) {
@@ -159,15 +193,6 @@ impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
             }
         }
 
-        // Check node ID
-        if let Some(node_id) = self.get_context_property(vec!["node", "id"]) {
-            if let Ok(id) = String::from_utf8(node_id) {
-                if id.contains("istio-ingressgateway") {
-                    return true;
-                }
-            }
-        }
-
         // Check peer metadata header
         if let Some(peer_metadata) = self.get_request_header("x-envoy-peer-metadata-id") {
             if peer_metadata.contains("istio-ingressgateway") {
@@ -175,15 +200,6 @@ impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
             }
         }
 
-        // Check labels
-        if let Some(labels) = self.get_context_property(vec!["node", "metadata", "LABELS"]) {
-            if let Ok(labels_str) = String::from_utf8(labels) {
-                if labels_str.contains("istio-ingressgateway") {
-                    return true;
-                }
-            }
-        }
-
         false
     }
 
@@ -236,8 +252,16 @@ impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
     }
 
     fn is_exempted(&self, config: &Config, request_headers: &HashMap<String, String>) -> bool {
+        self.matching_exemption_rule(config, request_headers).is_some()
+    }
+
+    fn matching_exemption_rule<'a>(
+        &self,
+        config: &'a Config,
+        request_headers: &HashMap<String, String>,
+    ) -> Option<&'a ExemptionRule> {
         if config.exemption_rules.is_empty() {
-            return false;
+            return None;
         }
 
         let request_host = request_headers
@@ -254,19 +278,20 @@ impl<T: Context> TrafficAnalyzer for T where T: RequestHeadersAccess {
         );
 
         for rule in &config.exemption_rules {
-            let host_matched = check_host_patterns(&rule.host_patterns, &request_host, &client_host);
-            let path_matched = check_path_patterns(&rule.path_patterns, &request_path, &client_path);
+            let host_matched = check_host_patterns(&rule.compiled_host_patterns, &request_host, &client_host);
+            let path_matched = check_path_patterns(&rule.compiled_path_patterns, &request_path, &client_path);
+            let headers_matched = header_patterns_match(&rule.compiled_header_patterns, request_headers);
 
-            if host_matched && path_matched {
+            if host_matched && path_matched && headers_matched {
                 crate::sp_info!(
                     "Request exempted by rule - hostPatterns: {:?}, pathPatterns: {:?}",
                     rule.host_patterns, rule.path_patterns
                 );
-                return true;
+                return Some(rule);
             }
         }
 
-        false
+        None
     }
 }
 
@@ -276,14 +301,9 @@ fn check_inbound_rules(config: &Config, request_headers: &HashMap<String, String
     if let Some(request_path) = request_headers.get(":path") {
         crate::sp_debug!("Checking inbound rules for path: {}", request_path);
 
-        for (i, rule) in config.collection_rules.iter().enumerate() {
-            if !rule.http.server.path.is_empty() {
-                crate::sp_debug!("Checking inbound rule {}: serverPath='{}'", i, rule.http.server.path);
-                if match_pattern(&rule.http.server.path, request_path) {
-                    crate::sp_debug!("Inbound request matched server_path: {}", rule.http.server.path);
-                    return true;
-                }
-            }
+        if let Some(pattern) = config.server_rule_index.find_match(request_path, request_headers) {
+            crate::sp_debug!("Inbound request matched server_path: {}", pattern);
+            return true;
         }
     }
     false
@@ -291,6 +311,7 @@ fn check_inbound_rules(config: &Config, request_headers: &HashMap<String, String
 
 fn check_outbound_rules(config: &Config, request_headers: &HashMap<String, String>) -> bool {
     let (client_host, client_path) = crate::http_helpers::extract_client_info(request_headers);
+    let method = request_headers.get(":method").map(|m| m.as_str());
     crate::sp_debug!("Checking outbound rules with client_host: {:?}, client_path: {:?}", client_host, client_path);
 
     for (i, rule) in config.collection_rules.iter().enumerate() {
@@ -300,7 +321,7 @@ fn check_outbound_rules(config: &Config, request_headers: &HashMap<String, Strin
 
                 // Check client host
                 if let Some(ref actual_client_host) = client_host {
-                    if !match_pattern(&client_config.host, actual_client_host) {
+                    if !client_config.compiled_host.is_match(actual_client_host) {
                         crate::sp_debug!("Client host mismatch: expected={}, actual={}", client_config.host, actual_client_host);
                         continue;
                     }
@@ -311,12 +332,22 @@ fn check_outbound_rules(config: &Config, request_headers: &HashMap<String, Strin
 
                 crate::sp_debug!("Client host matched");
 
+                if !is_method_allowed(&client_config.methods, method) {
+                    crate::sp_debug!("Client method mismatch: expected={:?}, actual={:?}", client_config.methods, method);
+                    continue;
+                }
+
+                if !header_patterns_match(&client_config.compiled_header_patterns, request_headers) {
+                    crate::sp_debug!("Client header patterns did not match: {:?}", client_config.header_patterns);
+                    continue;
+                }
+
                 // Check client paths if configured
                 if !client_config.paths.is_empty() {
                     if let Some(ref actual_client_path) = client_path {
-                        let matched = client_config.paths.iter().any(|client_path| {
-                            let matches = match_pattern(client_path, actual_client_path);
-                            crate::sp_debug!("Client path match: pattern='{}' result={}", client_path, matches);
+                        let matched = client_config.compiled_paths.iter().any(|client_path| {
+                            let matches = client_path.is_match(actual_client_path);
+                            crate::sp_debug!("Client path match: pattern='{}' result={}", client_path.as_str(), matches);
                             matches
                         });
                         if !matched {
@@ -337,10 +368,115 @@ fn check_outbound_rules(config: &Config, request_headers: &HashMap<String, Strin
     false
 }
 
+/// Whether every (header name, value pattern) pair matches the request. Names
+/// compare case-insensitively and a missing header never matches.
+fn header_patterns_match(patterns: &[(String, CompiledPattern)], request_headers: &HashMap<String, String>) -> bool {
+    patterns.iter().all(|(name, pattern)| {
+        let value = request_headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value);
+        let matched = value.is_some_and(|value| pattern.is_match(value));
+        crate::sp_debug!("Header pattern {}='{}' against {:?}: {}", name, pattern.as_str(), value, matched);
+        matched
+    })
+}
+
+/// Whether a rule's method list admits the request method; an empty list matches any
+fn is_method_allowed(methods: &[String], method: Option<&str>) -> bool {
+    if methods.is_empty() {
+        return true;
+    }
+    method.is_some_and(|method| methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+}
+
 // client info extraction is provided by crate::http_helpers::extract_client_info
 
+/// Check whether trace context header injection is disabled for the request path
+pub fn is_injection_denied(config: &Config, request_path: Option<&str>) -> bool {
+    let Some(path) = request_path else {
+        return false;
+    };
+
+    for pattern in &config.injection_path_denylist {
+        if pattern.is_match(path) {
+            crate::sp_debug!("Injection denylist pattern '{}' matched path '{}'", pattern.as_str(), path);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Check whether body capture is disabled for the request path
+pub fn is_body_capture_denied(config: &Config, request_path: Option<&str>) -> bool {
+    let Some(path) = request_path else {
+        return false;
+    };
+
+    for pattern in &config.no_body_paths {
+        if pattern.is_match(path) {
+            crate::sp_debug!("No-body pattern '{}' matched path '{}'", pattern.as_str(), path);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Check whether trace context may be injected into an outbound request to the host.
+/// `propagate_hosts` entries starting with '.' match by suffix, others exactly.
+pub fn is_propagation_allowed(config: &Config, traffic_direction: &str, host: Option<&str>) -> bool {
+    if config.propagate_hosts.is_empty() || traffic_direction != "outbound" {
+        return true;
+    }
+    let Some(host) = host else {
+        return false;
+    };
+    let host = strip_port(host).to_ascii_lowercase();
+    config.propagate_hosts.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        if allowed.starts_with('.') {
+            host.ends_with(&allowed)
+        } else {
+            host == allowed
+        }
+    })
+}
+
+/// Host part of a `host:port` authority
+pub fn strip_port(authority: &str) -> &str {
+    match authority_port(authority) {
+        Some(_) => authority.rsplit_once(':').map_or(authority, |(host, _)| host),
+        None => authority,
+    }
+}
+
+/// Port of a `host:port` authority or address, None when it carries no port
+pub fn authority_port(authority: &str) -> Option<u16> {
+    let (_, port) = authority.rsplit_once(':')?;
+    if port.contains(']') {
+        // Bare IPv6 literal such as [::1]
+        return None;
+    }
+    port.parse().ok()
+}
+
+/// Check whether an outbound request to the port passes the outbound_ports allowlist
+pub fn is_outbound_port_allowed(config: &Config, port: Option<u16>) -> bool {
+    if config.outbound_ports.is_empty() {
+        return true;
+    }
+    port.is_some_and(|port| config.outbound_ports.contains(&port))
+}
+
+/// Check whether the port is one of the infrastructure ports that are never captured
+pub fn is_infra_port(config: &Config, port: u16) -> bool {
+    config.infra_ports.iter().any(|range| range.contains(&port))
+}
+
 fn check_host_patterns(
-    host_patterns: &[String],
+    host_patterns: &[CompiledPattern],
     request_host: &Option<String>,
     client_host: &Option<String>,
 ) -> bool {
@@ -351,8 +487,8 @@ fn check_host_patterns(
     // Check inbound request host
     if let Some(ref host) = request_host {
         for pattern in host_patterns {
-            if match_pattern(pattern, host) {
-                crate::sp_debug!("Host pattern '{}' matched request host '{}'", pattern, host);
+            if pattern.is_match(host) {
+                crate::sp_debug!("Host pattern '{}' matched request host '{}'", pattern.as_str(), host);
                 return true;
             }
         }
@@ -361,8 +497,8 @@ fn check_host_patterns(
     // Check outbound client host
     if let Some(ref host) = client_host {
         for pattern in host_patterns {
-            if match_pattern(pattern, host) {
-                crate::sp_debug!("Host pattern '{}' matched client host '{}'", pattern, host);
+            if pattern.is_match(host) {
+                crate::sp_debug!("Host pattern '{}' matched client host '{}'", pattern.as_str(), host);
                 return true;
             }
         }
@@ -372,7 +508,7 @@ fn check_host_patterns(
 }
 
 fn check_path_patterns(
-    path_patterns: &[String],
+    path_patterns: &[CompiledPattern],
     request_path: &Option<String>,
     client_path: &Option<String>,
 ) -> bool {
@@ -383,8 +519,8 @@ fn check_path_patterns(
     // Check inbound request path
     if let Some(ref path) = request_path {
         for pattern in path_patterns {
-            if match_pattern(pattern, path) {
-                crate::sp_debug!("Path pattern '{}' matched request path '{}'", pattern, path);
+            if pattern.is_match(path) {
+                crate::sp_debug!("Path pattern '{}' matched request path '{}'", pattern.as_str(), path);
                 return true;
             }
         }
@@ -393,8 +529,8 @@ fn check_path_patterns(
     // Check outbound client path
     if let Some(ref path) = client_path {
         for pattern in path_patterns {
-            if match_pattern(pattern, path) {
-                crate::sp_debug!("Path pattern '{}' matched client path '{}'", pattern, path);
+            if pattern.is_match(path) {
+                crate::sp_debug!("Path pattern '{}' matched client path '{}'", pattern.as_str(), path);
                 return true;
             }
         }
@@ -403,19 +539,483 @@ fn check_path_patterns(
     false
 }
 
-fn match_pattern(pattern: &str, text: &str) -> bool {
-    crate::sp_debug!("Matching pattern '{}' against text '{}'", pattern, text);
-    match Regex::new(pattern) {
-        Ok(re) => {
-            let result = re.is_match(text);
-            crate::sp_debug!("Regex match result: {}", result);
-            result
+/// Precompiled server path rules bucketed by the first character of their
+/// anchored literal prefix, so a request only runs the regexes that can match.
+#[derive(Debug, Clone, Default)]
+pub struct ServerRuleIndex {
+    buckets: HashMap<char, Vec<IndexedRule>>,
+    unindexed: Vec<IndexedRule>,
+}
+
+/// A rule pattern compiled once at config parse time. Invalid regexes are
+/// reported when compiled and fall back to an exact match.
+#[derive(Debug, Clone)]
+pub struct CompiledPattern {
+    pattern: String,
+    regex: Option<Regex>,
+}
+
+impl CompiledPattern {
+    pub fn new(pattern: &str) -> Self {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                crate::sp_warn!("Invalid regex pattern '{}', matching it exactly: {}", pattern, e);
+                None
+            }
+        };
+        Self {
+            pattern: pattern.to_string(),
+            regex,
         }
-        Err(e) => {
-            crate::sp_warn!("Invalid regex pattern '{}': {}", pattern, e);
-            let result = pattern == text;
-            crate::sp_debug!("Fallback to exact match: {}", result);
-            result
+    }
+
+    pub fn compile_all(patterns: &[String]) -> Vec<Self> {
+        patterns.iter().map(|pattern| Self::new(pattern)).collect()
+    }
+
+    pub fn compile_headers(patterns: &[(String, String)]) -> Vec<(String, Self)> {
+        patterns.iter().map(|(name, pattern)| (name.clone(), Self::new(pattern))).collect()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(text),
+            None => self.pattern == text,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedRule {
+    compiled: CompiledPattern,
+    prefix: String,
+    methods: Vec<String>,
+    header_patterns: Vec<(String, CompiledPattern)>,
+}
+
+impl IndexedRule {
+    fn new(server: &crate::config::ServerConfig) -> Self {
+        let pattern = server.path.as_str();
+        let compiled = CompiledPattern::new(pattern);
+        // Invalid patterns match exactly, so the whole pattern is the prefix
+        let prefix = match compiled.regex {
+            Some(_) => literal_prefix(pattern),
+            None => pattern.to_string(),
+        };
+        Self {
+            compiled,
+            prefix,
+            methods: server.methods.clone(),
+            header_patterns: CompiledPattern::compile_headers(&server.header_patterns),
+        }
+    }
+
+    fn is_match(&self, text: &str, request_headers: &HashMap<String, String>) -> bool {
+        let method = request_headers.get(":method").map(|m| m.as_str());
+        text.starts_with(&self.prefix)
+            && is_method_allowed(&self.methods, method)
+            && self.compiled.is_match(text)
+            && header_patterns_match(&self.header_patterns, request_headers)
+    }
+}
+
+impl ServerRuleIndex {
+    pub fn build(rules: &[crate::config::CollectionRule]) -> Self {
+        let mut index = Self::default();
+        for rule in rules {
+            if rule.http.server.path.is_empty() {
+                continue;
+            }
+            let indexed = IndexedRule::new(&rule.http.server);
+            match indexed.prefix.chars().next() {
+                Some(first) => index.buckets.entry(first).or_default().push(indexed),
+                None => index.unindexed.push(indexed),
+            }
+        }
+        index
+    }
+
+    /// First server path pattern matching the request path, method and headers
+    pub fn find_match(&self, path: &str, request_headers: &HashMap<String, String>) -> Option<&str> {
+        let bucket = path
+            .chars()
+            .next()
+            .and_then(|first| self.buckets.get(&first))
+            .map(|rules| rules.as_slice())
+            .unwrap_or_default();
+
+        bucket
+            .iter()
+            .chain(self.unindexed.iter())
+            .find(|rule| rule.is_match(path, request_headers))
+            .map(|rule| rule.compiled.as_str())
+    }
+}
+
+/// Literal text every match of an anchored pattern must start with. Returns an
+/// empty prefix for unanchored patterns, alternations and leading groups/flags.
+fn literal_prefix(pattern: &str) -> String {
+    let Some(body) = pattern.strip_prefix('^') else {
+        return String::new();
+    };
+    if pattern.contains('|') {
+        return String::new();
+    }
+
+    let mut prefix = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if ".^$*+?()[]{}|\\".contains(c) {
+            break;
+        }
+        // A quantified character is optional, so it can't be part of the prefix
+        if matches!(chars.peek(), Some('*' | '?' | '{')) {
+            break;
+        }
+        prefix.push(c);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::tests::MockAccess;
+
+    fn config_with_direction(direction: &str) -> Config {
+        Config {
+            traffic_direction: Some(direction.to_string()),
+            ..Default::default()
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_detect_traffic_direction_configured_inbound() {
+        let access = MockAccess::default();
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("inbound")), "inbound");
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("server")), "inbound");
+    }
+
+    #[test]
+    fn test_detect_traffic_direction_configured_outbound() {
+        let access = MockAccess::default();
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("outbound")), "outbound");
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("client")), "outbound");
+    }
+
+    #[test]
+    fn test_detect_traffic_direction_invalid_falls_back_to_auto() {
+        let access = MockAccess::default();
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("sideways")), "auto");
+
+        let access = MockAccess::default().with_property("cluster_name", "inbound|8080||");
+        assert_eq!(access.detect_traffic_direction(&config_with_direction("sideways")), "inbound");
+    }
+
+    #[test]
+    fn test_is_injection_denied_empty_denylist() {
+        let config = Config::default();
+        assert!(!is_injection_denied(&config, Some("/api/orders")));
+    }
+
+    #[test]
+    fn test_is_injection_denied_matching_path() {
+        let config = Config {
+            injection_path_denylist: CompiledPattern::compile_all(&["^/signed/".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(is_injection_denied(&config, Some("/signed/upload?sig=abc")));
+        assert!(!is_injection_denied(&config, Some("/api/signed")));
+        assert!(!is_injection_denied(&config, None));
+    }
+
+    #[test]
+    fn test_node_metadata_detects_ingressgateway() {
+        let access = MockAccess::default().with_property("node.metadata.WORKLOAD_NAME", "istio-ingressgateway");
+        assert!(NodeMetadata::read(&access, &[]).is_ingressgateway);
+
+        let access = MockAccess::default().with_property("node.id", "router~10.0.0.1~istio-ingressgateway-abc.istio-system");
+        assert!(NodeMetadata::read(&access, &[]).is_ingressgateway);
+
+        let access = MockAccess::default().with_property("node.metadata.WORKLOAD_NAME", "orders");
+        assert!(!NodeMetadata::read(&access, &[]).is_ingressgateway);
+    }
+
+    #[test]
+    fn test_node_metadata_reads_configured_attributes() {
+        let access = MockAccess::default()
+            .with_property("node.metadata.ISTIO_VERSION", "1.22.1")
+            .with_property("node.metadata.MESH_ID", "cluster.local")
+            .with_property("node.metadata.CLUSTER_ID", "east-1");
+        let keys = vec!["ISTIO_VERSION".to_string(), "MESH_ID".to_string(), "REGION".to_string()];
+
+        let node_metadata = NodeMetadata::read(&access, &keys);
+
+        assert_eq!(
+            node_metadata.attributes,
+            vec![
+                ("k8s.node.ISTIO_VERSION".to_string(), "1.22.1".to_string()),
+                ("k8s.node.MESH_ID".to_string(), "cluster.local".to_string()),
+            ]
+        );
+    }
+
+    fn server_rules(paths: &[String]) -> Vec<crate::config::CollectionRule> {
+        paths
+            .iter()
+            .map(|path| crate::config::CollectionRule {
+                http: crate::config::HttpCollectionRule {
+                    server: crate::config::ServerConfig {
+                        path: path.clone(),
+                        methods: vec![],
+                        header_patterns: vec![],
+                    },
+                    client: vec![],
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix("^/api/v1/users/\\d+$"), "/api/v1/users/");
+        assert_eq!(literal_prefix("^/api/orders?"), "/api/order");
+        assert_eq!(literal_prefix("^/a|^/b"), "");
+        assert_eq!(literal_prefix("/api/.*"), "");
+        assert_eq!(literal_prefix("^(?i)/api"), "");
+    }
+
+    #[test]
+    fn test_rule_index_matches_naive_path_for_large_rule_set() {
+        let mut patterns: Vec<String> = (0..300)
+            .map(|i| match i % 5 {
+                0 => format!("^/svc{}/items/\\d+$", i),
+                1 => format!("^/svc{}/search?", i),
+                2 => format!("/tenant{}/", i),
+                3 => format!("^/(a|b){}/x", i),
+                _ => format!("^/svc{}/[unclosed", i),
+            })
+            .collect();
+        patterns.push("^.*\\.json$".to_string());
+        let rules = server_rules(&patterns);
+        let index = ServerRuleIndex::build(&rules);
+
+        let paths: Vec<String> = (0..300)
+            .flat_map(|i| {
+                vec![
+                    format!("/svc{}/items/42", i),
+                    format!("/svc{}/items/abc", i),
+                    format!("/svc{}/searc", i),
+                    format!("/svc{}/search", i),
+                    format!("/x/tenant{}/orders", i),
+                    format!("/b{}/x", i),
+                    format!("/svc{}/[unclosed", i),
+                ]
+            })
+            .chain(["/static/app.json".to_string(), "".to_string(), "/".to_string()])
+            .collect();
+
+        // Naive reference: every pattern in order, with match_pattern's exact-match fallback
+        let naive_rules: Vec<(String, Option<Regex>)> =
+            patterns.iter().map(|p| (p.clone(), Regex::new(p).ok())).collect();
+        let naive_match = |path: &str| {
+            naive_rules.iter().any(|(pattern, regex)| match regex {
+                Some(regex) => regex.is_match(path),
+                None => pattern == path,
+            })
+        };
+
+        let no_headers = HashMap::new();
+        for path in &paths {
+            assert_eq!(index.find_match(path, &no_headers).is_some(), naive_match(path), "path {}", path);
+        }
+    }
+
+    #[test]
+    fn test_config_rule_index_used_for_inbound_rules() {
+        let mut config = Config::default();
+        let config_str = r#"{"collectionRules":{"http":{"server":[{"path":"^/api/orders/\\d+$"}]}}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        let mut headers = HashMap::new();
+        headers.insert(":path".to_string(), "/api/orders/7".to_string());
+        assert!(check_inbound_rules(&config, &headers));
+
+        headers.insert(":path".to_string(), "/api/users/7".to_string());
+        assert!(!check_inbound_rules(&config, &headers));
+    }
+
+    #[test]
+    fn test_outbound_rules_use_compiled_patterns() {
+        let mut config = Config::default();
+        let config_str = r#"{"collectionRules":{"http":{"client":[{"host":"^api\\.example\\.com$","paths":["^/v\\d+/orders"]}]}}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        let client = &config.collection_rules[0].http.client[0];
+        assert_eq!(client.compiled_host.as_str(), "^api\\.example\\.com$");
+        assert_eq!(client.compiled_paths.len(), 1);
+
+        let mut headers = HashMap::new();
+        headers.insert("referer".to_string(), "https://api.example.com/v2/orders/9".to_string());
+        assert!(check_outbound_rules(&config, &headers));
+
+        headers.insert("referer".to_string(), "https://api.example.com/v2/users".to_string());
+        assert!(!check_outbound_rules(&config, &headers));
+    }
+
+    #[test]
+    fn test_server_rule_matches_configured_methods_only() {
+        let mut config = Config::default();
+        let config_str = r#"{"collectionRules":{"http":{"server":[{"path":"^/orders$","methods":["post"]}]}}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        let mut headers = HashMap::new();
+        headers.insert(":path".to_string(), "/orders".to_string());
+        headers.insert(":method".to_string(), "POST".to_string());
+        assert!(check_inbound_rules(&config, &headers));
+
+        headers.insert(":method".to_string(), "GET".to_string());
+        assert!(!check_inbound_rules(&config, &headers));
+
+        headers.remove(":method");
+        assert!(!check_inbound_rules(&config, &headers));
+    }
+
+    #[test]
+    fn test_client_rule_matches_configured_methods_only() {
+        let mut config = Config::default();
+        let config_str = r#"{"collectionRules":{"http":{"client":[{"host":"api.example.com","paths":["^/orders$"],"methods":["POST"]}]}}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        let mut headers = HashMap::new();
+        headers.insert("referer".to_string(), "https://api.example.com/orders".to_string());
+        headers.insert(":method".to_string(), "POST".to_string());
+        assert!(check_outbound_rules(&config, &headers));
+
+        headers.insert(":method".to_string(), "GET".to_string());
+        assert!(!check_outbound_rules(&config, &headers));
+    }
+
+    #[test]
+    fn test_rule_without_methods_matches_any_method() {
+        assert!(is_method_allowed(&[], Some("DELETE")));
+        assert!(is_method_allowed(&[], None));
+        assert!(is_method_allowed(&["POST".to_string()], Some("post")));
+        assert!(!is_method_allowed(&["POST".to_string()], None));
+    }
+
+    #[test]
+    fn test_exemption_header_patterns() {
+        let mut config = Config::default();
+        let config_str = r#"{"exemptionRules":[{"headerPatterns":{"X-Synthetic":"^true$"}}]}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+        let rule = &config.exemption_rules[0];
+        assert!(rule.path_patterns.is_empty());
+
+        let matches = |headers: &[(&str, &str)]| {
+            let headers: HashMap<String, String> =
+                headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            header_patterns_match(&rule.compiled_header_patterns, &headers)
+                && check_path_patterns(&rule.compiled_path_patterns, &headers.get(":path").cloned(), &None)
+        };
+
+        // Present and matching, with the header name in another case
+        assert!(matches(&[(":path", "/api/orders"), ("x-synthetic", "true")]));
+        // Absent
+        assert!(!matches(&[(":path", "/api/orders")]));
+        // Present but the value does not match
+        assert!(!matches(&[(":path", "/api/orders"), ("x-synthetic", "false")]));
+    }
+
+    #[test]
+    fn test_collection_rule_header_patterns() {
+        let mut config = Config::default();
+        let config_str = r#"{"collectionRules":{"http":{
+            "server":[{"path":"^/orders","headerPatterns":{"x-tenant":"^premium$"}}],
+            "client":[{"host":"api.example.com","headerPatterns":{"X-Tenant":"^premium$"}}]
+        }}}"#;
+        assert!(config.parse_from_json(config_str.as_bytes()));
+
+        let mut headers = HashMap::new();
+        headers.insert(":path".to_string(), "/orders".to_string());
+        assert!(!check_inbound_rules(&config, &headers));
+        headers.insert("x-tenant".to_string(), "basic".to_string());
+        assert!(!check_inbound_rules(&config, &headers));
+        headers.insert("x-tenant".to_string(), "premium".to_string());
+        assert!(check_inbound_rules(&config, &headers));
+
+        let mut headers = HashMap::new();
+        headers.insert("referer".to_string(), "https://api.example.com/orders".to_string());
+        assert!(!check_outbound_rules(&config, &headers));
+        headers.insert("x-tenant".to_string(), "premium".to_string());
+        assert!(check_outbound_rules(&config, &headers));
+    }
+
+    #[test]
+    fn test_invalid_exemption_pattern_matches_exactly() {
+        let rule = ExemptionRule::new(vec![], vec!["/health[".to_string(), "^/ready$".to_string()]);
+        assert!(rule.compiled_path_patterns[0].regex.is_none());
+
+        assert!(check_path_patterns(&rule.compiled_path_patterns, &Some("/health[".to_string()), &None));
+        assert!(check_path_patterns(&rule.compiled_path_patterns, &Some("/ready".to_string()), &None));
+        assert!(!check_path_patterns(&rule.compiled_path_patterns, &Some("/healthz".to_string()), &None));
+        assert!(check_host_patterns(&rule.compiled_host_patterns, &None, &None));
+    }
+
+    #[test]
+    fn test_authority_port() {
+        assert_eq!(authority_port("orders.svc:8080"), Some(8080));
+        assert_eq!(authority_port("10.0.0.7:443"), Some(443));
+        assert_eq!(authority_port("[::1]:9090"), Some(9090));
+        assert_eq!(authority_port("[::1]"), None);
+        assert_eq!(authority_port("orders.svc"), None);
+    }
+
+    #[test]
+    fn test_outbound_port_allowlist() {
+        let config = Config {
+            outbound_ports: vec![80, 8080],
+            ..Default::default()
+        };
+        assert!(is_outbound_port_allowed(&config, Some(8080)));
+        assert!(!is_outbound_port_allowed(&config, Some(53)));
+        assert!(!is_outbound_port_allowed(&config, None));
+        assert!(is_outbound_port_allowed(&Config::default(), Some(53)));
+    }
+
+    #[test]
+    fn test_infra_ports() {
+        let config = Config::default();
+        assert!(is_infra_port(&config, 15021));
+        assert!(is_infra_port(&config, 15000));
+        assert!(is_infra_port(&config, 15099));
+        assert!(!is_infra_port(&config, 15100));
+        assert!(!is_infra_port(&config, 8080));
+
+        let config = Config {
+            infra_ports: vec![],
+            ..Default::default()
+        };
+        assert!(!is_infra_port(&config, 15021));
+    }
+
+    #[test]
+    fn test_propagation_allowlist() {
+        let config = Config {
+            propagate_hosts: vec![".svc.cluster.local".to_string(), "api.partner.com".to_string()],
+            ..Default::default()
+        };
+        assert!(is_propagation_allowed(&config, "outbound", Some("orders.shop.svc.cluster.local:8080")));
+        assert!(is_propagation_allowed(&config, "outbound", Some("API.partner.com")));
+        assert!(!is_propagation_allowed(&config, "outbound", Some("hooks.vendor.io")));
+        assert!(!is_propagation_allowed(&config, "outbound", None));
+        assert!(is_propagation_allowed(&config, "inbound", Some("hooks.vendor.io")));
+        assert!(is_propagation_allowed(&Config::default(), "outbound", Some("hooks.vendor.io")));
+    }
+}
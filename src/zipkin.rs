@@ -0,0 +1,169 @@
+use serde_json::{json, Map, Value};
+
+use crate::otel::{any_value, AnyValue, Span, TracesData, span};
+
+/// Default Zipkin v2 collector path
+pub const DEFAULT_ZIPKIN_PATH: &str = "/api/v2/spans";
+
+/// Convert traces data into a Zipkin v2 JSON span list
+pub fn traces_data_to_zipkin_json(traces_data: &TracesData) -> Value {
+    let mut spans = Vec::new();
+    for resource_spans in &traces_data.resource_spans {
+        let service_name = resource_spans
+            .resource
+            .as_ref()
+            .and_then(|resource| resource.attributes.iter().find(|kv| kv.key == "service.name"))
+            .and_then(|kv| kv.value.as_ref())
+            .and_then(any_value_to_string)
+            .unwrap_or_else(|| "default-service".to_string());
+
+        for scope_spans in &resource_spans.scope_spans {
+            for otel_span in &scope_spans.spans {
+                spans.push(span_to_zipkin(otel_span, &service_name));
+            }
+        }
+    }
+    Value::Array(spans)
+}
+
+/// Serialize traces data as a Zipkin v2 JSON payload
+pub fn serialize_zipkin(traces_data: &TracesData) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec(&traces_data_to_zipkin_json(traces_data))
+}
+
+/// Set a tag on every span of a serialized Zipkin payload; None if it does not parse
+pub fn annotate_serialized_zipkin(payload: &[u8], key: &str, value: &str) -> Option<Vec<u8>> {
+    let mut spans: Vec<Value> = serde_json::from_slice(payload).ok()?;
+    for tags in spans.iter_mut().filter_map(|span| span.get_mut("tags").and_then(Value::as_object_mut)) {
+        tags.insert(key.to_string(), Value::String(value.to_string()));
+    }
+    serde_json::to_vec(&spans).ok()
+}
+
+fn span_to_zipkin(otel_span: &Span, service_name: &str) -> Value {
+    let mut zipkin_span = Map::new();
+    zipkin_span.insert("traceId".to_string(), json!(zipkin_trace_id(&otel_span.trace_id)));
+    zipkin_span.insert("id".to_string(), json!(hex(&otel_span.span_id)));
+    if !otel_span.parent_span_id.is_empty() {
+        zipkin_span.insert("parentId".to_string(), json!(hex(&otel_span.parent_span_id)));
+    }
+    zipkin_span.insert("name".to_string(), json!(otel_span.name));
+    if let Some(kind) = zipkin_kind(otel_span.kind) {
+        zipkin_span.insert("kind".to_string(), json!(kind));
+    }
+
+    // Zipkin timestamps and durations are in microseconds
+    let start_micros = otel_span.start_time_unix_nano / 1_000;
+    let end_micros = otel_span.end_time_unix_nano / 1_000;
+    zipkin_span.insert("timestamp".to_string(), json!(start_micros));
+    zipkin_span.insert("duration".to_string(), json!(end_micros.saturating_sub(start_micros)));
+    zipkin_span.insert("localEndpoint".to_string(), json!({ "serviceName": service_name }));
+
+    // Zipkin tags are string-valued
+    let tags: Map<String, Value> = otel_span
+        .attributes
+        .iter()
+        .filter_map(|kv| {
+            let value = any_value_to_string(kv.value.as_ref()?)?;
+            Some((kv.key.clone(), Value::String(value)))
+        })
+        .collect();
+    zipkin_span.insert("tags".to_string(), Value::Object(tags));
+
+    Value::Object(zipkin_span)
+}
+
+/// Zipkin accepts 64 or 128-bit trace IDs; use the short form when the high half is zero
+fn zipkin_trace_id(trace_id: &[u8]) -> String {
+    if trace_id.len() == 16 && trace_id[..8].iter().all(|b| *b == 0) {
+        hex(&trace_id[8..])
+    } else {
+        hex(trace_id)
+    }
+}
+
+fn zipkin_kind(kind: i32) -> Option<&'static str> {
+    match span::SpanKind::try_from(kind).ok()? {
+        span::SpanKind::Server => Some("SERVER"),
+        span::SpanKind::Client => Some("CLIENT"),
+        span::SpanKind::Producer => Some("PRODUCER"),
+        span::SpanKind::Consumer => Some("CONSUMER"),
+        _ => None,
+    }
+}
+
+fn any_value_to_string(value: &AnyValue) -> Option<String> {
+    match value.value.as_ref()? {
+        any_value::Value::StringValue(s) => Some(s.clone()),
+        any_value::Value::BoolValue(b) => Some(b.to_string()),
+        any_value::Value::IntValue(i) => Some(i.to_string()),
+        any_value::Value::DoubleValue(d) => Some(d.to_string()),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::otel::SpanBuilder;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_zipkin_json_for_single_span() {
+        let builder = SpanBuilder::new().with_service_name("orders".to_string());
+        let mut response_headers = HashMap::new();
+        response_headers.insert(":status".to_string(), "201".to_string());
+        let traces_data = builder.create_extract_span(
+            &HashMap::new(),
+            &[],
+            &response_headers,
+            &[],
+            Some("orders.svc"),
+            Some("/api/orders"),
+            Some(1_700_000_000_000_000_000),
+        );
+
+        let spans = traces_data_to_zipkin_json(&traces_data);
+        let spans = spans.as_array().unwrap();
+        assert_eq!(spans.len(), 1);
+
+        let zipkin_span = &spans[0];
+        assert_eq!(zipkin_span["traceId"], json!(builder.get_trace_id_hex()));
+        assert_eq!(zipkin_span["id"], json!(builder.get_current_span_id_hex()));
+        assert_eq!(zipkin_span["name"], json!("/api/orders"));
+        assert_eq!(zipkin_span["kind"], json!("SERVER"));
+        assert_eq!(zipkin_span["timestamp"], json!(1_700_000_000_000_000u64));
+        assert_eq!(zipkin_span["localEndpoint"]["serviceName"], json!("orders"));
+        assert_eq!(zipkin_span["tags"]["url.path"], json!("/api/orders"));
+        assert_eq!(zipkin_span["tags"]["http.response.status_code"], json!("201"));
+        assert!(zipkin_span.get("parentId").is_none());
+    }
+
+    #[test]
+    fn test_annotate_serialized_zipkin() {
+        let traces_data = SpanBuilder::new().create_extract_span(
+            &HashMap::new(), &[], &HashMap::new(), &[], None, Some("/api/orders"), None,
+        );
+        let payload = serialize_zipkin(&traces_data).unwrap();
+
+        let annotated = annotate_serialized_zipkin(&payload, "sp.export.error", "server_error").unwrap();
+
+        let spans: Value = serde_json::from_slice(&annotated).unwrap();
+        assert_eq!(spans[0]["tags"]["sp.export.error"], json!("server_error"));
+        assert_eq!(spans[0]["tags"]["url.path"], json!("/api/orders"));
+    }
+
+    #[test]
+    fn test_zipkin_trace_id_width() {
+        let mut trace_id = vec![0u8; 8];
+        trace_id.extend_from_slice(&[0x12; 8]);
+        assert_eq!(zipkin_trace_id(&trace_id), "1212121212121212");
+
+        let trace_id = vec![0xab; 16];
+        assert_eq!(zipkin_trace_id(&trace_id), "abababababababababababababababab");
+    }
+}